@@ -13,7 +13,7 @@ use crate::memory_sources::mmap::numa::numa_settings::NumaSettings;
 #[allow(unused_macros)]
 
 macro_rules! allocator_instance_macro {
-    ($hugepage:expr, $numa_policy:expr) => {
+    ($hugepage:expr, $numa_policy:expr, $label:expr) => {
         {
             use crate::memory_sources::mmap::prelude::*;
             lazy_static! {
@@ -26,7 +26,7 @@ macro_rules! allocator_instance_macro {
                             $numa_policy,
                             true,
                         );
-                        let mmap = MemoryMapSource::new(
+                        let mut mmap = MemoryMapSource::new(
                             true,
                             false,
                             false,
@@ -34,6 +34,7 @@ macro_rules! allocator_instance_macro {
                             $hugepage,
                             Some(numa_settings)
                         );
+                        mmap.set_label(Some(std::ffi::CStr::from_bytes_with_nul($label).unwrap()));
                         MemoryMapAllocator(mmap)
                     }
 
@@ -53,6 +54,7 @@ macro_rules! allocator_instance_macro {
     };
 }
 
+/// `node` is the raw node mask this has always used, not a node index: `Some(0)`, the empty mask, prefers the local node and `Some(1)` prefers node 0.
 pub(crate) fn allocator_instance(
     huge_page: bool,
     node: Option<u8>,
@@ -61,54 +63,50 @@ pub(crate) fn allocator_instance(
         (true, Some(0)) => {
             allocator_instance_macro!(
                 HugePageSize::Default,
-                NumaAllocationPolicy::Preferred(NumaNodeBitSet {
-                    bits: 0,
-                    static_nodes: true,
-                    relative_nodes: false,
-                })
+                NumaAllocationPolicy::Preferred(NumaNodeBitSet::new_static()),
+                b"huge page allocator instance\0"
             )
         }
         (true, Some(1)) => {
             allocator_instance_macro!(
                 HugePageSize::Default,
-                NumaAllocationPolicy::Preferred(NumaNodeBitSet {
-                    bits: 1,
-                    static_nodes: true,
-                    relative_nodes: false,
-                })
+                NumaAllocationPolicy::Preferred(NumaNodeBitSet::new_static().with_numa_node(0)),
+                b"huge page allocator instance preferring numa node 0\0"
             )
         }
         (true, Some(n)) => {
             panic!("binding node {} not supported yet", n);
         }
         (true, None) => {
-            allocator_instance_macro!(HugePageSize::Default, NumaAllocationPolicy::Local)
+            allocator_instance_macro!(
+                HugePageSize::Default,
+                NumaAllocationPolicy::Local,
+                b"huge page allocator instance\0"
+            )
         }
         (false, Some(1)) => {
             allocator_instance_macro!(
                 HugePageSize::None,
-                NumaAllocationPolicy::Preferred(NumaNodeBitSet {
-                    bits: 1,
-                    static_nodes: true,
-                    relative_nodes: false,
-                })
+                NumaAllocationPolicy::Preferred(NumaNodeBitSet::new_static().with_numa_node(0)),
+                b"allocator instance preferring numa node 0\0"
             )
         }
         (false, Some(0)) => {
             allocator_instance_macro!(
                 HugePageSize::None,
-                NumaAllocationPolicy::Preferred(NumaNodeBitSet {
-                    bits: 0,
-                    static_nodes: true,
-                    relative_nodes: false,
-                })
+                NumaAllocationPolicy::Preferred(NumaNodeBitSet::new_static()),
+                b"allocator instance\0"
             )
         }
         (false, Some(n)) => {
             panic!("binding node {} not supported yet", n);
         }
         (false, None) => {
-            allocator_instance_macro!(HugePageSize::None, NumaAllocationPolicy::Local)
+            allocator_instance_macro!(
+                HugePageSize::None,
+                NumaAllocationPolicy::Local,
+                b"allocator instance\0"
+            )
         }
     }
 }
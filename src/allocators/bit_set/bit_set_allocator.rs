@@ -16,6 +16,8 @@ use std::cell::Cell;
 use std::num::NonZeroUsize;
 
 /// Bit set based allocator.
+///
+/// Runs of fully free bit set words can have their pages decommitted with `trim()`, either on demand or automatically once a threshold of bytes has been deallocated (see `with_trim_threshold()`).
 #[derive(Debug)]
 pub struct BitSetAllocator<MS: MemorySource> {
     inclusive_start_of_bit_set: BitSetWordPointer,
@@ -29,8 +31,14 @@ pub struct BitSetAllocator<MS: MemorySource> {
 
     memory_source: MS,
     memory_source_size: NonZeroUsize,
+
+    trim_threshold: Option<NonZeroUsize>,
+    bytes_deallocated_since_trim: Cell<usize>,
 }
 
+// Both the bit set and the blocks it tracks lie in memory exclusively owned by this allocator.
+unsafe impl<MS: MemorySource + Send> Send for BitSetAllocator<MS> {}
+
 impl<MS: MemorySource> Drop for BitSetAllocator<MS> {
     #[inline(always)]
     fn drop(&mut self) {
@@ -134,6 +142,17 @@ impl<MS: MemorySource> Allocator for BitSetAllocator<MS> {
             remaining_bits_to_unset_in_middle_and_at_end,
         );
         unset_unaligned_leading_bits_at_end(location_major, remaining_bits_to_unset_at_end);
+
+        if let Some(trim_threshold) = self.trim_threshold {
+            let bytes_deallocated_since_trim =
+                self.bytes_deallocated_since_trim.get() + non_zero_size.get();
+            if unlikely!(bytes_deallocated_since_trim >= trim_threshold.get()) {
+                self.trim();
+            } else {
+                self.bytes_deallocated_since_trim
+                    .set(bytes_deallocated_since_trim);
+            }
+        }
     }
 
     #[inline(always)]
@@ -277,8 +296,9 @@ impl<MS: MemorySource> BitSetAllocator<MS> {
         debug_assert!(block_size.get() >= BitSetWord::SIZE_IN_BYTES, "block_size `{:?}` must at least `{:?}` so that the bit set metadata holding free blocks can be allocated contiguous with the memory used for blocks", block_size, BitSetWord::SIZE_IN_BYTES);
 
         let size_in_bytes = number_of_blocks.get() << block_size.logarithm_base2();
-        let bit_set_size_in_bytes =
-            number_of_blocks.get() / NumberOfBits::IN_BIT_SET_WORD.to_usize();
+        let bit_set_size_in_bytes = (number_of_blocks.get()
+            / NumberOfBits::IN_BIT_SET_WORD.to_usize())
+            * BitSetWord::SIZE_IN_BYTES;
         let memory_source_size = (size_in_bytes + bit_set_size_in_bytes).non_zero();
         let allocations_start_from = memory_source.obtain(memory_source_size)?;
 
@@ -301,9 +321,71 @@ impl<MS: MemorySource> BitSetAllocator<MS> {
 
             memory_source_size,
             memory_source,
+
+            trim_threshold: None,
+            bytes_deallocated_since_trim: Cell::new(0),
         })
     }
 
+    /// Automatically `trim()` once at least `trim_threshold` bytes have been deallocated since the last trim.
+    #[inline(always)]
+    pub fn with_trim_threshold(mut self, trim_threshold: NonZeroUsize) -> Self {
+        self.trim_threshold = Some(trim_threshold);
+        self
+    }
+
+    /// Decommits the pages of blocks covered by runs of fully free bit set words, so that resident memory falls after a burst of allocations is freed.
+    ///
+    /// The bit set itself is kept, so decommitted blocks are reused as normal.
+    ///
+    /// Returns the number of bytes decommitted.
+    pub fn trim(&self) -> usize {
+        self.bytes_deallocated_since_trim.set(0);
+
+        let bytes_per_bit_set_word = NumberOfBits::IN_BIT_SET_WORD
+            .scale_to_memory_offset_in_bytes(&self.block_size)
+            .to_usize();
+
+        let mut decommitted = 0;
+        let mut run_starts_at = None;
+        let mut memory_address = self.allocations_start_from;
+        let mut bit_set_word_pointer = self.inclusive_start_of_bit_set;
+        while bit_set_word_pointer != self.exclusive_end_of_bit_set {
+            let is_fully_free = bit_set_word_pointer.bit_set_word().to_u64() == 0;
+            match (is_fully_free, run_starts_at) {
+                (true, None) => run_starts_at = Some(memory_address),
+
+                (false, Some(run_start)) => {
+                    decommitted += self.decommit_run(run_start, memory_address);
+                    run_starts_at = None
+                }
+
+                _ => (),
+            }
+
+            memory_address.add_assign(bytes_per_bit_set_word);
+            bit_set_word_pointer.increment_assign();
+        }
+        if let Some(run_start) = run_starts_at {
+            decommitted += self.decommit_run(run_start, memory_address);
+        }
+        decommitted
+    }
+
+    #[inline(always)]
+    fn decommit_run(&self, from: MemoryAddress, to: MemoryAddress) -> usize {
+        let to = if unlikely!(to > self.allocations_end_at) {
+            self.allocations_end_at
+        } else {
+            to
+        };
+        if unlikely!(to <= from) {
+            return 0;
+        }
+        self.memory_source
+            .decommit(to.difference(from).non_zero(), from)
+    }
+
     #[inline(always)]
     fn initialize_bit_set_so_all_memory_is_unallocated(
         allocations_end_at: MemoryAddress,
@@ -35,6 +35,9 @@ pub struct BumpAllocator<MS: MemorySource> {
     memory_source_size: NonZeroUsize,
 }
 
+// The pointers are into memory obtained from, and exclusively owned by, the memory source.
+unsafe impl<MS: MemorySource + Send> Send for BumpAllocator<MS> {}
+
 impl<MS: MemorySource> Drop for BumpAllocator<MS> {
     #[inline(always)]
     fn drop(&mut self) {
@@ -0,0 +1,208 @@
+use crate::allocators::allocator::Allocator;
+use crate::allocators::global::local_allocator::LocalAllocator;
+use crate::allocators::global::memory_range::MemoryRange;
+use crate::memory_address::MemoryAddress;
+use std::alloc::AllocError;
+use std::fmt::Debug;
+use std::num::NonZeroUsize;
+
+/// An object-safe companion to `Allocator`.
+///
+/// Every `Allocator` is a `DynAllocator`, and `&dyn DynAllocator` and `Box<dyn DynAllocator>` are in turn `Allocator`s, so an allocator stack can be chosen at runtime (say, from configuration) without monomorphising every combination.
+///
+/// Methods are prefixed `dyn_` so that they do not clash with those of `Allocator`.
+pub trait DynAllocator: Debug {
+    /// Allocate memory.
+    ///
+    /// See `Allocator::allocate()`.
+    fn dyn_allocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocError>;
+
+    /// Deallocate (free) memory.
+    ///
+    /// See `Allocator::deallocate()`.
+    fn dyn_deallocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        current_memory: MemoryAddress,
+    );
+
+    /// Reallocate memory by growing it.
+    ///
+    /// See `Allocator::growing_reallocate()`.
+    fn dyn_growing_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocError>;
+
+    /// Reallocate memory by shrinking it.
+    ///
+    /// See `Allocator::shrinking_reallocate()`.
+    fn dyn_shrinking_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocError>;
+}
+
+impl<A: Allocator> DynAllocator for A {
+    #[inline(always)]
+    fn dyn_allocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocError> {
+        self.allocate(non_zero_size, non_zero_power_of_two_alignment)
+    }
+
+    #[inline(always)]
+    fn dyn_deallocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) {
+        self.deallocate(
+            non_zero_size,
+            non_zero_power_of_two_alignment,
+            current_memory,
+        )
+    }
+
+    #[inline(always)]
+    fn dyn_growing_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocError> {
+        self.growing_reallocate(
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+            non_zero_current_size,
+            current_memory,
+        )
+    }
+
+    #[inline(always)]
+    fn dyn_shrinking_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocError> {
+        self.shrinking_reallocate(
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+            non_zero_current_size,
+            current_memory,
+        )
+    }
+}
+
+/// An object-safe companion to `LocalAllocator`.
+///
+/// Every `LocalAllocator` is a `DynLocalAllocator`, and `&dyn DynLocalAllocator` and `Box<dyn DynLocalAllocator>` are in turn `LocalAllocator`s, so they can be used as the coroutine local or thread local allocator of a switchable allocator.
+pub trait DynLocalAllocator: DynAllocator {
+    /// The range of memory addresses that can be used to allocate memory by this allocator.
+    ///
+    /// See `LocalAllocator::memory_range()`.
+    fn dyn_memory_range(&self) -> MemoryRange;
+}
+
+impl<A: LocalAllocator> DynLocalAllocator for A {
+    #[inline(always)]
+    fn dyn_memory_range(&self) -> MemoryRange {
+        self.memory_range()
+    }
+}
+
+macro_rules! dyn_allocator {
+    ($type: ty) => {
+        impl<'a> Allocator for $type {
+            #[inline(always)]
+            fn allocate(
+                &self,
+                non_zero_size: NonZeroUsize,
+                non_zero_power_of_two_alignment: NonZeroUsize,
+            ) -> Result<MemoryAddress, AllocError> {
+                (**self).dyn_allocate(non_zero_size, non_zero_power_of_two_alignment)
+            }
+
+            #[inline(always)]
+            fn deallocate(
+                &self,
+                non_zero_size: NonZeroUsize,
+                non_zero_power_of_two_alignment: NonZeroUsize,
+                current_memory: MemoryAddress,
+            ) {
+                (**self).dyn_deallocate(
+                    non_zero_size,
+                    non_zero_power_of_two_alignment,
+                    current_memory,
+                )
+            }
+
+            #[inline(always)]
+            fn growing_reallocate(
+                &self,
+                non_zero_new_size: NonZeroUsize,
+                non_zero_power_of_two_alignment: NonZeroUsize,
+                non_zero_current_size: NonZeroUsize,
+                current_memory: MemoryAddress,
+            ) -> Result<MemoryAddress, AllocError> {
+                (**self).dyn_growing_reallocate(
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                )
+            }
+
+            #[inline(always)]
+            fn shrinking_reallocate(
+                &self,
+                non_zero_new_size: NonZeroUsize,
+                non_zero_power_of_two_alignment: NonZeroUsize,
+                non_zero_current_size: NonZeroUsize,
+                current_memory: MemoryAddress,
+            ) -> Result<MemoryAddress, AllocError> {
+                (**self).dyn_shrinking_reallocate(
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                )
+            }
+        }
+    };
+}
+
+macro_rules! dyn_local_allocator {
+    ($type: ty) => {
+        dyn_allocator!($type);
+
+        impl<'a> LocalAllocator for $type {
+            #[inline(always)]
+            fn memory_range(&self) -> MemoryRange {
+                (**self).dyn_memory_range()
+            }
+        }
+    };
+}
+
+dyn_allocator!(&'a dyn DynAllocator);
+dyn_allocator!(Box<dyn DynAllocator + 'a>);
+dyn_local_allocator!(&'a dyn DynLocalAllocator);
+dyn_local_allocator!(Box<dyn DynLocalAllocator + 'a>);
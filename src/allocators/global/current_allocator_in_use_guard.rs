@@ -0,0 +1,44 @@
+use crate::allocators::global::current_allocator_in_use::CurrentAllocatorInUse;
+use crate::allocators::global::global_switchable_allocator::GlobalSwitchableAllocator;
+use std::marker::PhantomData;
+
+/// Restores the current allocator in use when dropped, including during unwinding.
+///
+/// Created by `GlobalSwitchableAllocator::use_coroutine_local()`, `use_thread_local()`, `use_global()` or `use_different_allocator()`.
+///
+/// Guards nest; they should be dropped in the reverse order to that in which they were created, which is what scoping does.
+///
+/// As the current allocator in use is per-thread, a guard can not be sent to another thread.
+#[derive(Debug)]
+#[must_use = "the previous allocator in use is restored as soon as the guard is dropped"]
+pub struct CurrentAllocatorInUseGuard<'a, GSA: GlobalSwitchableAllocator> {
+    switchable_allocator: &'a GSA,
+    restore_to: CurrentAllocatorInUse,
+    not_send: PhantomData<*const ()>,
+}
+
+impl<'a, GSA: GlobalSwitchableAllocator> Drop for CurrentAllocatorInUseGuard<'a, GSA> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.switchable_allocator
+            .restore_current_allocator_in_use(self.restore_to)
+    }
+}
+
+impl<'a, GSA: GlobalSwitchableAllocator> CurrentAllocatorInUseGuard<'a, GSA> {
+    /// Switches the current allocator in use to `different`.
+    #[inline(always)]
+    pub fn new(switchable_allocator: &'a GSA, different: CurrentAllocatorInUse) -> Self {
+        Self {
+            switchable_allocator,
+            restore_to: switchable_allocator.replace_current_allocator_in_use(different),
+            not_send: PhantomData,
+        }
+    }
+
+    /// The allocator in use which will be restored when dropped.
+    #[inline(always)]
+    pub fn restore_to(&self) -> CurrentAllocatorInUse {
+        self.restore_to
+    }
+}
@@ -1,11 +1,13 @@
 use crate::allocators::allocator::Allocator;
 use crate::allocators::global::current_allocator_in_use::CurrentAllocatorInUse;
+use crate::allocators::global::current_allocator_in_use_guard::CurrentAllocatorInUseGuard;
 use crate::allocators::global::local_allocator::LocalAllocator;
+use crate::allocators::global::overflow_policy::{OverflowPolicy, OverflowStatistics};
 use std::alloc::{Allocator as AllocRef, GlobalAlloc};
 
 /// A trait that all such allocators implement.
 ///
-/// Create a new instance using the macro `switchable_allocator`.
+/// Create a new instance using `SwitchableAllocator::new()` or the macro `switchable_allocator`.
 pub trait GlobalSwitchableAllocator: Sync + GlobalAlloc + AllocRef + Allocator {
     /// Type of the coroutine local allocator.
     type CoroutineLocalAllocator: LocalAllocator;
@@ -27,15 +29,31 @@ pub trait GlobalSwitchableAllocator: Sync + GlobalAlloc + AllocRef + Allocator {
     ) -> Option<Self::CoroutineLocalAllocator>;
 
     /// Initializes the thread local allocator.
+    ///
+    /// If it can not be registered (eg its memory range lies beyond the 48-bit addresses a `PageMap` covers), it is dropped, and allocations with `CurrentAllocatorInUse::ThreadLocal` fall back to the global allocator.
     fn initialize_thread_local_allocator(&self, thread_local_allocator: Self::ThreadLocalAllocator);
 
     /// Drops the thread local allocator.
     ///
     /// Panics in debug if no thread local allocator has been initialized with `initialize_thread_local_allocator()`.
     ///
-    /// Could be made hidden by using a destructor with `libc::pthread_key_create()` for an otherwise unused key.
+    /// `SwitchableAllocator` also tears down the thread local allocator automatically when a thread exits, so calling this is only necessary to drop it sooner.
+    /// As when a thread exits, a thread local allocator which still has live allocations is not dropped but retired, and its memory leaked.
     fn drop_thread_local_allocator(&self);
 
+    /// What to do when the coroutine local or thread local allocator in use can not satisfy an allocation.
+    ///
+    /// Defaults to `OverflowPolicy::Fail`.
+    fn overflow_policy(&self) -> OverflowPolicy;
+
+    /// Sets what to do when the coroutine local or thread local allocator in use can not satisfy an allocation.
+    ///
+    /// Applies to all threads.
+    fn set_overflow_policy(&self, overflow_policy: OverflowPolicy);
+
+    /// Counts of how often local allocators have overflowed, across all threads.
+    fn overflow_statistics(&self) -> OverflowStatistics;
+
     /// Save the current allocator in use.
     fn save_current_allocator_in_use(&self) -> CurrentAllocatorInUse;
 
@@ -53,6 +71,35 @@ pub trait GlobalSwitchableAllocator: Sync + GlobalAlloc + AllocRef + Allocator {
         was
     }
 
+    /// Switch the current allocator in use to coroutine local until the returned guard is dropped.
+    #[inline(always)]
+    fn use_coroutine_local(&self) -> CurrentAllocatorInUseGuard<Self> {
+        self.use_different_allocator(CurrentAllocatorInUse::CoroutineLocal)
+    }
+
+    /// Switch the current allocator in use to thread local until the returned guard is dropped.
+    #[inline(always)]
+    fn use_thread_local(&self) -> CurrentAllocatorInUseGuard<Self> {
+        self.use_different_allocator(CurrentAllocatorInUse::ThreadLocal)
+    }
+
+    /// Switch the current allocator in use to global until the returned guard is dropped.
+    #[inline(always)]
+    fn use_global(&self) -> CurrentAllocatorInUseGuard<Self> {
+        self.use_different_allocator(CurrentAllocatorInUse::Global)
+    }
+
+    /// Switch the current allocator in use until the returned guard is dropped, including when dropped during unwinding.
+    ///
+    /// Unlike the `callback_with_*` methods, this works across early returns and `?`.
+    #[inline(always)]
+    fn use_different_allocator(
+        &self,
+        different: CurrentAllocatorInUse,
+    ) -> CurrentAllocatorInUseGuard<Self> {
+        CurrentAllocatorInUseGuard::new(self, different)
+    }
+
     /// Switch the current allocator in use to coroutine local and execute the callback; restore it after calling the callback unless a panic occurs.
     #[inline(always)]
     fn callback_with_coroutine_local_allocator<R>(&self, callback: impl FnOnce() -> R) -> R {
@@ -0,0 +1,441 @@
+use crate::allocators::allocator::Allocator;
+use crate::allocators::global::memory_range::MemoryRange;
+#[cfg(unix)]
+use crate::allocators::global::page_map::{PageMap, PageOwner};
+use crate::extensions::prelude::*;
+use crate::memory_address::MemoryAddress;
+use std::alloc::AllocError;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::mem::{align_of, size_of};
+use std::num::NonZeroUsize;
+use std::ptr::{null_mut, NonNull};
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicPtr, AtomicUsize};
+
+/// A registry of the memory ranges of local allocators, so that memory freed on a thread other than the one owning a local allocator can be found and queued for the owning thread.
+///
+/// Each registration has a lock-free queue of remote frees, which only the owning thread drains.
+///
+/// Registrations are only freed when the registry itself is freed; until then, a vacated registration is reused.
+/// A registration retired with `retire_until_freed()` is vacated once all of its leaked bytes have been freed.
+///
+/// On unix, a `PageMap` finds the registration owning an address in constant time; a linear search is only needed for addresses in pages shared by more than one registration's memory range.
+pub struct LocalAllocatorRegistry {
+    head: AtomicPtr<LocalAllocatorRegistration>,
+    #[cfg(unix)]
+    page_map: PageMap,
+}
+
+impl Debug for LocalAllocatorRegistry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "LocalAllocatorRegistry")
+    }
+}
+
+impl Default for LocalAllocatorRegistry {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalAllocatorRegistry {
+    /// Creates a new, empty instance.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(null_mut()),
+            #[cfg(unix)]
+            page_map: PageMap::new(),
+        }
+    }
+
+    /// Registers the memory range of a local allocator owned by the current thread.
+    ///
+    /// `allocator` is used to allocate the registration if a vacated one can not be reused; it must not itself be a local allocator.
+    pub fn register<A: Allocator>(
+        &self,
+        allocator: &A,
+        memory_range: MemoryRange,
+    ) -> Result<&LocalAllocatorRegistration, AllocError> {
+        use self::LocalAllocatorRegistration as R;
+
+        let mut registration = self.head.load(Acquire);
+        while !registration.is_null() {
+            let reusable = unsafe { &*registration };
+            if reusable
+                .state
+                .compare_exchange(R::VACANT, R::REGISTERING, Acquire, Relaxed)
+                .is_ok()
+            {
+                reusable.set_memory_range(memory_range);
+                reusable.state.store(R::OWNED, Release);
+                return self.map(reusable);
+            }
+            registration = reusable.next;
+        }
+
+        let registration = allocator
+            .allocate(
+                size_of::<LocalAllocatorRegistration>().non_zero(),
+                align_of::<LocalAllocatorRegistration>().non_zero(),
+            )?
+            .as_ptr() as *mut LocalAllocatorRegistration;
+
+        let mut next = self.head.load(Acquire);
+        loop {
+            unsafe {
+                registration.write(LocalAllocatorRegistration {
+                    from: AtomicUsize::new(memory_range.from.to_usize()),
+                    to: AtomicUsize::new(memory_range.to.to_usize()),
+                    state: AtomicUsize::new(R::OWNED),
+                    leaked_bytes: AtomicUsize::new(0),
+                    remote_frees: AtomicPtr::new(null_mut()),
+                    next,
+                })
+            };
+            match self
+                .head
+                .compare_exchange_weak(next, registration, AcqRel, Acquire)
+            {
+                Ok(_) => return self.map(unsafe { &*registration }),
+                Err(was) => next = was,
+            }
+        }
+    }
+
+    /// Vacates a registration so it can be reused; the local allocator must have no live allocations.
+    #[inline(always)]
+    pub fn vacate(&self, registration: &LocalAllocatorRegistration) {
+        #[cfg(unix)]
+        self.page_map
+            .remove(registration.memory_range(), registration.page_map_owner());
+        registration
+            .state
+            .store(LocalAllocatorRegistration::VACANT, Release)
+    }
+
+    /// Frees memory from a local allocator owned by another thread.
+    ///
+    /// If the local allocator is still owned, the memory is queued for the owning thread.
+    /// If it has been retired, the memory is not freed; a registration retired with `retire_until_freed()` is vacated once the last of its leaked bytes has been freed.
+    ///
+    /// Fails if the memory is too small to hold its own queue entry and one can not be allocated from `allocator`; the memory is then not freed.
+    #[inline(always)]
+    pub fn free_remotely<A: Allocator>(
+        &self,
+        registration: &LocalAllocatorRegistration,
+        allocator: &A,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<(), AllocError> {
+        if unlikely!(registration.is_retired()) {
+            if registration.forget_freed_bytes(non_zero_size.get()) {
+                self.vacate(registration)
+            }
+            return Ok(());
+        }
+
+        registration.queue_remote_free(
+            allocator,
+            non_zero_size,
+            non_zero_power_of_two_alignment,
+            current_memory,
+        )
+    }
+
+    /// Retires a local allocator which still has `leaked_bytes` of live allocations: its memory will never be freed, and frees of memory within it are only counted from now on.
+    ///
+    /// Once all `leaked_bytes` have been freed, the registration is vacated so it can be reused.
+    /// Remote frees already queued are counted as freed; `allocator` must be the one passed to `free_remotely()`.
+    pub fn retire_until_freed<A: Allocator>(
+        &self,
+        registration: &LocalAllocatorRegistration,
+        allocator: &A,
+        leaked_bytes: NonZeroUsize,
+    ) {
+        use self::LocalAllocatorRegistration as R;
+
+        registration.leaked_bytes.store(leaked_bytes.get(), Relaxed);
+        registration.state.store(R::RETIRED_UNTIL_FREED, Release);
+
+        let mut freed_bytes = 0;
+        registration.drain_remote_frees(allocator, |non_zero_size, _, _| {
+            freed_bytes += non_zero_size.get()
+        });
+        if freed_bytes != 0 && registration.forget_freed_bytes(freed_bytes) {
+            self.vacate(registration)
+        }
+    }
+
+    /// Finds the owned or retired registration whose memory range contains `current_memory`.
+    #[cfg(unix)]
+    #[inline(always)]
+    pub fn find(&self, current_memory: MemoryAddress) -> Option<&LocalAllocatorRegistration> {
+        match self.page_map.get(current_memory) {
+            PageOwner::Unowned => None,
+
+            PageOwner::Owner(owner) => {
+                let registration = unsafe { &*(owner.get() as *const LocalAllocatorRegistration) };
+                if likely!(registration.contains(current_memory)) {
+                    Some(registration)
+                } else {
+                    None
+                }
+            }
+
+            PageOwner::Shared => self.search(current_memory),
+        }
+    }
+
+    /// Finds the owned or retired registration whose memory range contains `current_memory`.
+    #[cfg(not(unix))]
+    #[inline(always)]
+    pub fn find(&self, current_memory: MemoryAddress) -> Option<&LocalAllocatorRegistration> {
+        self.search(current_memory)
+    }
+
+    #[cfg(unix)]
+    #[inline(always)]
+    fn map<'a>(
+        &self,
+        registration: &'a LocalAllocatorRegistration,
+    ) -> Result<&'a LocalAllocatorRegistration, AllocError> {
+        match self
+            .page_map
+            .insert(registration.memory_range(), registration.page_map_owner())
+        {
+            Ok(()) => Ok(registration),
+            Err(allocation_error) => {
+                self.vacate(registration);
+                Err(allocation_error)
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    #[inline(always)]
+    fn map<'a>(
+        &self,
+        registration: &'a LocalAllocatorRegistration,
+    ) -> Result<&'a LocalAllocatorRegistration, AllocError> {
+        Ok(registration)
+    }
+
+    #[cold]
+    fn search(&self, current_memory: MemoryAddress) -> Option<&LocalAllocatorRegistration> {
+        let mut registration = self.head.load(Acquire);
+        while !registration.is_null() {
+            let candidate = unsafe { &*registration };
+            if candidate.contains(current_memory) {
+                return Some(candidate);
+            }
+            registration = candidate.next;
+        }
+        None
+    }
+
+    /// Frees all registrations and any remote frees still queued, without passing them to the local allocators.
+    ///
+    /// # Safety
+    ///
+    /// No registration may be in use; `allocator` must be the one passed to `register()`.
+    pub unsafe fn free<A: Allocator>(&self, allocator: &A) {
+        let mut registration = self.head.swap(null_mut(), AcqRel);
+        while !registration.is_null() {
+            let next = (*registration).next;
+            (*registration).drain_remote_frees(allocator, |_, _, _| {});
+            allocator.deallocate(
+                size_of::<LocalAllocatorRegistration>().non_zero(),
+                align_of::<LocalAllocatorRegistration>().non_zero(),
+                NonNull::new_unchecked(registration as *mut u8),
+            );
+            registration = next
+        }
+    }
+}
+
+/// The registration of a local allocator's memory range.
+pub struct LocalAllocatorRegistration {
+    from: AtomicUsize,
+    to: AtomicUsize,
+    state: AtomicUsize,
+    leaked_bytes: AtomicUsize,
+    remote_frees: AtomicPtr<RemoteFree>,
+    next: *mut LocalAllocatorRegistration,
+}
+
+impl Debug for LocalAllocatorRegistration {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LocalAllocatorRegistration({:?}, {})",
+            self.memory_range(),
+            self.state.load(Relaxed)
+        )
+    }
+}
+
+impl LocalAllocatorRegistration {
+    const VACANT: usize = 0;
+
+    const REGISTERING: usize = 1;
+
+    const OWNED: usize = 2;
+
+    const RETIRED: usize = 3;
+
+    const RETIRED_UNTIL_FREED: usize = 4;
+
+    /// The registered memory range.
+    #[inline(always)]
+    pub fn memory_range(&self) -> MemoryRange {
+        MemoryRange::new(
+            MemoryAddress::from_usize(self.from.load(Relaxed)),
+            MemoryAddress::from_usize(self.to.load(Relaxed)),
+        )
+    }
+
+    #[inline(always)]
+    fn set_memory_range(&self, memory_range: MemoryRange) {
+        self.from.store(memory_range.from.to_usize(), Relaxed);
+        self.to.store(memory_range.to.to_usize(), Relaxed)
+    }
+
+    #[inline(always)]
+    fn contains(&self, current_memory: MemoryAddress) -> bool {
+        match self.state.load(Acquire) {
+            Self::OWNED | Self::RETIRED | Self::RETIRED_UNTIL_FREED => {
+                self.memory_range().contains(current_memory)
+            }
+            _ => false,
+        }
+    }
+
+    /// Is the local allocator retired, ie its memory is never freed?
+    #[inline(always)]
+    pub fn is_retired(&self) -> bool {
+        match self.state.load(Acquire) {
+            Self::RETIRED | Self::RETIRED_UNTIL_FREED => true,
+            _ => false,
+        }
+    }
+
+    /// Queues memory freed on a thread other than the owning thread.
+    ///
+    /// The queue entry is written into the freed memory itself if it is large enough and suitably aligned; otherwise it is allocated from `allocator`.
+    #[inline(always)]
+    fn queue_remote_free<A: Allocator>(
+        &self,
+        allocator: &A,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<(), AllocError> {
+        let remote_free = if likely!(RemoteFree::fits_in(non_zero_size, current_memory)) {
+            current_memory.as_ptr() as *mut RemoteFree
+        } else {
+            allocator
+                .allocate(
+                    size_of::<RemoteFree>().non_zero(),
+                    align_of::<RemoteFree>().non_zero(),
+                )?
+                .as_ptr() as *mut RemoteFree
+        };
+
+        let mut next = self.remote_frees.load(Acquire);
+        loop {
+            unsafe {
+                remote_free.write(RemoteFree {
+                    next,
+                    non_zero_size,
+                    non_zero_power_of_two_alignment,
+                    current_memory,
+                })
+            };
+            match self
+                .remote_frees
+                .compare_exchange_weak(next, remote_free, AcqRel, Acquire)
+            {
+                Ok(_) => return Ok(()),
+                Err(was) => next = was,
+            }
+        }
+    }
+
+    /// Are there remote frees queued?
+    #[inline(always)]
+    pub fn has_remote_frees(&self) -> bool {
+        !self.remote_frees.load(Relaxed).is_null()
+    }
+
+    /// Drains the queue of remote frees, calling `deallocate` for each; only the owning thread should do this.
+    ///
+    /// `allocator` must be the one passed to `free_remotely()`.
+    pub fn drain_remote_frees<A: Allocator>(
+        &self,
+        allocator: &A,
+        mut deallocate: impl FnMut(NonZeroUsize, NonZeroUsize, MemoryAddress),
+    ) {
+        let mut remote_free = self.remote_frees.swap(null_mut(), Acquire);
+        while !remote_free.is_null() {
+            let RemoteFree {
+                next,
+                non_zero_size,
+                non_zero_power_of_two_alignment,
+                current_memory,
+            } = unsafe { remote_free.read() };
+            if (remote_free as usize) != current_memory.to_usize() {
+                allocator.deallocate(
+                    size_of::<RemoteFree>().non_zero(),
+                    align_of::<RemoteFree>().non_zero(),
+                    unsafe { NonNull::new_unchecked(remote_free as *mut u8) },
+                );
+            }
+            deallocate(
+                non_zero_size,
+                non_zero_power_of_two_alignment,
+                current_memory,
+            );
+            remote_free = next
+        }
+    }
+
+    /// Retires the local allocator: its memory will never be freed, so frees of memory within it are ignored from now on.
+    ///
+    /// The registration is never vacated; use `LocalAllocatorRegistry::retire_until_freed()` if the live allocations are known.
+    #[inline(always)]
+    pub fn retire(&self) {
+        self.state.store(Self::RETIRED, Release)
+    }
+
+    /// Returns `true` if these were the last of the leaked bytes of a registration retired until freed.
+    #[inline(always)]
+    fn forget_freed_bytes(&self, freed_bytes: usize) -> bool {
+        self.state.load(Acquire) == Self::RETIRED_UNTIL_FREED
+            && self.leaked_bytes.fetch_sub(freed_bytes, AcqRel) == freed_bytes
+    }
+
+    #[inline(always)]
+    fn page_map_owner(&self) -> NonZeroUsize {
+        (self as *const Self as usize).non_zero()
+    }
+}
+
+struct RemoteFree {
+    next: *mut RemoteFree,
+    non_zero_size: NonZeroUsize,
+    non_zero_power_of_two_alignment: NonZeroUsize,
+    current_memory: MemoryAddress,
+}
+
+impl RemoteFree {
+    #[inline(always)]
+    fn fits_in(non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> bool {
+        non_zero_size.get() >= size_of::<Self>()
+            && current_memory.to_usize() % align_of::<Self>() == 0
+    }
+}
@@ -1,17 +1,29 @@
 pub mod current_allocator_in_use;
+pub mod current_allocator_in_use_guard;
 pub mod global_switchable_allocator;
 pub mod local_allocator;
+pub mod local_allocator_registry;
 pub mod memory_range;
+pub mod overflow_policy;
+#[cfg(unix)]
+pub mod page_map;
 pub mod per_thread_state;
 #[macro_use]
 pub mod switchable_allocator;
+pub mod with_allocator;
 
 #[macro_use]
 pub mod prelude {
     pub use super::current_allocator_in_use::*;
+    pub use super::current_allocator_in_use_guard::*;
     pub use super::global_switchable_allocator::*;
     pub use super::local_allocator::*;
+    pub use super::local_allocator_registry::*;
     pub use super::memory_range::*;
+    pub use super::overflow_policy::*;
+    #[cfg(unix)]
+    pub use super::page_map::*;
     pub use super::per_thread_state::*;
     pub use super::switchable_allocator::*;
+    pub use super::with_allocator::*;
 }
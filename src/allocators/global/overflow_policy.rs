@@ -0,0 +1,51 @@
+use crate::allocators::global::current_allocator_in_use::CurrentAllocatorInUse;
+use std::num::NonZeroUsize;
+
+/// What a global, switchable allocator does when the coroutine local or thread local allocator in use can not satisfy an allocation (or a growing reallocation).
+///
+/// Whatever allocator is used instead, deallocation remains correct, as it is routed by memory range rather than by the current allocator in use.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum OverflowPolicy {
+    /// Fail the allocation.
+    Fail,
+
+    /// Use the global allocator instead.
+    Global,
+
+    /// Use another allocator instead, eg the thread local allocator when a coroutine local allocator is exhausted.
+    ///
+    /// Fails if it is the allocator which overflowed.
+    Secondary(CurrentAllocatorInUse),
+
+    /// Call a hook to decide which allocator to use instead, if any.
+    Hook(OverflowHook),
+}
+
+impl Default for OverflowPolicy {
+    #[inline(always)]
+    fn default() -> Self {
+        OverflowPolicy::Fail
+    }
+}
+
+/// Called when the allocator `overflowed` can not satisfy an allocation of `non_zero_size` bytes.
+///
+/// Returns the allocator to use instead, or `None` to fail the allocation.
+pub type OverflowHook = fn(
+    overflowed: CurrentAllocatorInUse,
+    non_zero_size: NonZeroUsize,
+    non_zero_power_of_two_alignment: NonZeroUsize,
+) -> Option<CurrentAllocatorInUse>;
+
+/// Counts of how often local allocators have overflowed.
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct OverflowStatistics {
+    /// How often the coroutine local allocator overflowed.
+    pub coroutine_local_overflows: usize,
+
+    /// How often the thread local allocator overflowed.
+    pub thread_local_overflows: usize,
+
+    /// How often an overflow could not be satisfied by another allocator, either because of the overflow policy or because it too failed.
+    pub unsatisfied_overflows: usize,
+}
@@ -0,0 +1,262 @@
+use crate::allocators::global::memory_range::MemoryRange;
+use crate::extensions::prelude::*;
+use crate::memory_address::MemoryAddress;
+use libc::{mmap, munmap, MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+use std::alloc::AllocError;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::mem::size_of;
+use std::num::NonZeroUsize;
+use std::ptr::null_mut;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{AcqRel, Acquire};
+
+/// A map from the page of a memory address to the identifier of the allocator which owns it, eg a pointer to a registration.
+///
+/// Lookups are constant time: the map is a three level radix tree indexed by page number, covering 48-bit addresses.
+/// Nodes are obtained directly from `mmap()`, so the map can be used inside a global allocator without recursion, and are only released when the map is dropped.
+///
+/// Ownership is recorded per page; a page overlapped by the memory ranges of more than one owner becomes `PageOwner::Shared`.
+/// A shared page counts its owners, and becomes unowned again once all of them have been removed.
+pub struct PageMap {
+    root: AtomicUsize,
+}
+
+impl Debug for PageMap {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "PageMap")
+    }
+}
+
+impl Default for PageMap {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for PageMap {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let root = self.root.load(Acquire);
+        if root != Self::ABSENT {
+            Self::release_node(root, 0)
+        }
+    }
+}
+
+impl PageMap {
+    const ABSENT: usize = 0;
+
+    /// Set in entries for shared pages, which hold the number of owners in the remaining bits.
+    const SHARED: usize = 1;
+
+    const ONE_SHARED_OWNER: usize = 1 << 1;
+
+    const PAGE_SHIFT: usize = 12;
+
+    const BITS_PER_LEVEL: usize = 12;
+
+    const LEVELS: usize = 3;
+
+    const ENTRIES_PER_NODE: usize = 1 << Self::BITS_PER_LEVEL;
+
+    const NODE_SIZE: usize = Self::ENTRIES_PER_NODE * size_of::<AtomicUsize>();
+
+    const MAXIMUM_PAGE_NUMBER: usize = 1 << (Self::BITS_PER_LEVEL * Self::LEVELS);
+
+    /// Creates a new, empty instance.
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Self {
+            root: AtomicUsize::new(Self::ABSENT),
+        }
+    }
+
+    /// Maps every page overlapping `memory_range` to `owner`, or to shared if already mapped to a different owner.
+    ///
+    /// `owner` must be even, as the lowest bit is used to represent shared; a pointer to anything aligned to two or more bytes is.
+    /// The memory ranges inserted for one owner must not overlap the same page more than once.
+    ///
+    /// Fails if a node could not be mapped or the memory range lies beyond 48-bit addresses.
+    pub fn insert(&self, memory_range: MemoryRange, owner: NonZeroUsize) -> Result<(), AllocError> {
+        debug_assert_eq!(owner.get() & Self::SHARED, 0, "owner must be even");
+
+        let (first_page_number, last_page_number) = Self::page_numbers(memory_range)?;
+        for page_number in first_page_number..=last_page_number {
+            let _ = self
+                .entry_or_insert(page_number)?
+                .fetch_update(AcqRel, Acquire, |existing| match existing {
+                    Self::ABSENT => Some(owner.get()),
+                    existing if existing == owner.get() => None,
+                    shared if shared & Self::SHARED != 0 => Some(shared + Self::ONE_SHARED_OWNER),
+                    _ => Some(Self::SHARED | (2 * Self::ONE_SHARED_OWNER)),
+                });
+        }
+        Ok(())
+    }
+
+    /// Unmaps every page overlapping `memory_range` which is mapped to `owner`, and removes `owner` from every such page which is shared.
+    ///
+    /// `memory_range` and `owner` must have been inserted.
+    pub fn remove(&self, memory_range: MemoryRange, owner: NonZeroUsize) {
+        let (first_page_number, last_page_number) = match Self::page_numbers(memory_range) {
+            Ok(page_numbers) => page_numbers,
+            Err(_) => return,
+        };
+        for page_number in first_page_number..=last_page_number {
+            if let Some(entry) = self.entry(page_number) {
+                let _ = entry.fetch_update(AcqRel, Acquire, |existing| match existing {
+                    Self::ABSENT => None,
+                    existing if existing == owner.get() => Some(Self::ABSENT),
+                    shared if shared & Self::SHARED != 0 => {
+                        if shared == Self::SHARED | Self::ONE_SHARED_OWNER {
+                            Some(Self::ABSENT)
+                        } else {
+                            Some(shared - Self::ONE_SHARED_OWNER)
+                        }
+                    }
+                    _ => None,
+                });
+            }
+        }
+    }
+
+    /// The owner of the page containing `address`.
+    #[inline(always)]
+    pub fn get(&self, address: MemoryAddress) -> PageOwner {
+        let page_number = address.to_usize() >> Self::PAGE_SHIFT;
+        if unlikely!(page_number >= Self::MAXIMUM_PAGE_NUMBER) {
+            return PageOwner::Unowned;
+        }
+
+        match self.entry(page_number) {
+            None => PageOwner::Unowned,
+            Some(entry) => match entry.load(Acquire) {
+                Self::ABSENT => PageOwner::Unowned,
+                shared if shared & Self::SHARED != 0 => PageOwner::Shared,
+                owner => PageOwner::Owner(owner.non_zero()),
+            },
+        }
+    }
+
+    #[inline(always)]
+    fn page_numbers(memory_range: MemoryRange) -> Result<(usize, usize), AllocError> {
+        let from = memory_range.from.to_usize();
+        let to = memory_range.to.to_usize();
+        if unlikely!(to <= from) {
+            return Err(AllocError);
+        }
+
+        let first_page_number = from >> Self::PAGE_SHIFT;
+        let last_page_number = (to - 1) >> Self::PAGE_SHIFT;
+        if unlikely!(last_page_number >= Self::MAXIMUM_PAGE_NUMBER) {
+            return Err(AllocError);
+        }
+        Ok((first_page_number, last_page_number))
+    }
+
+    #[inline(always)]
+    fn index(page_number: usize, level: usize) -> usize {
+        (page_number >> (Self::BITS_PER_LEVEL * (Self::LEVELS - 1 - level)))
+            & (Self::ENTRIES_PER_NODE - 1)
+    }
+
+    #[inline(always)]
+    fn entry(&self, page_number: usize) -> Option<&AtomicUsize> {
+        let mut node = self.root.load(Acquire);
+        for level in 0..(Self::LEVELS - 1) {
+            if node == Self::ABSENT {
+                return None;
+            }
+            node = Self::node_entry(node, Self::index(page_number, level)).load(Acquire)
+        }
+        if node == Self::ABSENT {
+            return None;
+        }
+        Some(Self::node_entry(
+            node,
+            Self::index(page_number, Self::LEVELS - 1),
+        ))
+    }
+
+    fn entry_or_insert(&self, page_number: usize) -> Result<&AtomicUsize, AllocError> {
+        let mut node = Self::child_or_insert(&self.root)?;
+        for level in 0..(Self::LEVELS - 1) {
+            node = Self::child_or_insert(Self::node_entry(node, Self::index(page_number, level)))?
+        }
+        Ok(Self::node_entry(
+            node,
+            Self::index(page_number, Self::LEVELS - 1),
+        ))
+    }
+
+    #[inline(always)]
+    fn child_or_insert(entry: &AtomicUsize) -> Result<usize, AllocError> {
+        let child = entry.load(Acquire);
+        if likely!(child != Self::ABSENT) {
+            return Ok(child);
+        }
+
+        let new_child = Self::map_node()?;
+        match entry.compare_exchange(Self::ABSENT, new_child, AcqRel, Acquire) {
+            Ok(_) => Ok(new_child),
+
+            Err(inserted_by_another_thread) => {
+                unsafe { munmap(new_child as *mut _, Self::NODE_SIZE) };
+                Ok(inserted_by_another_thread)
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn node_entry<'a>(node: usize, index: usize) -> &'a AtomicUsize {
+        unsafe { &*(node as *const AtomicUsize).add(index) }
+    }
+
+    /// Anonymous memory is zeroed, ie all entries are absent.
+    #[cold]
+    fn map_node() -> Result<usize, AllocError> {
+        let node = unsafe {
+            mmap(
+                null_mut(),
+                Self::NODE_SIZE,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if unlikely!(node == MAP_FAILED) {
+            Err(AllocError)
+        } else {
+            Ok(node as usize)
+        }
+    }
+
+    fn release_node(node: usize, level: usize) {
+        if level < Self::LEVELS - 1 {
+            for index in 0..Self::ENTRIES_PER_NODE {
+                let child = Self::node_entry(node, index).load(Acquire);
+                if child != Self::ABSENT {
+                    Self::release_node(child, level + 1)
+                }
+            }
+        }
+        unsafe { munmap(node as *mut _, Self::NODE_SIZE) };
+    }
+}
+
+/// The owner of a page in a `PageMap`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PageOwner {
+    /// No owner.
+    Unowned,
+
+    /// A single owner.
+    Owner(NonZeroUsize),
+
+    /// More than one owner; callers need to find which owner a particular address belongs to some other way.
+    Shared,
+}
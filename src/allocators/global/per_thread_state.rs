@@ -1,5 +1,7 @@
 use crate::allocators::global::current_allocator_in_use::CurrentAllocatorInUse;
 use crate::allocators::global::local_allocator::LocalAllocator;
+use crate::allocators::global::local_allocator_registry::LocalAllocatorRegistration;
+use std::ptr::null;
 
 #[doc(hidden)]
 #[allow(dead_code)]
@@ -10,6 +12,9 @@ pub struct PerThreadState<
     pub current_allocator_in_use: CurrentAllocatorInUse,
     pub coroutine_local_allocator: Option<CoroutineLocalAllocator>,
     pub thread_local_allocator: Option<ThreadLocalAllocator>,
+    pub thread_local_allocator_live_bytes: usize,
+    pub thread_local_allocator_factory_failed: bool,
+    pub thread_local_allocator_registration: *const LocalAllocatorRegistration,
 }
 
 impl<CoroutineLocalAllocator: LocalAllocator, ThreadLocalAllocator: LocalAllocator>
@@ -22,6 +27,9 @@ impl<CoroutineLocalAllocator: LocalAllocator, ThreadLocalAllocator: LocalAllocat
             current_allocator_in_use: CurrentAllocatorInUse::Global,
             coroutine_local_allocator: None,
             thread_local_allocator: None,
+            thread_local_allocator_live_bytes: 0,
+            thread_local_allocator_factory_failed: false,
+            thread_local_allocator_registration: null(),
         }
     }
 
@@ -32,6 +40,15 @@ impl<CoroutineLocalAllocator: LocalAllocator, ThreadLocalAllocator: LocalAllocat
             current_allocator_in_use: CurrentAllocatorInUse::ThreadLocal,
             coroutine_local_allocator: None,
             thread_local_allocator: None,
+            thread_local_allocator_live_bytes: 0,
+            thread_local_allocator_factory_failed: false,
+            thread_local_allocator_registration: null(),
         }
     }
+
+    #[inline(always)]
+    pub(crate) fn thread_local_allocator_has_remote_frees(&self) -> bool {
+        let registration = self.thread_local_allocator_registration;
+        !registration.is_null() && unsafe { &*registration }.has_remote_frees()
+    }
 }
@@ -1,252 +1,1142 @@
-/// Creates a new global, switchable allocator inside a module `$mod_name`.
+use crate::allocators::allocator::Allocator;
+use crate::allocators::global::current_allocator_in_use::CurrentAllocatorInUse;
+use crate::allocators::global::global_switchable_allocator::GlobalSwitchableAllocator;
+use crate::allocators::global::local_allocator::LocalAllocator;
+use crate::allocators::global::local_allocator_registry::{
+    LocalAllocatorRegistration, LocalAllocatorRegistry,
+};
+use crate::allocators::global::memory_range::MemoryRange;
+use crate::allocators::global::overflow_policy::{OverflowPolicy, OverflowStatistics};
+use crate::allocators::global::per_thread_state::PerThreadState;
+use crate::extensions::prelude::*;
+use crate::memory_address::MemoryAddress;
+use core::ptr::NonNull;
+#[cfg(unix)]
+use libc::{
+    c_void, pthread_getspecific, pthread_key_create, pthread_key_delete, pthread_key_t,
+    pthread_setspecific,
+};
+use std::alloc::{AllocError as AllocErr, Allocator as AllocRef, GlobalAlloc, Layout};
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::marker::PhantomData;
+use std::mem::{align_of, forget, replace, size_of, transmute};
+use std::num::NonZeroUsize;
+use std::ptr::null;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! choose_allocator
+{
+	($self: ident, $current_memory: ident, $callback: ident, $($argument: ident),*) =>
+	{
+		{
+			if let Some(coroutine_local_allocator) = $self.coroutine_local_allocator()
+			{
+				if likely!(coroutine_local_allocator.contains($current_memory))
+				{
+					return coroutine_local_allocator.$callback($($argument, )*)
+				}
+			}
+
+			if let Some(thread_local_allocator) = $self.thread_local_allocator()
+			{
+				if likely!(thread_local_allocator.contains($current_memory))
+				{
+					return thread_local_allocator.$callback($($argument, )*)
+				}
+			}
+
+			$self.global_allocator().$callback($($argument, )*)
+		}
+	}
+}
+
+/// A global, switchable allocator.
 ///
-/// Parameters:-
+/// * `CoroutineLocalAllocator`: the type of the coroutine local allocator.
+/// * `ThreadLocalAllocator`: the type of the thread local allocator.
+/// * `GlobalAllocator`: the type of the global allocator; a common usage is `GlobalAllocToAllocatorAdaptor<System>`.
 ///
-/// * `$mod_name`: A `pub(crate)` module.
-/// * `$CoroutineLocalAllocator`: the type of the coroutine local allocator. Must implement `LocalAllocator`.
-/// * `$ThreadLocalAllocator`: the type of the thread local allocator. Must implement `LocalAllocator`.
-/// * `$GlobalAllocator`: the type of the thread local allocator. Must implement `Allocator`; a common usage is `GlobalAllocToAllocatorAdaptor<System>`.
-/// * `global_allocator_instance`: a constant expression for instantiating the global allocator. A common usage is `GlobalAllocToAllocatorAdaptor(System)`.
+/// Each instance keeps its per-thread state (the current allocator in use, and the coroutine local and thread local allocators) behind its own pthread key, so any number of instances can exist at once.
+/// An instance can be used as a plain value which is never moved (eg in a unit test) or, as it can be created in a constant expression, as a `#[global_allocator]`.
+/// The per-thread state is itself allocated from the global allocator the first time a thread uses an instance.
 ///
-/// To access the switchable allocator, call `$mod_name::global_thread_and_coroutine_switchable_allocator()`; this returns an object reference that implements the trait `GlobalSwitchableAllocator`.
+/// When a thread exits its per-thread state is torn down automatically by the pthread key's destructor:-
 ///
-/// Done using a macro due to a limitation when combining thread-local statics with generics (which could be solved using pthread keys, but these aren't always the most efficient of approaches); in essence, a thread-local struct field is needed.
+/// * A thread local allocator with no live allocations is dropped;
+/// * A thread local allocator with live allocations is retired instead: its memory is left mapped, its registration is flagged so that later frees (eg from other threads) of memory within it are only counted, and the hook set with `set_leaked_thread_local_allocator_hook()` is called with the number of leaked bytes; once all of them have been freed, the registration is reused;
+/// * A coroutine local allocator still in place is always retired, as its live allocations are not tracked.
 ///
-/// # Example
+/// When the coroutine local or thread local allocator in use can not satisfy an allocation, what happens instead is decided by the `OverflowPolicy` set with `set_overflow_policy()`.
 ///
-/// ```Rust
+/// Thread local allocators are registered in a `LocalAllocatorRegistry`.
+/// Memory freed (or reallocated) on a thread other than the one owning the thread local allocator it came from is found using the registry, and queued for the owning thread, which frees it the next time it allocates from its thread local allocator.
+/// Coroutine local allocators are not registered, so memory from them must not be freed on another thread.
 ///
-/// switchable_allocator!(application_allocator, BumpAllocator<ArenaMemorySource<MemoryMapSource>>, MultipleBinarySearchTreeAllocator<MemoryMapSource>, GlobalAllocToAllocatorAdaptor<System>, GlobalAllocToAllocatorAdaptor(System));
+/// A thread local allocator can also be created lazily, the first time a thread allocates with `CurrentAllocatorInUse::ThreadLocal`, by setting a factory with `set_thread_local_allocator_factory()`.
+/// If there is no factory, or it fails, allocations fall back to the global allocator rather than panicking.
 ///
-/// ```
-#[macro_export]
-macro_rules! switchable_allocator {
-    ($mod_name: ident, $CoroutineLocalAllocator: ty, $ThreadLocalAllocator: ty, $GlobalAllocator: ty, $global_allocator_instance: expr) => {
-        #[global_allocator]
-        pub(crate) static GLOBAL: $mod_name::SwitchableAllocator =
-            $mod_name::SwitchableAllocator {
-                global_allocator: $global_allocator_instance,
-            };
+/// As the destructor refers back to the instance, an instance must not be moved once it has been used (a `static` never is); hence `new()` is `unsafe`.
+/// Dropping an instance tears down the per-thread state of the current thread only; that of other threads still running is leaked.
+///
+/// The macro `switchable_allocator!` is a thin wrapper which creates a `#[global_allocator]` instance.
+#[cfg(unix)]
+pub struct SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator> {
+    global_allocator: GlobalAllocator,
+    per_thread_state_key: AtomicUsize,
+    tear_down_when_dropped: AtomicUsize,
+    leaked_thread_local_allocator_hook: AtomicUsize,
+    thread_local_allocator_factory: AtomicUsize,
+    overflow_policy: AtomicUsize,
+    coroutine_local_overflows: AtomicUsize,
+    thread_local_overflows: AtomicUsize,
+    unsatisfied_overflows: AtomicUsize,
+    leaked_remote_frees: AtomicUsize,
+    local_allocator_registry: LocalAllocatorRegistry,
+    marker: PhantomData<(CoroutineLocalAllocator, ThreadLocalAllocator)>,
+}
 
-        pub(crate) mod $mod_name {
+/// Called when a thread exits, or drops its thread local allocator, whilst the thread local allocator still has live allocations.
+///
+/// * `leaked_bytes`: the number of bytes still allocated.
+/// * `memory_range`: the memory range of the retired thread local allocator.
+pub type LeakedThreadLocalAllocatorHook = fn(leaked_bytes: usize, memory_range: MemoryRange);
 
-            use core::ptr::NonNull;
+/// Creates a thread local allocator the first time a thread needs one.
+///
+/// Called with the current allocator in use set to global.
+pub type ThreadLocalAllocatorFactory<ThreadLocalAllocator> =
+    fn() -> Result<ThreadLocalAllocator, AllocErr>;
 
-            /// Embeddable macros first
-            use allocator_suite::prelude::*;
+#[cfg(unix)]
+impl<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator: Debug> Debug
+    for SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SwitchableAllocator({:?})", self.global_allocator)
+    }
+}
 
-            /// All allocator related imports, users can use anything.
-            use allocator_suite::adaptors::prelude::*;
-            use allocator_suite::allocators::prelude::*;
-            use allocator_suite::allocators::global::prelude::*;
-            use allocator_suite::memory_sources::prelude::*;
+// Local allocators are only ever used by the thread owning them, so only the global allocator is shared between threads.
+#[cfg(unix)]
+unsafe impl<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator> Sync
+    for SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+where
+    GlobalAllocator: Sync,
+{
+}
 
-            /// Std imports
-            use std::num::NonZeroUsize;
-            use std::alloc::{Allocator as AllocRef, AllocError as AllocErr , GlobalAlloc, Layout, System};
-            use std::mem::replace;
+#[cfg(unix)]
+impl<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator> Drop
+    for SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    #[inline(always)]
+    fn drop(&mut self) {
+        let tear_down_when_dropped = self.tear_down_when_dropped.load(Acquire);
+        if tear_down_when_dropped != 0 {
+            // Registered by `create_per_thread_state_key()`, which, unlike `Drop`, has the trait bounds needed.
+            let tear_down_when_dropped: unsafe fn(&Self) =
+                unsafe { transmute(tear_down_when_dropped) };
+            unsafe { tear_down_when_dropped(self) }
+        }
+    }
+}
 
-            /// Effectively this is a field of `SwitchableAllocator` with a different value for each thread.
-            ///
-            /// It is this piece of logic that necessitates this macro definition.
-            #[thread_local]
-            static mut per_thread_state: PerThreadState<
-                $CoroutineLocalAllocator,
-                $ThreadLocalAllocator,
-            > = PerThreadState::empty();
+#[cfg(unix)]
+unsafe impl<
+        CoroutineLocalAllocator: LocalAllocator,
+        ThreadLocalAllocator: LocalAllocator,
+        GlobalAllocator: Allocator + Sync,
+    > GlobalAlloc
+    for SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    crate::global_alloc!();
+}
 
-            #[derive(Debug)]
-            pub(crate) struct SwitchableAllocator {
-                pub(crate) global_allocator: $GlobalAllocator,
-            }
+#[cfg(unix)]
+unsafe impl<
+        CoroutineLocalAllocator: LocalAllocator,
+        ThreadLocalAllocator: LocalAllocator,
+        GlobalAllocator: Allocator + Sync,
+    > AllocRef
+    for SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    crate::alloc_ref!();
+}
 
-            unsafe impl Sync for SwitchableAllocator {}
+#[cfg(unix)]
+impl<
+        CoroutineLocalAllocator: LocalAllocator,
+        ThreadLocalAllocator: LocalAllocator,
+        GlobalAllocator: Allocator + Sync,
+    > Allocator
+    for SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    #[inline(always)]
+    fn allocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocErr> {
+        let current_allocator_in_use = self.save_current_allocator_in_use();
+        let result = self.allocate_with(
+            current_allocator_in_use,
+            non_zero_size,
+            non_zero_power_of_two_alignment,
+        );
+        if likely!(result.is_ok()) || current_allocator_in_use == CurrentAllocatorInUse::Global {
+            return result;
+        }
 
-            unsafe impl GlobalAlloc for SwitchableAllocator {
-                global_alloc!();
-            }
+        self.allocate_on_overflow(
+            current_allocator_in_use,
+            non_zero_size,
+            non_zero_power_of_two_alignment,
+        )
+    }
 
-            unsafe impl AllocRef for SwitchableAllocator {
-                alloc_ref!();
+    #[inline(always)]
+    fn deallocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) {
+        if let Some(coroutine_local_allocator) = self.coroutine_local_allocator() {
+            if likely!(coroutine_local_allocator.contains(current_memory)) {
+                return coroutine_local_allocator.deallocate(
+                    non_zero_size,
+                    non_zero_power_of_two_alignment,
+                    current_memory,
+                );
             }
+        }
 
-            impl Allocator for SwitchableAllocator {
-                #[inline(always)]
-                fn allocate(
-                    &self,
-                    non_zero_size: NonZeroUsize,
-                    non_zero_power_of_two_alignment: NonZeroUsize,
-                ) -> Result<MemoryAddress, AllocErr> {
-                    use allocator_suite::allocators::global::current_allocator_in_use::CurrentAllocatorInUse::*;
+        let per_thread_state = self.per_thread_state();
+        if let Some(thread_local_allocator) = per_thread_state.thread_local_allocator.as_ref() {
+            if likely!(thread_local_allocator.contains(current_memory)) {
+                thread_local_allocator.deallocate(
+                    non_zero_size,
+                    non_zero_power_of_two_alignment,
+                    current_memory,
+                );
+                per_thread_state.thread_local_allocator_live_bytes -= non_zero_size.get();
+                return;
+            }
+        }
 
-                    match self.save_current_allocator_in_use() {
-                        CoroutineLocal => self
-                            .coroutine_local_allocator()
-                            .expect("Should have assigned a coroutine local allocator")
-                            .allocate(non_zero_size, non_zero_power_of_two_alignment),
+        if let Some(registration) = self.local_allocator_registry.find(current_memory) {
+            if unlikely!(self
+                .local_allocator_registry
+                .free_remotely(
+                    registration,
+                    &self.global_allocator,
+                    non_zero_size,
+                    non_zero_power_of_two_alignment,
+                    current_memory,
+                )
+                .is_err())
+            {
+                self.leaked_remote_frees.fetch_add(1, Relaxed);
+            }
+            return;
+        }
 
-                        ThreadLocal => self
-                            .thread_local_allocator()
-                            .expect("Should have assigned a thread local allocator")
-                            .allocate(non_zero_size, non_zero_power_of_two_alignment),
+        self.global_allocator().deallocate(
+            non_zero_size,
+            non_zero_power_of_two_alignment,
+            current_memory,
+        )
+    }
 
-                        Global => self
-                            .global_allocator()
-                            .allocate(non_zero_size, non_zero_power_of_two_alignment),
-                    }
+    #[inline(always)]
+    fn growing_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        if let Some(coroutine_local_allocator) = self.coroutine_local_allocator() {
+            if likely!(coroutine_local_allocator.contains(current_memory)) {
+                let result = coroutine_local_allocator.growing_reallocate(
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                );
+                if likely!(result.is_ok()) {
+                    return result;
                 }
+                return self.grow_on_overflow(
+                    CurrentAllocatorInUse::CoroutineLocal,
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                );
+            }
+        }
 
-                #[inline(always)]
-                fn deallocate(
-                    &self,
-                    non_zero_size: NonZeroUsize,
-                    non_zero_power_of_two_alignment: NonZeroUsize,
-                    current_memory: MemoryAddress,
-                ) {
-                    choose_allocator!(
-                        self,
-                        current_memory,
-                        deallocate,
-                        non_zero_size,
-                        non_zero_power_of_two_alignment,
-                        current_memory
-                    )
+        let per_thread_state = self.per_thread_state();
+        if let Some(thread_local_allocator) = per_thread_state.thread_local_allocator.as_ref() {
+            if likely!(thread_local_allocator.contains(current_memory)) {
+                let result = thread_local_allocator.growing_reallocate(
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                );
+                if likely!(result.is_ok()) {
+                    per_thread_state.thread_local_allocator_live_bytes +=
+                        non_zero_new_size.get() - non_zero_current_size.get();
+                    return result;
                 }
+                return self.grow_on_overflow(
+                    CurrentAllocatorInUse::ThreadLocal,
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                );
+            }
+        }
 
-                #[inline(always)]
-                fn growing_reallocate(
-                    &self,
-                    non_zero_new_size: NonZeroUsize,
-                    non_zero_power_of_two_alignment: NonZeroUsize,
-                    non_zero_current_size: NonZeroUsize,
-                    current_memory: MemoryAddress,
-                ) -> Result<MemoryAddress, AllocErr> {
-                    choose_allocator!(
-                        self,
-                        current_memory,
-                        growing_reallocate,
-                        non_zero_new_size,
-                        non_zero_power_of_two_alignment,
-                        non_zero_current_size,
-                        current_memory
-                    )
-                }
+        if let Some(registration) = self.local_allocator_registry.find(current_memory) {
+            return self.reallocate_remotely(
+                registration,
+                non_zero_new_size,
+                non_zero_power_of_two_alignment,
+                non_zero_current_size,
+                non_zero_current_size,
+                current_memory,
+            );
+        }
+
+        self.global_allocator().growing_reallocate(
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+            non_zero_current_size,
+            current_memory,
+        )
+    }
+
+    #[inline(always)]
+    fn shrinking_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        if let Some(coroutine_local_allocator) = self.coroutine_local_allocator() {
+            if likely!(coroutine_local_allocator.contains(current_memory)) {
+                return coroutine_local_allocator.shrinking_reallocate(
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                );
+            }
+        }
 
-                #[inline(always)]
-                fn shrinking_reallocate(
-                    &self,
-                    non_zero_new_size: NonZeroUsize,
-                    non_zero_power_of_two_alignment: NonZeroUsize,
-                    non_zero_current_size: NonZeroUsize,
-                    current_memory: MemoryAddress,
-                ) -> Result<MemoryAddress, AllocErr> {
-                    choose_allocator!(
-                        self,
-                        current_memory,
-                        growing_reallocate,
-                        non_zero_new_size,
-                        non_zero_power_of_two_alignment,
-                        non_zero_current_size,
-                        current_memory
-                    )
+        let per_thread_state = self.per_thread_state();
+        if let Some(thread_local_allocator) = per_thread_state.thread_local_allocator.as_ref() {
+            if likely!(thread_local_allocator.contains(current_memory)) {
+                let result = thread_local_allocator.shrinking_reallocate(
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                );
+                if likely!(result.is_ok()) {
+                    per_thread_state.thread_local_allocator_live_bytes -=
+                        non_zero_current_size.get() - non_zero_new_size.get()
                 }
+                return result;
             }
+        }
 
-            impl GlobalSwitchableAllocator
-                for SwitchableAllocator
-            {
-                type CoroutineLocalAllocator = $CoroutineLocalAllocator;
+        if let Some(registration) = self.local_allocator_registry.find(current_memory) {
+            return self.reallocate_remotely(
+                registration,
+                non_zero_new_size,
+                non_zero_power_of_two_alignment,
+                non_zero_current_size,
+                non_zero_new_size,
+                current_memory,
+            );
+        }
 
-                type ThreadLocalAllocator = $ThreadLocalAllocator;
+        self.global_allocator().shrinking_reallocate(
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+            non_zero_current_size,
+            current_memory,
+        )
+    }
+}
 
-                type GlobalAllocator = $GlobalAllocator;
+#[cfg(unix)]
+impl<
+        CoroutineLocalAllocator: LocalAllocator,
+        ThreadLocalAllocator: LocalAllocator,
+        GlobalAllocator: Allocator + Sync,
+    > GlobalSwitchableAllocator
+    for SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    type CoroutineLocalAllocator = CoroutineLocalAllocator;
 
-                #[inline(always)]
-                fn replace_coroutine_local_allocator(
-                    &self,
-                    replacement: Option<Self::CoroutineLocalAllocator>,
-                ) -> Option<Self::CoroutineLocalAllocator> {
-                    unsafe { replace(&mut per_thread_state.coroutine_local_allocator, replacement) }
-                }
+    type ThreadLocalAllocator = ThreadLocalAllocator;
 
-                #[inline(always)]
-                fn initialize_thread_local_allocator(
-                    &self,
-                    thread_local_allocator: Self::ThreadLocalAllocator,
-                ) {
-                    debug_assert!(
-                        unsafe { per_thread_state.thread_local_allocator.is_none() },
-                        "Already initialized thread local allocator"
-                    );
-
-                    unsafe {
-                        per_thread_state.thread_local_allocator = Some(thread_local_allocator)
-                    }
-                }
+    type GlobalAllocator = GlobalAllocator;
 
-                #[inline(always)]
-                fn drop_thread_local_allocator(&self) {
-                    debug_assert!(
-                        unsafe { per_thread_state.thread_local_allocator.is_some() },
-                        "Already deinitialized thread local allocator"
-                    );
+    #[inline(always)]
+    fn replace_coroutine_local_allocator(
+        &self,
+        replacement: Option<Self::CoroutineLocalAllocator>,
+    ) -> Option<Self::CoroutineLocalAllocator> {
+        replace(
+            &mut self.per_thread_state().coroutine_local_allocator,
+            replacement,
+        )
+    }
 
-                    unsafe { per_thread_state.thread_local_allocator = None }
-                }
+    #[inline(always)]
+    fn initialize_thread_local_allocator(
+        &self,
+        thread_local_allocator: Self::ThreadLocalAllocator,
+    ) {
+        let per_thread_state = self.per_thread_state();
 
-                #[inline(always)]
-                fn save_current_allocator_in_use(&self) -> CurrentAllocatorInUse {
-                    unsafe { per_thread_state.current_allocator_in_use }
-                }
+        debug_assert!(
+            per_thread_state.thread_local_allocator.is_none(),
+            "Already initialized thread local allocator"
+        );
 
-                #[inline(always)]
-                fn restore_current_allocator_in_use(&self, restore_to: CurrentAllocatorInUse) {
-                    unsafe { per_thread_state.current_allocator_in_use = restore_to }
-                }
+        // If it can not be registered, allocations fall back to the global allocator, as when a factory fails.
+        let _ = self.set_thread_local_allocator(thread_local_allocator);
+    }
+
+    #[inline(always)]
+    fn drop_thread_local_allocator(&self) {
+        debug_assert!(
+            self.per_thread_state().thread_local_allocator.is_some(),
+            "Already deinitialized thread local allocator"
+        );
+
+        self.drain_remote_frees();
+        self.drop_or_retire_thread_local_allocator()
+    }
+
+    #[inline(always)]
+    fn overflow_policy(&self) -> OverflowPolicy {
+        use self::CurrentAllocatorInUse::*;
+
+        match self.overflow_policy.load(Acquire) {
+            Self::OVERFLOW_POLICY_FAIL => OverflowPolicy::Fail,
+            Self::OVERFLOW_POLICY_GLOBAL => OverflowPolicy::Global,
+            Self::OVERFLOW_POLICY_SECONDARY_COROUTINE_LOCAL => {
+                OverflowPolicy::Secondary(CoroutineLocal)
+            }
+            Self::OVERFLOW_POLICY_SECONDARY_THREAD_LOCAL => OverflowPolicy::Secondary(ThreadLocal),
+            Self::OVERFLOW_POLICY_SECONDARY_GLOBAL => OverflowPolicy::Secondary(Global),
+            hook => OverflowPolicy::Hook(unsafe { transmute(hook) }),
+        }
+    }
+
+    #[inline(always)]
+    fn set_overflow_policy(&self, overflow_policy: OverflowPolicy) {
+        use self::CurrentAllocatorInUse::*;
+
+        let overflow_policy = match overflow_policy {
+            OverflowPolicy::Fail => Self::OVERFLOW_POLICY_FAIL,
+            OverflowPolicy::Global => Self::OVERFLOW_POLICY_GLOBAL,
+            OverflowPolicy::Secondary(CoroutineLocal) => {
+                Self::OVERFLOW_POLICY_SECONDARY_COROUTINE_LOCAL
+            }
+            OverflowPolicy::Secondary(ThreadLocal) => Self::OVERFLOW_POLICY_SECONDARY_THREAD_LOCAL,
+            OverflowPolicy::Secondary(Global) => Self::OVERFLOW_POLICY_SECONDARY_GLOBAL,
+            OverflowPolicy::Hook(hook) => hook as usize,
+        };
+        self.overflow_policy.store(overflow_policy, Release)
+    }
+
+    #[inline(always)]
+    fn overflow_statistics(&self) -> OverflowStatistics {
+        OverflowStatistics {
+            coroutine_local_overflows: self.coroutine_local_overflows.load(Relaxed),
+            thread_local_overflows: self.thread_local_overflows.load(Relaxed),
+            unsatisfied_overflows: self.unsatisfied_overflows.load(Relaxed),
+        }
+    }
+
+    #[inline(always)]
+    fn save_current_allocator_in_use(&self) -> CurrentAllocatorInUse {
+        self.per_thread_state().current_allocator_in_use
+    }
+
+    #[inline(always)]
+    fn restore_current_allocator_in_use(&self, restore_to: CurrentAllocatorInUse) {
+        self.per_thread_state().current_allocator_in_use = restore_to
+    }
+
+    #[inline(always)]
+    fn coroutine_local_allocator(&self) -> Option<&Self::CoroutineLocalAllocator> {
+        self.per_thread_state().coroutine_local_allocator.as_ref()
+    }
+
+    #[inline(always)]
+    fn thread_local_allocator(&self) -> Option<&Self::ThreadLocalAllocator> {
+        self.per_thread_state().thread_local_allocator.as_ref()
+    }
+
+    #[inline(always)]
+    fn global_allocator(&self) -> &Self::GlobalAllocator {
+        &self.global_allocator
+    }
+}
+
+#[cfg(unix)]
+impl<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+    SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    const UNINITIALIZED_PER_THREAD_STATE_KEY: usize = 0;
+
+    const NO_HOOK: usize = 0;
+
+    // The overflow policy is published as one value, so a reader never sees the kind of one policy with the argument of another.
+    // Any value other than these is a hook; a function never lies in the first page of address space, which is never mapped.
+
+    const OVERFLOW_POLICY_FAIL: usize = 0;
+
+    const OVERFLOW_POLICY_GLOBAL: usize = 1;
+
+    const OVERFLOW_POLICY_SECONDARY_COROUTINE_LOCAL: usize = 2;
+
+    const OVERFLOW_POLICY_SECONDARY_THREAD_LOCAL: usize = 3;
+
+    const OVERFLOW_POLICY_SECONDARY_GLOBAL: usize = 4;
+
+    /// Creates a new instance.
+    ///
+    /// Can be used in a constant expression, eg to initialize a `static` annotated with `#[global_allocator]`.
+    ///
+    /// # Safety
+    ///
+    /// Once the instance has been used, it must not be moved until it is dropped, as the per-thread state of each thread which used it refers back to it; a `static` is never moved.
+    #[inline(always)]
+    pub const unsafe fn new(global_allocator: GlobalAllocator) -> Self {
+        Self {
+            global_allocator,
+            per_thread_state_key: AtomicUsize::new(Self::UNINITIALIZED_PER_THREAD_STATE_KEY),
+            tear_down_when_dropped: AtomicUsize::new(Self::NO_HOOK),
+            leaked_thread_local_allocator_hook: AtomicUsize::new(Self::NO_HOOK),
+            thread_local_allocator_factory: AtomicUsize::new(Self::NO_HOOK),
+            overflow_policy: AtomicUsize::new(Self::OVERFLOW_POLICY_FAIL),
+            coroutine_local_overflows: AtomicUsize::new(0),
+            thread_local_overflows: AtomicUsize::new(0),
+            unsatisfied_overflows: AtomicUsize::new(0),
+            leaked_remote_frees: AtomicUsize::new(0),
+            local_allocator_registry: LocalAllocatorRegistry::new(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Sets (or, with `None`, clears) the hook called when a thread exits, or drops its thread local allocator, whilst the thread local allocator still has live allocations.
+    ///
+    /// The hook is called on that thread; on exit, with the current allocator in use set to global.
+    #[inline(always)]
+    pub fn set_leaked_thread_local_allocator_hook(
+        &self,
+        hook: Option<LeakedThreadLocalAllocatorHook>,
+    ) {
+        let hook = match hook {
+            None => Self::NO_HOOK,
+            Some(hook) => hook as usize,
+        };
+        self.leaked_thread_local_allocator_hook.store(hook, Release)
+    }
+
+    /// Count of frees, across all threads, of memory from another thread's local allocator which could not be queued for that thread; the memory was too small to hold its own queue entry, and one could not be allocated from the global allocator.
+    ///
+    /// Such memory is leaked.
+    #[inline(always)]
+    pub fn leaked_remote_frees(&self) -> usize {
+        self.leaked_remote_frees.load(Relaxed)
+    }
+
+    #[inline(always)]
+    fn leaked_thread_local_allocator_hook(&self) -> Option<LeakedThreadLocalAllocatorHook> {
+        match self.leaked_thread_local_allocator_hook.load(Acquire) {
+            Self::NO_HOOK => None,
+            hook => Some(unsafe { transmute(hook) }),
+        }
+    }
 
-                #[inline(always)]
-                fn coroutine_local_allocator(&self) -> Option<&Self::CoroutineLocalAllocator> {
-                    unsafe { per_thread_state.coroutine_local_allocator.as_ref() }
+    /// Sets (or, with `None`, clears) the factory used to create a thread local allocator the first time a thread allocates with `CurrentAllocatorInUse::ThreadLocal` without having called `initialize_thread_local_allocator()`.
+    ///
+    /// The factory is called at most once per thread; if it fails, that thread's thread local allocations fall back to the global allocator.
+    #[inline(always)]
+    pub fn set_thread_local_allocator_factory(
+        &self,
+        factory: Option<ThreadLocalAllocatorFactory<ThreadLocalAllocator>>,
+    ) {
+        let factory = match factory {
+            None => Self::NO_HOOK,
+            Some(factory) => factory as usize,
+        };
+        self.thread_local_allocator_factory.store(factory, Release)
+    }
+
+    #[inline(always)]
+    fn thread_local_allocator_factory(
+        &self,
+    ) -> Option<ThreadLocalAllocatorFactory<ThreadLocalAllocator>> {
+        match self.thread_local_allocator_factory.load(Acquire) {
+            Self::NO_HOOK => None,
+            factory => Some(unsafe { transmute(factory) }),
+        }
+    }
+
+    /// The pthread key is created lazily, as `pthread_key_create()` can not be called in a constant expression.
+    ///
+    /// Keys are stored offset by one so that zero, a valid key, can represent 'uninitialized'.
+    #[inline(always)]
+    fn per_thread_state_key_if_created(&self) -> Option<pthread_key_t> {
+        let per_thread_state_key = self.per_thread_state_key.load(Acquire);
+        if likely!(per_thread_state_key != Self::UNINITIALIZED_PER_THREAD_STATE_KEY) {
+            Some((per_thread_state_key - 1) as pthread_key_t)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(unix)]
+impl<
+        CoroutineLocalAllocator: LocalAllocator,
+        ThreadLocalAllocator: LocalAllocator,
+        GlobalAllocator: Allocator + Sync,
+    > SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>
+{
+    /// Effectively this is a field of `SwitchableAllocator` with a different value for each thread.
+    #[inline(always)]
+    fn per_thread_state(
+        &self,
+    ) -> &mut PerThreadState<CoroutineLocalAllocator, ThreadLocalAllocator> {
+        let per_thread_state_key = self.per_thread_state_key();
+
+        let owned_per_thread_state = unsafe { pthread_getspecific(per_thread_state_key) }
+            as *mut OwnedPerThreadState<
+                CoroutineLocalAllocator,
+                ThreadLocalAllocator,
+                GlobalAllocator,
+            >;
+        if likely!(!owned_per_thread_state.is_null()) {
+            return unsafe { &mut (*owned_per_thread_state).per_thread_state };
+        }
+
+        self.create_per_thread_state(per_thread_state_key)
+    }
+
+    #[cold]
+    fn lazily_initialize_thread_local_allocator(&self) {
+        if self
+            .per_thread_state()
+            .thread_local_allocator_factory_failed
+        {
+            return;
+        }
+
+        let factory = match self.thread_local_allocator_factory() {
+            None => return,
+            Some(factory) => factory,
+        };
+
+        let thread_local_allocator = self.callback_with_global_allocator(factory);
+
+        let registered = match thread_local_allocator {
+            Ok(thread_local_allocator) => self.set_thread_local_allocator(thread_local_allocator),
+            Err(allocation_error) => Err(allocation_error),
+        };
+        if unlikely!(registered.is_err()) {
+            self.per_thread_state()
+                .thread_local_allocator_factory_failed = true
+        }
+    }
+
+    #[inline(always)]
+    fn allocate_with(
+        &self,
+        allocator_in_use: CurrentAllocatorInUse,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocErr> {
+        use self::CurrentAllocatorInUse::*;
+
+        match allocator_in_use {
+            CoroutineLocal => self
+                .coroutine_local_allocator()
+                .expect("Should have assigned a coroutine local allocator")
+                .allocate(non_zero_size, non_zero_power_of_two_alignment),
+
+            ThreadLocal => {
+                if unlikely!(self.per_thread_state().thread_local_allocator.is_none()) {
+                    self.lazily_initialize_thread_local_allocator()
                 }
 
-                #[inline(always)]
-                fn thread_local_allocator(&self) -> Option<&Self::ThreadLocalAllocator> {
-                    unsafe { per_thread_state.thread_local_allocator.as_ref() }
+                if unlikely!(self
+                    .per_thread_state()
+                    .thread_local_allocator_has_remote_frees())
+                {
+                    self.drain_remote_frees()
                 }
 
-                #[inline(always)]
-                fn global_allocator(&self) -> &Self::GlobalAllocator {
-                    &self.global_allocator
+                let per_thread_state = self.per_thread_state();
+                match per_thread_state.thread_local_allocator.as_ref() {
+                    Some(thread_local_allocator) => {
+                        let result = thread_local_allocator
+                            .allocate(non_zero_size, non_zero_power_of_two_alignment);
+                        if likely!(result.is_ok()) {
+                            per_thread_state.thread_local_allocator_live_bytes +=
+                                non_zero_size.get()
+                        }
+                        result
+                    }
+
+                    None => self
+                        .global_allocator()
+                        .allocate(non_zero_size, non_zero_power_of_two_alignment),
                 }
             }
+
+            Global => self
+                .global_allocator()
+                .allocate(non_zero_size, non_zero_power_of_two_alignment),
         }
-    };
+    }
+
+    #[cold]
+    fn allocate_on_overflow(
+        &self,
+        overflowed: CurrentAllocatorInUse,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocErr> {
+        use self::CurrentAllocatorInUse::*;
+
+        match overflowed {
+            CoroutineLocal => self.coroutine_local_overflows.fetch_add(1, Relaxed),
+            ThreadLocal => self.thread_local_overflows.fetch_add(1, Relaxed),
+            Global => 0,
+        };
+
+        let secondary = match self.overflow_policy() {
+            OverflowPolicy::Fail => None,
+            OverflowPolicy::Global => Some(Global),
+            OverflowPolicy::Secondary(secondary) => Some(secondary),
+            OverflowPolicy::Hook(hook) => {
+                hook(overflowed, non_zero_size, non_zero_power_of_two_alignment)
+            }
+        };
+
+        let result = match secondary {
+            Some(CoroutineLocal) if self.coroutine_local_allocator().is_none() => Err(AllocErr),
+            Some(secondary) if secondary != overflowed => {
+                self.allocate_with(secondary, non_zero_size, non_zero_power_of_two_alignment)
+            }
+            _ => Err(AllocErr),
+        };
+        if unlikely!(result.is_err()) {
+            self.unsatisfied_overflows.fetch_add(1, Relaxed);
+        }
+        result
+    }
+
+    /// Memory can not be grown within the local allocator it came from, so it is moved to the allocator chosen by the overflow policy.
+    #[cold]
+    fn grow_on_overflow(
+        &self,
+        overflowed: CurrentAllocatorInUse,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        let new_memory = self.allocate_on_overflow(
+            overflowed,
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+        )?;
+        unsafe {
+            new_memory
+                .as_ptr()
+                .copy_from_nonoverlapping(current_memory.as_ptr(), non_zero_current_size.get())
+        };
+        Allocator::deallocate(
+            self,
+            non_zero_current_size,
+            non_zero_power_of_two_alignment,
+            current_memory,
+        );
+        Ok(new_memory)
+    }
+
+    #[inline(always)]
+    fn set_thread_local_allocator(
+        &self,
+        thread_local_allocator: ThreadLocalAllocator,
+    ) -> Result<(), AllocErr> {
+        let registration = self.local_allocator_registry.register(
+            &self.global_allocator,
+            thread_local_allocator.memory_range(),
+        )?;
+
+        let per_thread_state = self.per_thread_state();
+        per_thread_state.thread_local_allocator = Some(thread_local_allocator);
+        per_thread_state.thread_local_allocator_live_bytes = 0;
+        per_thread_state.thread_local_allocator_registration = registration;
+        Ok(())
+    }
+
+    /// Frees memory from the thread local allocator which other threads have freed.
+    #[cold]
+    fn drain_remote_frees(&self) {
+        let per_thread_state = self.per_thread_state();
+        let registration = per_thread_state.thread_local_allocator_registration;
+        if unlikely!(registration.is_null()) {
+            return;
+        }
+
+        let thread_local_allocator = match per_thread_state.thread_local_allocator.as_ref() {
+            None => return,
+            Some(thread_local_allocator) => thread_local_allocator,
+        };
+        let live_bytes = &mut per_thread_state.thread_local_allocator_live_bytes;
+        unsafe { &*registration }.drain_remote_frees(
+            &self.global_allocator,
+            |non_zero_size, non_zero_power_of_two_alignment, current_memory| {
+                thread_local_allocator.deallocate(
+                    non_zero_size,
+                    non_zero_power_of_two_alignment,
+                    current_memory,
+                );
+                *live_bytes -= non_zero_size.get()
+            },
+        )
+    }
+
+    /// Drops the thread local allocator if it has no live allocations, vacating its registration.
+    ///
+    /// Otherwise it is retired until its live allocations have been freed (eg by other threads) and its memory is leaked, as is done when a thread exits.
+    fn drop_or_retire_thread_local_allocator(&self) {
+        let per_thread_state = self.per_thread_state();
+        let thread_local_allocator = match per_thread_state.thread_local_allocator.take() {
+            None => return,
+            Some(thread_local_allocator) => thread_local_allocator,
+        };
+        let registration = unsafe {
+            &*replace(
+                &mut per_thread_state.thread_local_allocator_registration,
+                null(),
+            )
+        };
+        let leaked_bytes = replace(&mut per_thread_state.thread_local_allocator_live_bytes, 0);
+
+        if likely!(leaked_bytes == 0) {
+            self.local_allocator_registry.vacate(registration);
+            drop(thread_local_allocator)
+        } else {
+            let memory_range = thread_local_allocator.memory_range();
+            forget(thread_local_allocator);
+            self.local_allocator_registry.retire_until_freed(
+                registration,
+                &self.global_allocator,
+                leaked_bytes.non_zero(),
+            );
+
+            if let Some(hook) = self.leaked_thread_local_allocator_hook() {
+                hook(leaked_bytes, memory_range)
+            }
+        }
+    }
+
+    /// Memory from another thread's local allocator can not be reallocated in place, so it is moved out of it and freed remotely.
+    #[cold]
+    fn reallocate_remotely(
+        &self,
+        registration: &LocalAllocatorRegistration,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        non_zero_copy_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        let new_memory =
+            Allocator::allocate(self, non_zero_new_size, non_zero_power_of_two_alignment)?;
+        unsafe {
+            new_memory
+                .as_ptr()
+                .copy_from_nonoverlapping(current_memory.as_ptr(), non_zero_copy_size.get())
+        };
+        if let Err(allocation_error) = self.local_allocator_registry.free_remotely(
+            registration,
+            &self.global_allocator,
+            non_zero_current_size,
+            non_zero_power_of_two_alignment,
+            current_memory,
+        ) {
+            Allocator::deallocate(
+                self,
+                non_zero_new_size,
+                non_zero_power_of_two_alignment,
+                new_memory,
+            );
+            return Err(allocation_error);
+        }
+        Ok(new_memory)
+    }
+
+    #[cold]
+    fn create_per_thread_state(
+        &self,
+        per_thread_state_key: pthread_key_t,
+    ) -> &mut PerThreadState<CoroutineLocalAllocator, ThreadLocalAllocator> {
+        let memory = self
+            .global_allocator
+            .allocate(
+                Self::owned_per_thread_state_size(),
+                Self::owned_per_thread_state_alignment(),
+            )
+            .expect("Could not allocate per-thread state from the global allocator");
+
+        let owned_per_thread_state = memory.as_ptr()
+            as *mut OwnedPerThreadState<
+                CoroutineLocalAllocator,
+                ThreadLocalAllocator,
+                GlobalAllocator,
+            >;
+        unsafe {
+            owned_per_thread_state.write(OwnedPerThreadState {
+                switchable_allocator: self,
+                per_thread_state: PerThreadState::empty(),
+            });
+            pthread_setspecific(
+                per_thread_state_key,
+                owned_per_thread_state as *const c_void,
+            );
+            &mut (*owned_per_thread_state).per_thread_state
+        }
+    }
+
+    #[inline(always)]
+    fn per_thread_state_key(&self) -> pthread_key_t {
+        match self.per_thread_state_key_if_created() {
+            Some(per_thread_state_key) => per_thread_state_key,
+            None => self.create_per_thread_state_key(),
+        }
+    }
+
+    #[cold]
+    fn create_per_thread_state_key(&self) -> pthread_key_t {
+        let tear_down_when_dropped: unsafe fn(&Self) = Self::tear_down_when_dropped;
+        self.tear_down_when_dropped
+            .store(tear_down_when_dropped as usize, Release);
+
+        let mut per_thread_state_key: pthread_key_t = 0;
+        let result = unsafe {
+            pthread_key_create(
+                &mut per_thread_state_key,
+                Some(Self::tear_down_per_thread_state_on_thread_exit),
+            )
+        };
+        assert_eq!(
+            result, 0,
+            "Could not create a pthread key for per-thread state"
+        );
+
+        match self.per_thread_state_key.compare_exchange(
+            Self::UNINITIALIZED_PER_THREAD_STATE_KEY,
+            (per_thread_state_key as usize) + 1,
+            AcqRel,
+            Acquire,
+        ) {
+            Ok(_) => per_thread_state_key,
+
+            Err(created_by_another_thread) => {
+                unsafe { pthread_key_delete(per_thread_state_key) };
+                (created_by_another_thread - 1) as pthread_key_t
+            }
+        }
+    }
+
+    /// The destructor of the pthread key; pthreads clears the key's value before calling it.
+    unsafe extern "C" fn tear_down_per_thread_state_on_thread_exit(
+        owned_per_thread_state: *mut c_void,
+    ) {
+        let owned_per_thread_state = owned_per_thread_state
+            as *mut OwnedPerThreadState<
+                CoroutineLocalAllocator,
+                ThreadLocalAllocator,
+                GlobalAllocator,
+            >;
+        let switchable_allocator = &*(*owned_per_thread_state).switchable_allocator;
+        switchable_allocator.tear_down_per_thread_state(owned_per_thread_state)
+    }
+
+    unsafe fn tear_down_when_dropped(&self) {
+        if let Some(per_thread_state_key) = self.per_thread_state_key_if_created() {
+            let owned_per_thread_state = pthread_getspecific(per_thread_state_key)
+                as *mut OwnedPerThreadState<
+                    CoroutineLocalAllocator,
+                    ThreadLocalAllocator,
+                    GlobalAllocator,
+                >;
+            if !owned_per_thread_state.is_null() {
+                self.tear_down_per_thread_state(owned_per_thread_state)
+            }
+            pthread_key_delete(per_thread_state_key);
+        }
+
+        self.local_allocator_registry.free(&self.global_allocator)
+    }
+
+    unsafe fn tear_down_per_thread_state(
+        &self,
+        owned_per_thread_state: *mut OwnedPerThreadState<
+            CoroutineLocalAllocator,
+            ThreadLocalAllocator,
+            GlobalAllocator,
+        >,
+    ) {
+        let per_thread_state_key = self.per_thread_state_key();
+
+        // Keep the per-thread state visible whilst allocators are dropped, so anything they free is still routed correctly.
+        pthread_setspecific(
+            per_thread_state_key,
+            owned_per_thread_state as *const c_void,
+        );
+
+        let per_thread_state = &mut (*owned_per_thread_state).per_thread_state;
+        per_thread_state.current_allocator_in_use = CurrentAllocatorInUse::Global;
+
+        if let Some(coroutine_local_allocator) = per_thread_state.coroutine_local_allocator.take() {
+            if let Ok(registration) = self.local_allocator_registry.register(
+                &self.global_allocator,
+                coroutine_local_allocator.memory_range(),
+            ) {
+                registration.retire()
+            }
+            forget(coroutine_local_allocator)
+        }
+
+        self.drain_remote_frees();
+        self.drop_or_retire_thread_local_allocator();
+
+        pthread_setspecific(per_thread_state_key, null());
+        self.global_allocator.deallocate(
+            Self::owned_per_thread_state_size(),
+            Self::owned_per_thread_state_alignment(),
+            NonNull::new_unchecked(owned_per_thread_state as *mut u8),
+        )
+    }
+
+    #[inline(always)]
+    fn owned_per_thread_state_size() -> NonZeroUsize {
+        size_of::<OwnedPerThreadState<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>>()
+            .non_zero()
+    }
+
+    #[inline(always)]
+    fn owned_per_thread_state_alignment() -> NonZeroUsize {
+        align_of::<
+            OwnedPerThreadState<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>,
+        >()
+        .non_zero()
+    }
 }
 
-#[doc(hidden)]
+/// The value of the pthread key; refers back to the owning instance so that the key's destructor can tear it down.
+#[cfg(unix)]
+struct OwnedPerThreadState<
+    CoroutineLocalAllocator: LocalAllocator,
+    ThreadLocalAllocator: LocalAllocator,
+    GlobalAllocator,
+> {
+    switchable_allocator:
+        *const SwitchableAllocator<CoroutineLocalAllocator, ThreadLocalAllocator, GlobalAllocator>,
+    per_thread_state: PerThreadState<CoroutineLocalAllocator, ThreadLocalAllocator>,
+}
+
+/// Creates a new global, switchable allocator inside a module `$mod_name`.
+///
+/// Parameters:-
+///
+/// * `$mod_name`: A `pub(crate)` module.
+/// * `$CoroutineLocalAllocator`: the type of the coroutine local allocator. Must implement `LocalAllocator`.
+/// * `$ThreadLocalAllocator`: the type of the thread local allocator. Must implement `LocalAllocator`.
+/// * `$GlobalAllocator`: the type of the thread local allocator. Must implement `Allocator`; a common usage is `GlobalAllocToAllocatorAdaptor<System>`.
+/// * `global_allocator_instance`: a constant expression for instantiating the global allocator. A common usage is `GlobalAllocToAllocatorAdaptor(System)`.
+///
+/// To access the switchable allocator, call `$mod_name::global_thread_and_coroutine_switchable_allocator()`; this returns an object reference that implements the trait `GlobalSwitchableAllocator`.
+///
+/// This is a thin wrapper around `SwitchableAllocator`, which can be used directly to create instances which are not the `#[global_allocator]`.
+///
+/// # Example
+///
+/// ```Rust
+///
+/// switchable_allocator!(application_allocator, BumpAllocator<ArenaMemorySource<MemoryMapSource>>, MultipleBinarySearchTreeAllocator<MemoryMapSource>, GlobalAllocToAllocatorAdaptor<System>, GlobalAllocToAllocatorAdaptor(System));
+///
+/// ```
 #[macro_export]
-macro_rules! choose_allocator
-{
-	($self: ident, $current_memory: ident, $callback: ident, $($argument: ident),*) =>
-	{
-		{
-			if let Some(coroutine_local_allocator) = $self.coroutine_local_allocator()
-			{
-				if likely!(coroutine_local_allocator.contains($current_memory))
-				{
-					return coroutine_local_allocator.$callback($($argument, )*)
-				}
-			}
+macro_rules! switchable_allocator {
+    ($mod_name: ident, $CoroutineLocalAllocator: ty, $ThreadLocalAllocator: ty, $GlobalAllocator: ty, $global_allocator_instance: expr) => {
+        #[global_allocator]
+        pub(crate) static GLOBAL: $mod_name::SwitchableAllocator = {
+            let global_allocator = $global_allocator_instance;
+            unsafe { $mod_name::SwitchableAllocator::new(global_allocator) }
+        };
 
-			if let Some(thread_local_allocator) = $self.thread_local_allocator()
-			{
-				if likely!(thread_local_allocator.contains($current_memory))
-				{
-					return thread_local_allocator.$callback($($argument, )*)
-				}
-			}
+        pub(crate) mod $mod_name {
 
-			$self.global_allocator().$callback($($argument, )*)
-		}
-	}
+            /// Embeddable macros first
+            #[allow(unused_imports)]
+            use allocator_suite::prelude::*;
+
+            /// All allocator related imports, users can use anything.
+            #[allow(unused_imports)]
+            use allocator_suite::adaptors::prelude::*;
+            #[allow(unused_imports)]
+            use allocator_suite::allocators::global::prelude::*;
+            #[allow(unused_imports)]
+            use allocator_suite::allocators::prelude::*;
+            #[allow(unused_imports)]
+            use allocator_suite::memory_sources::prelude::*;
+
+            /// Std imports
+            #[allow(unused_imports)]
+            use std::alloc::System;
+
+            pub(crate) type SwitchableAllocator =
+                allocator_suite::allocators::global::switchable_allocator::SwitchableAllocator<
+                    $CoroutineLocalAllocator,
+                    $ThreadLocalAllocator,
+                    $GlobalAllocator,
+                >;
+
+            /// The global, switchable allocator.
+            #[allow(dead_code)]
+            #[inline(always)]
+            pub(crate) fn global_thread_and_coroutine_switchable_allocator(
+            ) -> &'static SwitchableAllocator {
+                &super::GLOBAL
+            }
+        }
+    };
 }
@@ -0,0 +1,120 @@
+use crate::allocators::global::global_switchable_allocator::GlobalSwitchableAllocator;
+use std::future::Future;
+use std::mem::{replace, ManuallyDrop};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A future adaptor which gives a future (eg an async task) its own coroutine local allocator.
+///
+/// Before each `poll()`, the coroutine local allocator is swapped in and the current allocator in use switched to coroutine local; after each `poll()`, both are swapped back out, even if `poll()` panics.
+/// This works under any executor.
+///
+/// The coroutine local allocator is dropped when the future finishes, so the future's output must not own memory allocated from it (allocate the output with `use_global()` in force, say).
+/// If the adaptor is dropped before the future finishes, the coroutine local allocator is swapped in whilst the future is dropped, then dropped itself.
+pub struct WithAllocator<'a, GSA: GlobalSwitchableAllocator, F: Future> {
+    switchable_allocator: &'a GSA,
+    coroutine_local_allocator: Option<GSA::CoroutineLocalAllocator>,
+    future: ManuallyDrop<F>,
+    finished: bool,
+}
+
+impl<'a, GSA: GlobalSwitchableAllocator, F: Future> Drop for WithAllocator<'a, GSA, F> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if !self.finished {
+            let future = &mut self.future;
+            Self::with_coroutine_local_allocator_swapped_in(
+                self.switchable_allocator,
+                &mut self.coroutine_local_allocator,
+                || unsafe { ManuallyDrop::drop(future) },
+            )
+        }
+    }
+}
+
+impl<'a, GSA: GlobalSwitchableAllocator, F: Future> Future for WithAllocator<'a, GSA, F> {
+    type Output = F::Output;
+
+    #[inline(always)]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // The future is never moved out of `self`, so it remains pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(!this.finished, "Polled after the future finished");
+
+        let future = &mut this.future;
+        let finished = &mut this.finished;
+        let poll = Self::with_coroutine_local_allocator_swapped_in(
+            this.switchable_allocator,
+            &mut this.coroutine_local_allocator,
+            || {
+                let poll = unsafe { Pin::new_unchecked(&mut **future) }.poll(cx);
+                if poll.is_ready() {
+                    *finished = true;
+                    unsafe { ManuallyDrop::drop(future) }
+                }
+                poll
+            },
+        );
+
+        if poll.is_ready() {
+            this.coroutine_local_allocator = None
+        }
+        poll
+    }
+}
+
+impl<'a, GSA: GlobalSwitchableAllocator, F: Future> WithAllocator<'a, GSA, F> {
+    /// Creates a new instance which will use `coroutine_local_allocator` whilst `future` is polled.
+    #[inline(always)]
+    pub fn new(
+        switchable_allocator: &'a GSA,
+        coroutine_local_allocator: GSA::CoroutineLocalAllocator,
+        future: F,
+    ) -> Self {
+        Self {
+            switchable_allocator,
+            coroutine_local_allocator: Some(coroutine_local_allocator),
+            future: ManuallyDrop::new(future),
+            finished: false,
+        }
+    }
+
+    #[inline(always)]
+    fn with_coroutine_local_allocator_swapped_in<R>(
+        switchable_allocator: &'a GSA,
+        coroutine_local_allocator: &mut Option<GSA::CoroutineLocalAllocator>,
+        callback: impl FnOnce() -> R,
+    ) -> R {
+        let previous = switchable_allocator
+            .replace_coroutine_local_allocator(replace(coroutine_local_allocator, None));
+        let swap_back = SwapBack {
+            switchable_allocator,
+            coroutine_local_allocator,
+            previous,
+        };
+
+        let result = {
+            let _coroutine_local = switchable_allocator.use_coroutine_local();
+            callback()
+        };
+
+        drop(swap_back);
+        result
+    }
+}
+
+/// Swaps the coroutine local allocator back out when dropped, including during unwinding.
+struct SwapBack<'a, 'b, GSA: GlobalSwitchableAllocator> {
+    switchable_allocator: &'a GSA,
+    coroutine_local_allocator: &'b mut Option<GSA::CoroutineLocalAllocator>,
+    previous: Option<GSA::CoroutineLocalAllocator>,
+}
+
+impl<'a, 'b, GSA: GlobalSwitchableAllocator> Drop for SwapBack<'a, 'b, GSA> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        *self.coroutine_local_allocator = self
+            .switchable_allocator
+            .replace_coroutine_local_allocator(self.previous.take())
+    }
+}
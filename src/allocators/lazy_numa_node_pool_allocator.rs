@@ -0,0 +1,243 @@
+use crate::allocators::allocator::Allocator;
+use crate::allocators::global::local_allocator::LocalAllocator;
+use crate::allocators::numa_node_pool_allocator::{CurrentNumaNode, NumaNodePoolAllocator};
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::mmap::prelude::*;
+use core::ptr::NonNull;
+use libc::pthread_self;
+use std::alloc::{AllocError as AllocErr, Allocator as AllocRef, GlobalAlloc, Layout};
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::hint::spin_loop;
+use std::mem::MaybeUninit;
+use std::num::NonZeroUsize;
+use std::ptr::drop_in_place;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+/// Finds the NUMA nodes to create pools for, eg the online ones using `NumaNodeBitSet::online()`.
+///
+/// Must not use the global allocator.
+pub type NumaNodes = fn() -> Result<NumaNodeBitSet, AllocErr>;
+
+/// Creates the pool for one NUMA node from a memory map source bound to it.
+///
+/// Must not use the global allocator.
+pub type NewPool<A> = fn(MemoryMapSource) -> Result<A, AllocErr>;
+
+/// A `NumaNodePoolAllocator` created the first time it is used.
+///
+/// Unlike a `NumaNodePoolAllocator`, it can be created in a constant expression, so can be a `#[global_allocator]` static in place of `NumaAllocator`.
+///
+/// If creation fails, all allocations fail.
+/// Whilst one thread is creating it, other threads wait; allocations made by the creating thread itself (eg by a `NewPool` which uses the global allocator) fail rather than deadlock.
+pub struct LazyNumaNodePoolAllocator<A: LocalAllocator> {
+    state: AtomicUsize,
+    creating_thread: AtomicUsize,
+    numa_node_pool_allocator: UnsafeCell<MaybeUninit<NumaNodePoolAllocator<A>>>,
+    numa_nodes: NumaNodes,
+    new_pool: NewPool<A>,
+    current_numa_node: CurrentNumaNode,
+}
+
+impl<A: LocalAllocator> Debug for LazyNumaNodePoolAllocator<A> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.created() {
+            None => write!(f, "LazyNumaNodePoolAllocator"),
+            Some(numa_node_pool_allocator) => numa_node_pool_allocator.fmt(f),
+        }
+    }
+}
+
+// The `NumaNodePoolAllocator` is only written once, by the creating thread, before it is published.
+unsafe impl<A: LocalAllocator + Send> Send for LazyNumaNodePoolAllocator<A> {}
+
+unsafe impl<A: LocalAllocator + Send> Sync for LazyNumaNodePoolAllocator<A> {}
+
+impl<A: LocalAllocator> Drop for LazyNumaNodePoolAllocator<A> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        if self.created().is_some() {
+            unsafe { drop_in_place((*self.numa_node_pool_allocator.get()).as_mut_ptr()) }
+        }
+    }
+}
+
+unsafe impl<A: LocalAllocator> GlobalAlloc for LazyNumaNodePoolAllocator<A> {
+    crate::global_alloc!();
+}
+
+unsafe impl<A: LocalAllocator> AllocRef for LazyNumaNodePoolAllocator<A> {
+    crate::alloc_ref!();
+}
+
+impl<A: LocalAllocator> Allocator for LazyNumaNodePoolAllocator<A> {
+    #[inline(always)]
+    fn allocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocErr> {
+        Allocator::allocate(
+            self.numa_node_pool_allocator().ok_or(AllocErr)?,
+            non_zero_size,
+            non_zero_power_of_two_alignment,
+        )
+    }
+
+    #[inline(always)]
+    fn deallocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) {
+        // Memory can only have been allocated once created.
+        if let Some(numa_node_pool_allocator) = self.created() {
+            Allocator::deallocate(
+                numa_node_pool_allocator,
+                non_zero_size,
+                non_zero_power_of_two_alignment,
+                current_memory,
+            )
+        }
+    }
+
+    #[inline(always)]
+    fn growing_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        Allocator::growing_reallocate(
+            self.created().ok_or(AllocErr)?,
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+            non_zero_current_size,
+            current_memory,
+        )
+    }
+
+    #[inline(always)]
+    fn shrinking_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        Allocator::shrinking_reallocate(
+            self.created().ok_or(AllocErr)?,
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+            non_zero_current_size,
+            current_memory,
+        )
+    }
+}
+
+impl<A: LocalAllocator> LazyNumaNodePoolAllocator<A> {
+    const UNCREATED: usize = 0;
+
+    const CREATING: usize = 1;
+
+    const CREATED: usize = 2;
+
+    const FAILED: usize = 3;
+
+    const NO_THREAD: usize = 0;
+
+    /// Creates a new instance; the `NumaNodePoolAllocator` is created, as if by `NumaNodePoolAllocator::new()`, the first time it is used.
+    ///
+    /// `current_numa_node` is usually `NumaNodePoolAllocator::<A>::current_numa_node_using_getcpu`.
+    #[inline(always)]
+    pub const fn new(
+        numa_nodes: NumaNodes,
+        new_pool: NewPool<A>,
+        current_numa_node: CurrentNumaNode,
+    ) -> Self {
+        Self {
+            state: AtomicUsize::new(Self::UNCREATED),
+            creating_thread: AtomicUsize::new(Self::NO_THREAD),
+            numa_node_pool_allocator: UnsafeCell::new(MaybeUninit::uninit()),
+            numa_nodes,
+            new_pool,
+            current_numa_node,
+        }
+    }
+
+    /// The `NumaNodePoolAllocator`, created if this is the first time it is used.
+    ///
+    /// `None` if creation failed, or if called by the creating thread whilst creating.
+    #[inline(always)]
+    pub fn numa_node_pool_allocator(&self) -> Option<&NumaNodePoolAllocator<A>> {
+        match self.created() {
+            Some(numa_node_pool_allocator) => Some(numa_node_pool_allocator),
+            None => self.create(),
+        }
+    }
+
+    #[inline(always)]
+    fn created(&self) -> Option<&NumaNodePoolAllocator<A>> {
+        if likely!(self.state.load(Acquire) == Self::CREATED) {
+            Some(unsafe { &*(*self.numa_node_pool_allocator.get()).as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    #[cold]
+    fn create(&self) -> Option<&NumaNodePoolAllocator<A>> {
+        let current_thread = unsafe { pthread_self() } as usize;
+        loop {
+            match self
+                .state
+                .compare_exchange(Self::UNCREATED, Self::CREATING, Acquire, Acquire)
+            {
+                Ok(_) => {
+                    self.creating_thread.store(current_thread, Relaxed);
+                    let numa_node_pool_allocator = (self.numa_nodes)().and_then(|numa_nodes| {
+                        NumaNodePoolAllocator::new(
+                            &numa_nodes,
+                            self.new_pool,
+                            self.current_numa_node,
+                        )
+                    });
+                    self.creating_thread.store(Self::NO_THREAD, Relaxed);
+
+                    return match numa_node_pool_allocator {
+                        Ok(numa_node_pool_allocator) => {
+                            unsafe {
+                                (*self.numa_node_pool_allocator.get())
+                                    .as_mut_ptr()
+                                    .write(numa_node_pool_allocator)
+                            };
+                            self.state.store(Self::CREATED, Release);
+                            self.created()
+                        }
+
+                        Err(_) => {
+                            self.state.store(Self::FAILED, Release);
+                            None
+                        }
+                    };
+                }
+
+                Err(Self::CREATING) => {
+                    if unlikely!(self.creating_thread.load(Relaxed) == current_thread) {
+                        return None;
+                    }
+                    spin_loop()
+                }
+
+                Err(Self::CREATED) => return self.created(),
+
+                Err(_) => return None,
+            }
+        }
+    }
+}
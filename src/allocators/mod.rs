@@ -10,8 +10,13 @@ pub mod global;
 pub mod allocator;
 pub mod bump_allocator;
 pub mod context_allocator;
+pub mod dyn_allocator;
+#[cfg(unix)]
+pub mod lazy_numa_node_pool_allocator;
 pub mod memory_map_allocator;
 pub mod multiple_binary_search_tree_allocator;
+#[cfg(unix)]
+pub mod numa_node_pool_allocator;
 
 #[macro_use]
 pub mod prelude {
@@ -22,6 +27,11 @@ pub mod prelude {
     pub use super::allocator::*;
     pub use super::bump_allocator::*;
     pub use super::context_allocator::*;
+    pub use super::dyn_allocator::*;
+    #[cfg(unix)]
+    pub use super::lazy_numa_node_pool_allocator::*;
     pub use super::memory_map_allocator::*;
     pub use super::multiple_binary_search_tree_allocator::*;
+    #[cfg(unix)]
+    pub use super::numa_node_pool_allocator::*;
 }
@@ -2,6 +2,7 @@ use crate::allocators::global::local_allocator::LocalAllocator;
 use crate::memory_address::MemoryAddress;
 use crate::memory_sources::memory_source::MemorySource;
 use std::alloc::AllocError;
+use std::cell::Cell;
 use std::fmt;
 use std::fmt::Formatter;
 use std::num::NonZeroUsize;
@@ -11,8 +12,10 @@ use crate::extensions::non_null_u8_ext::NonNullU8Ext;
 use crate::extensions::non_zero_usize_ext::NonZeroUsizeExt;
 use crate::extensions::usize_ext::UsizeExt;
 use std::fmt::Debug;
+use std::mem::size_of;
 
 use crate::allocators::global::memory_range::MemoryRange;
+use crate::allocators::binary_search_trees::red_black_tree::node::Node;
 use crate::allocators::binary_search_trees::red_black_tree::node_pointer::NodePointer;
 use crate::allocators::binary_search_trees::binary_search_tree_with_cached_knowledge_of_first_child::BinarySearchTreeWithCachedKnowledgeOfFirstChild;
 use crate::allocators::binary_search_trees::binary_search_trees_with_cached_knowledge_of_first_child::BinarySearchTreesWithCachedKnowledgeOfFirstChild;
@@ -30,6 +33,7 @@ use crate::allocators::allocator::Allocator;
 /// Whilst is could be modified to make such allocations, its lack of book-keeping prevents them being deallocated.
 ///
 /// This allocator NEVER grows or shrinks its memory region.
+/// It can, however, decommit the pages of large free blocks with `trim()`, either on demand or automatically once a threshold of bytes has been deallocated (see `with_trim_threshold()`).
 ///
 /// This allocator is not thread-safe.
 pub struct MultipleBinarySearchTreeAllocator<MS: MemorySource> {
@@ -37,8 +41,14 @@ pub struct MultipleBinarySearchTreeAllocator<MS: MemorySource> {
     memory_source: MS,
     allocations_start_from: MemoryAddress,
     memory_source_size: NonZeroUsize,
+
+    trim_threshold: Option<NonZeroUsize>,
+    bytes_deallocated_since_trim: Cell<usize>,
 }
 
+// The trees only link free blocks within the memory exclusively owned by this allocator, so it can be moved to another thread.
+unsafe impl<MS: MemorySource + Send> Send for MultipleBinarySearchTreeAllocator<MS> {}
+
 impl<MS: MemorySource> Drop for MultipleBinarySearchTreeAllocator<MS> {
     #[inline(always)]
     fn drop(&mut self) {
@@ -198,17 +208,17 @@ impl<MS: MemorySource> Allocator for MultipleBinarySearchTreeAllocator<MS> {
         _non_zero_power_of_two_alignment: NonZeroUsize,
         current_memory: MemoryAddress,
     ) {
-        let block_size = Self::block_size(non_zero_size);
-
-        let binary_search_tree_index =
-            BinarySearchTreesWithCachedKnowledgeOfFirstChild::binary_search_tree_index(block_size);
-
-        // TODO: Optimization - can we use lower bound / upper bound rather than doing an insert in order to find blocks to coalesce?
-        let binary_search_tree = self.binary_search_tree_for(binary_search_tree_index);
-        let has_blocks = binary_search_tree.has_blocks();
-        let inserted_node_pointer = binary_search_tree.insert_memory_address(current_memory);
-        if likely!(has_blocks) {
-            self.coalesce(inserted_node_pointer, block_size, binary_search_tree_index);
+        self.free_block(non_zero_size, current_memory);
+
+        if let Some(trim_threshold) = self.trim_threshold {
+            let bytes_deallocated_since_trim =
+                self.bytes_deallocated_since_trim.get() + non_zero_size.get();
+            if unlikely!(bytes_deallocated_since_trim >= trim_threshold.get()) {
+                self.trim();
+            } else {
+                self.bytes_deallocated_since_trim
+                    .set(bytes_deallocated_since_trim);
+            }
         }
     }
 
@@ -332,6 +342,9 @@ impl<MS: MemorySource> MultipleBinarySearchTreeAllocator<MS> {
             memory_source,
             allocations_start_from,
             memory_source_size,
+
+            trim_threshold: None,
+            bytes_deallocated_since_trim: Cell::new(0),
         };
 
         let mut size = memory_source_size.get();
@@ -367,6 +380,58 @@ impl<MS: MemorySource> MultipleBinarySearchTreeAllocator<MS> {
         Ok(this)
     }
 
+    /// Automatically `trim()` once at least `trim_threshold` bytes have been deallocated since the last trim.
+    #[inline(always)]
+    pub fn with_trim_threshold(mut self, trim_threshold: NonZeroUsize) -> Self {
+        self.trim_threshold = Some(trim_threshold);
+        self
+    }
+
+    /// Decommits the pages of free blocks so that resident memory falls after a burst of allocations is freed.
+    ///
+    /// The start of each free block holds its binary search tree node, so only the page-aligned interior after the node is decommitted; in practice, only blocks larger than a page are affected.
+    /// Decommitted blocks remain in the free lists and are reused as normal.
+    ///
+    /// Returns the number of bytes decommitted.
+    pub fn trim(&self) -> usize {
+        self.bytes_deallocated_since_trim.set(0);
+
+        let mut decommitted = 0;
+        for binary_search_tree_index in
+            0..BinarySearchTreesWithCachedKnowledgeOfFirstChild::NUMBER_OF_BINARY_SEARCH_TREES
+        {
+            let block_size = BinarySearchTreesWithCachedKnowledgeOfFirstChild::binary_search_tree_index_to_block_size(binary_search_tree_index);
+            if likely!(block_size == size_of::<Node>()) {
+                continue;
+            }
+            let interior_size = (block_size - size_of::<Node>()).non_zero();
+
+            let binary_search_tree = self.binary_search_tree_for(binary_search_tree_index);
+            for free_block in binary_search_tree.double_ended_iterate() {
+                decommitted += self
+                    .memory_source
+                    .decommit(interior_size, free_block.add(size_of::<Node>()));
+            }
+        }
+        decommitted
+    }
+
+    #[inline(always)]
+    fn free_block(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        let block_size = Self::block_size(non_zero_size);
+
+        let binary_search_tree_index =
+            BinarySearchTreesWithCachedKnowledgeOfFirstChild::binary_search_tree_index(block_size);
+
+        // TODO: Optimization - can we use lower bound / upper bound rather than doing an insert in order to find blocks to coalesce?
+        let binary_search_tree = self.binary_search_tree_for(binary_search_tree_index);
+        let has_blocks = binary_search_tree.has_blocks();
+        let inserted_node_pointer = binary_search_tree.insert_memory_address(current_memory);
+        if likely!(has_blocks) {
+            self.coalesce(inserted_node_pointer, block_size, binary_search_tree_index);
+        }
+    }
+
     #[inline(always)]
     fn split_up_block(&self, mut from: MemoryAddress, to: MemoryAddress) {
         let mut difference = to.difference(from);
@@ -376,11 +441,7 @@ impl<MS: MemorySource> MultipleBinarySearchTreeAllocator<MS> {
                     difference,
                 );
 
-            self.deallocate(
-                smallest_power_of_two_difference,
-                smallest_power_of_two_difference,
-                from,
-            );
+            self.free_block(smallest_power_of_two_difference, from);
 
             from.add_assign_non_zero(smallest_power_of_two_difference);
             difference -= smallest_power_of_two_difference.get();
@@ -408,7 +469,7 @@ impl<MS: MemorySource> MultipleBinarySearchTreeAllocator<MS> {
             return;
         }
 
-        let first_block_memory_address = {
+        let (first_block_memory_address, last_block_memory_address) = {
             let binary_search_tree = self.binary_search_tree_for(binary_search_tree_index);
 
             let (first_block_memory_address, last_block_memory_address) = binary_search_tree
@@ -426,11 +487,13 @@ impl<MS: MemorySource> MultipleBinarySearchTreeAllocator<MS> {
                 block_size,
             );
 
-            first_block_memory_address
+            (first_block_memory_address, last_block_memory_address)
         };
 
         // TODO: Do we actually need a loop and all the stuff above? Would we ever have more than 3 potentially coalescing blocks at once?
-        let mut difference = difference;
+        // The coalesced blocks run up to the end of the last block, not its start.
+        let mut difference =
+            last_block_memory_address.difference(first_block_memory_address) + block_size.get();
         let mut from = first_block_memory_address;
         while {
             let smallest_power_of_two_difference =
@@ -442,11 +505,7 @@ impl<MS: MemorySource> MultipleBinarySearchTreeAllocator<MS> {
                 "difference should never be block_size"
             );
 
-            self.deallocate(
-                smallest_power_of_two_difference,
-                smallest_power_of_two_difference,
-                from,
-            );
+            self.free_block(smallest_power_of_two_difference, from);
 
             from.add_assign_non_zero(smallest_power_of_two_difference);
             difference -= smallest_power_of_two_difference.get();
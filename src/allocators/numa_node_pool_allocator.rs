@@ -0,0 +1,356 @@
+use crate::allocators::allocator::Allocator;
+use crate::allocators::global::local_allocator::LocalAllocator;
+use crate::allocators::global::memory_range::MemoryRange;
+use crate::allocators::global::page_map::{PageMap, PageOwner};
+use crate::extensions::prelude::*;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use crate::memory_sources::mmap::prelude::*;
+use crate::spin_lock::SpinLock;
+use core::ptr::NonNull;
+use std::alloc::{AllocError as AllocErr, Allocator as AllocRef, GlobalAlloc, Layout};
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::mem::size_of;
+use std::num::NonZeroUsize;
+use std::ptr::drop_in_place;
+use std::slice::from_raw_parts;
+
+/// Finds the NUMA node of the CPU the calling thread is running on.
+///
+/// Replace with a fake to test on a machine with only one NUMA node.
+pub type CurrentNumaNode = fn() -> u16;
+
+/// An allocator with one pool (sub-allocator) per NUMA node, each fed by a `MemoryMapSource` bound (`NumaAllocationPolicy::Bind`) to its node.
+///
+/// Allocations are made from the pool of the NUMA node the calling thread is running on, or, if that pool is exhausted, from the other pools in turn.
+/// Likewise, memory which can not grow within its own pool is moved to another.
+/// Deallocations and reallocations are routed back to the owning pool by address, using a `PageMap`; memory not allocated by any pool is ignored.
+///
+/// Each pool is protected by a spin lock, so this allocator can be shared between threads and used as a `GlobalAlloc`.
+/// It obtains the memory it needs for itself with `mmap()`, so never uses the global allocator; however, as `new()` is not `const`, it can not itself be a `#[global_allocator]` static; use a `LazyNumaNodePoolAllocator` for that.
+pub struct NumaNodePoolAllocator<A: LocalAllocator> {
+    pools: NonNull<NumaNodePool<A>>,
+    page_map: PageMap,
+    number_of_pools: NonZeroUsize,
+    current_numa_node: CurrentNumaNode,
+}
+
+impl<A: LocalAllocator> Debug for NumaNodePoolAllocator<A> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.pools().iter().map(|pool| pool.numa_node))
+            .finish()
+    }
+}
+
+// The pools, and the allocators within them, are exclusively owned; each pool's allocator is only used whilst its lock is held, so, like a `Mutex`, it need only be `Send` to be shared.
+unsafe impl<A: LocalAllocator + Send> Send for NumaNodePoolAllocator<A> {}
+
+unsafe impl<A: LocalAllocator + Send> Sync for NumaNodePoolAllocator<A> {}
+
+impl<A: LocalAllocator> Drop for NumaNodePoolAllocator<A> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        Self::release_pools(
+            self.pools,
+            self.number_of_pools.get(),
+            Self::pools_size(self.number_of_pools),
+        )
+    }
+}
+
+unsafe impl<A: LocalAllocator> GlobalAlloc for NumaNodePoolAllocator<A> {
+    crate::global_alloc!();
+}
+
+unsafe impl<A: LocalAllocator> AllocRef for NumaNodePoolAllocator<A> {
+    crate::alloc_ref!();
+}
+
+impl<A: LocalAllocator> Allocator for NumaNodePoolAllocator<A> {
+    #[inline(always)]
+    fn allocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocErr> {
+        let pools = self.pools();
+        let local_pool_index = self.local_pool_index();
+        let pool_indices = (local_pool_index..pools.len()).chain(0..local_pool_index);
+        for pool_index in pool_indices {
+            let result = pools[pool_index].allocator.locked(|allocator| {
+                allocator.allocate(non_zero_size, non_zero_power_of_two_alignment)
+            });
+            if likely!(result.is_ok()) {
+                return result;
+            }
+        }
+        Err(AllocErr)
+    }
+
+    #[inline(always)]
+    fn deallocate(
+        &self,
+        non_zero_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) {
+        if let Some(owning_pool) = self.owning_pool(current_memory) {
+            owning_pool.allocator.locked(|allocator| {
+                allocator.deallocate(
+                    non_zero_size,
+                    non_zero_power_of_two_alignment,
+                    current_memory,
+                )
+            })
+        }
+    }
+
+    #[inline(always)]
+    fn growing_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        let owning_pool = self.owning_pool(current_memory).ok_or(AllocErr)?;
+        let result = owning_pool.allocator.locked(|allocator| {
+            allocator.growing_reallocate(
+                non_zero_new_size,
+                non_zero_power_of_two_alignment,
+                non_zero_current_size,
+                current_memory,
+            )
+        });
+        if likely!(result.is_ok()) {
+            return result;
+        }
+
+        self.reallocate_from_another_pool(
+            owning_pool,
+            non_zero_new_size,
+            non_zero_power_of_two_alignment,
+            non_zero_current_size,
+            current_memory,
+        )
+    }
+
+    #[inline(always)]
+    fn shrinking_reallocate(
+        &self,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        self.owning_pool(current_memory)
+            .ok_or(AllocErr)?
+            .allocator
+            .locked(|allocator| {
+                allocator.shrinking_reallocate(
+                    non_zero_new_size,
+                    non_zero_power_of_two_alignment,
+                    non_zero_current_size,
+                    current_memory,
+                )
+            })
+    }
+}
+
+impl<A: LocalAllocator> NumaNodePoolAllocator<A> {
+    /// Creates a new instance with a pool for each NUMA node in `numa_nodes`, which must not be empty.
+    ///
+    /// `new_pool` is called with the memory map source to use for each NUMA node's pool, labelled eg `numa node 1 pool` (see `MemoryMapSource::set_label()`).
+    /// `current_numa_node` is usually `NumaNodePoolAllocator::<A>::current_numa_node_using_getcpu`.
+    #[inline(always)]
+    pub fn new(
+        numa_nodes: &NumaNodeBitSet,
+        new_pool: impl Fn(MemoryMapSource) -> Result<A, AllocErr>,
+        current_numa_node: CurrentNumaNode,
+    ) -> Result<Self, AllocErr> {
+        let number_of_pools = NonZeroUsize::new(numa_nodes.len()).ok_or(AllocErr)?;
+        let pools_size = Self::pools_size(number_of_pools);
+        let pools = Self::pools_memory_source()
+            .obtain(pools_size)?
+            .cast::<NumaNodePool<A>>();
+
+        for (pool_index, numa_node) in numa_nodes.iter().enumerate() {
+            let mut memory_map_source = MemoryMapSource::with_numa_settings(NumaSettings::new(
+                NumaAllocationPolicy::Bind(NumaNodeBitSet::new_static().with_numa_node(numa_node)),
+                false,
+            ));
+            memory_map_source.set_label(Some(MemoryLabel::numa_node_pool(numa_node).as_c_str()));
+            match new_pool(memory_map_source) {
+                Ok(allocator) => unsafe {
+                    pools.as_ptr().add(pool_index).write(NumaNodePool {
+                        numa_node,
+                        memory_range: allocator.memory_range(),
+                        allocator: SpinLock::new(allocator),
+                    })
+                },
+
+                Err(error) => {
+                    Self::release_pools(pools, pool_index, pools_size);
+                    return Err(error);
+                }
+            }
+        }
+
+        let page_map = PageMap::new();
+        for pool_index in 0..number_of_pools.get() {
+            let pool = unsafe { &*pools.as_ptr().add(pool_index) };
+            if let Err(error) = page_map.insert(pool.memory_range, pool.page_map_owner()) {
+                Self::release_pools(pools, number_of_pools.get(), pools_size);
+                return Err(error);
+            }
+        }
+
+        Ok(Self {
+            pools,
+            page_map,
+            number_of_pools,
+            current_numa_node,
+        })
+    }
+
+    /// Finds the NUMA node of the CPU the calling thread is running on using the `getcpu()` system call.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    pub fn current_numa_node_using_getcpu() -> u16 {
+        let mut cpu: u32 = 0;
+        let mut numa_node: u32 = 0;
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_getcpu,
+                &mut cpu as *mut u32,
+                &mut numa_node as *mut u32,
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if likely!(result == 0) {
+            numa_node as u16
+        } else {
+            0
+        }
+    }
+
+    /// Always NUMA node 0 on operating systems other than Android and Linux.
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    #[inline(always)]
+    pub fn current_numa_node_using_getcpu() -> u16 {
+        0
+    }
+
+    /// The NUMA nodes with pools, in ascending order.
+    #[inline(always)]
+    pub fn numa_nodes(&self) -> impl Iterator<Item = u16> + '_ {
+        self.pools().iter().map(|pool| pool.numa_node)
+    }
+
+    /// The NUMA node whose pool owns `memory_address`, if any.
+    #[inline(always)]
+    pub fn owning_numa_node(&self, memory_address: MemoryAddress) -> Option<u16> {
+        self.owning_pool(memory_address).map(|pool| pool.numa_node)
+    }
+
+    /// The owning pool is exhausted, so the memory is moved to whichever pool can allocate it.
+    #[cold]
+    fn reallocate_from_another_pool(
+        &self,
+        owning_pool: &NumaNodePool<A>,
+        non_zero_new_size: NonZeroUsize,
+        non_zero_power_of_two_alignment: NonZeroUsize,
+        non_zero_current_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<MemoryAddress, AllocErr> {
+        let new_memory =
+            Allocator::allocate(self, non_zero_new_size, non_zero_power_of_two_alignment)?;
+        unsafe {
+            new_memory
+                .as_ptr()
+                .copy_from_nonoverlapping(current_memory.as_ptr(), non_zero_current_size.get())
+        };
+        owning_pool.allocator.locked(|allocator| {
+            allocator.deallocate(
+                non_zero_current_size,
+                non_zero_power_of_two_alignment,
+                current_memory,
+            )
+        });
+        Ok(new_memory)
+    }
+
+    /// Falls back to the first pool if the current NUMA node has no pool.
+    #[inline(always)]
+    fn local_pool_index(&self) -> usize {
+        let current_numa_node = (self.current_numa_node)();
+        self.pools()
+            .iter()
+            .position(|pool| pool.numa_node == current_numa_node)
+            .unwrap_or(0)
+    }
+
+    #[inline(always)]
+    fn owning_pool(&self, current_memory: MemoryAddress) -> Option<&NumaNodePool<A>> {
+        match self.page_map.get(current_memory) {
+            PageOwner::Unowned => None,
+
+            PageOwner::Owner(owner) => {
+                let pool = unsafe { &*(owner.get() as *const NumaNodePool<A>) };
+                if likely!(pool.memory_range.contains(current_memory)) {
+                    Some(pool)
+                } else {
+                    None
+                }
+            }
+
+            PageOwner::Shared => self
+                .pools()
+                .iter()
+                .find(|pool| pool.memory_range.contains(current_memory)),
+        }
+    }
+
+    #[inline(always)]
+    fn pools(&self) -> &[NumaNodePool<A>] {
+        unsafe { from_raw_parts(self.pools.as_ptr(), self.number_of_pools.get()) }
+    }
+
+    #[inline(always)]
+    fn pools_size(number_of_pools: NonZeroUsize) -> NonZeroUsize {
+        (size_of::<NumaNodePool<A>>() * number_of_pools.get()).non_zero()
+    }
+
+    #[inline(always)]
+    fn release_pools(
+        pools: NonNull<NumaNodePool<A>>,
+        number_of_created_pools: usize,
+        pools_size: NonZeroUsize,
+    ) {
+        for created_pool_index in 0..number_of_created_pools {
+            unsafe { drop_in_place(pools.as_ptr().add(created_pool_index)) }
+        }
+        Self::pools_memory_source().release(pools_size, pools.cast::<u8>())
+    }
+
+    #[inline(always)]
+    fn pools_memory_source() -> MemoryMapSource {
+        MemoryMapSource::new(false, false, true, false, HugePageSize::None, None)
+    }
+}
+
+struct NumaNodePool<A: LocalAllocator> {
+    numa_node: u16,
+    memory_range: MemoryRange,
+    allocator: SpinLock<A>,
+}
+
+impl<A: LocalAllocator> NumaNodePool<A> {
+    #[inline(always)]
+    fn page_map_owner(&self) -> NonZeroUsize {
+        (self as *const Self as usize).non_zero()
+    }
+}
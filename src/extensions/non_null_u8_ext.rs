@@ -195,7 +195,7 @@ pub trait NonNullU8Ext: Sized + Copy + Ord + Debug {
 
         let number_of_lower_bits = number_of_lower_bits as u64;
 
-        let bits_to_preserve = !((1 << number_of_bits_to_unset - 1)
+        let bits_to_preserve = !(((1 << number_of_bits_to_unset) - 1)
             << (number_of_lower_bits - number_of_bits_to_unset));
         self.and_u64(bits_to_preserve);
     }
@@ -30,6 +30,8 @@ pub mod memory_address;
 
 pub mod simple_use;
 
+mod spin_lock;
+
 pub mod prelude {
     pub use crate::adaptors::prelude::*;
     pub use crate::adaptors::*;
@@ -0,0 +1,64 @@
+use crate::allocators::global::global_switchable_allocator::GlobalSwitchableAllocator;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use std::alloc::AllocError;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// Represents an Atomically Reference-counted (ARC) memory source.
+///
+/// Unlike `RcMemorySource`, this can be shared by allocators on several threads, eg a `ConcurrentArenaMemorySource` feeding per-thread `BumpAllocator`s.
+/// The memory source must be thread-safe; a memory source which is not can be made so by wrapping it in a `SpinLockedMemorySource`.
+#[derive(Debug)]
+pub struct ArcMemorySource<MS: MemorySource + Send + Sync>(Arc<MS>);
+
+impl<MS: MemorySource + Send + Sync> Clone for ArcMemorySource<MS> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<MS: MemorySource + Send + Sync> Deref for ArcMemorySource<MS> {
+    type Target = MS;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<MS: MemorySource + Send + Sync> MemorySource for ArcMemorySource<MS> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        self.0.obtain(non_zero_size)
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        self.0.release(non_zero_size, current_memory)
+    }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.0.decommit(non_zero_size, current_memory)
+    }
+}
+
+impl<MS: MemorySource + Send + Sync> ArcMemorySource<MS> {
+    /// Creates a new instance with its control block allocated from the current allocator in use.
+    #[inline(always)]
+    pub fn new(underlying_memory_source: MS) -> Self {
+        Self(Arc::new(underlying_memory_source))
+    }
+
+    /// Creates a new instance with its control block allocated from the global allocator, so that it outlives any thread's local allocators.
+    #[inline(always)]
+    pub fn new_global<GTACSA: GlobalSwitchableAllocator>(
+        global_allocator: &GTACSA,
+        underlying_memory_source: MS,
+    ) -> Self {
+        Self(global_allocator.callback_with_global_allocator(|| Arc::new(underlying_memory_source)))
+    }
+}
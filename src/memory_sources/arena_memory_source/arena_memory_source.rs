@@ -2,11 +2,14 @@ use crate::extensions::non_null_u8_ext::NonNullU8Ext;
 use crate::extensions::non_zero_usize_ext::NonZeroUsizeExt;
 use crate::extensions::usize_ext::UsizeExt;
 use crate::memory_address::MemoryAddress;
+use crate::memory_sources::arena_memory_source::arena_misuse::ArenaMisuse;
+use crate::memory_sources::arena_memory_source::arena_misuse::ArenaMisuseHook;
 use crate::memory_sources::arena_memory_source::slot_index::SlotIndex;
 use crate::memory_sources::arena_memory_source::unallocated_block::UnallocatedBlock;
 use crate::memory_sources::memory_source::MemorySource;
 use std::alloc::AllocError;
 use std::cell::Cell;
+use std::mem::size_of;
 use std::num::NonZeroUsize;
 
 /// An arena memory source.
@@ -21,8 +24,57 @@ pub struct ArenaMemorySource<MS: MemorySource> {
     memory_source: MS,
     allocations_start_from: MemoryAddress,
     memory_source_size: NonZeroUsize,
+
+    checks: Option<Checks>,
+}
+
+/// Only present for an arena created with `new_checked()`.
+#[derive(Debug)]
+struct Checks {
+    number_of_blocks: NonZeroUsize,
+
+    /// One bit per block, set when the block is occupied; lives immediately after the last block.
+    occupancy_bitmap: MemoryAddress,
+
+    misuse_hook: ArenaMisuseHook,
+}
+
+impl Checks {
+    const BITS_PER_WORD: usize = size_of::<usize>() * 8;
+
+    #[inline(always)]
+    fn occupancy_bitmap_size(number_of_blocks: NonZeroUsize) -> usize {
+        ((number_of_blocks.get() + (Self::BITS_PER_WORD - 1)) / Self::BITS_PER_WORD)
+            * size_of::<usize>()
+    }
+
+    #[inline(always)]
+    fn word_and_bit(&self, slot_index: SlotIndex) -> (*mut usize, usize) {
+        let word = unsafe {
+            (self.occupancy_bitmap.as_ptr() as *mut usize).add(slot_index.0 / Self::BITS_PER_WORD)
+        };
+        (word, 1 << (slot_index.0 % Self::BITS_PER_WORD))
+    }
+
+    /// Returns the previous occupancy.
+    #[inline(always)]
+    fn set_occupied(&self, slot_index: SlotIndex, occupied: bool) -> bool {
+        let (word, bit) = self.word_and_bit(slot_index);
+        unsafe {
+            let previous = *word;
+            *word = if occupied {
+                previous | bit
+            } else {
+                previous & !bit
+            };
+            previous & bit != 0
+        }
+    }
 }
 
+// The arena exclusively owns the memory it hands out, so it can be moved to another thread, but, being unsynchronized, not shared; see `SpinLockedMemorySource`.
+unsafe impl<MS: MemorySource + Send> Send for ArenaMemorySource<MS> {}
+
 impl<MS: MemorySource> Drop for ArenaMemorySource<MS> {
     #[inline(always)]
     fn drop(&mut self) {
@@ -34,6 +86,15 @@ impl<MS: MemorySource> Drop for ArenaMemorySource<MS> {
 impl<MS: MemorySource> MemorySource for ArenaMemorySource<MS> {
     #[inline(always)]
     fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        if let Some(ref checks) = self.checks {
+            if unlikely!(non_zero_size > self.block_size) {
+                (checks.misuse_hook)(ArenaMisuse::SizeLargerThanBlock {
+                    non_zero_size,
+                    block_size: self.block_size,
+                });
+                return Err(AllocError);
+            }
+        }
         debug_assert!(non_zero_size <= self.block_size);
 
         let next_available_slot_index = self.next_available_slot_index.get();
@@ -46,11 +107,21 @@ impl<MS: MemorySource> MemorySource for ArenaMemorySource<MS> {
         self.next_available_slot_index
             .set(unallocated_block.next_available_slot_index());
 
+        if let Some(ref checks) = self.checks {
+            checks.set_occupied(next_available_slot_index, true);
+        }
+
         Ok(unallocated_block.to_memory_address())
     }
 
     #[inline(always)]
     fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        if let Some(ref checks) = self.checks {
+            if let Err(misuse) = self.check_release(checks, non_zero_size, current_memory) {
+                (checks.misuse_hook)(misuse);
+                return;
+            }
+        }
         debug_assert!(non_zero_size <= self.block_size);
 
         let unallocated_block = UnallocatedBlock::from_memory_address(current_memory);
@@ -60,6 +131,11 @@ impl<MS: MemorySource> MemorySource for ArenaMemorySource<MS> {
         self.next_available_slot_index
             .set(self.slot_index_from_block(unallocated_block));
     }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.memory_source.decommit(non_zero_size, current_memory)
+    }
 }
 
 impl<MS: MemorySource> ArenaMemorySource<MS> {
@@ -93,17 +169,73 @@ impl<MS: MemorySource> ArenaMemorySource<MS> {
         number_of_blocks: NonZeroUsize,
         block_initializer: impl Fn(MemoryAddress, NonZeroUsize),
     ) -> Result<Self, AllocError> {
-        let memory_source_size = block_size.multiply(number_of_blocks);
+        Self::new_with_checks(
+            memory_source,
+            block_size,
+            number_of_blocks,
+            block_initializer,
+            None,
+        )
+    }
+
+    /// Creates a new instance which, even in release builds, checks every obtain and release.
+    ///
+    /// Sizes larger than a block, releases of addresses which are not the start of a block within the arena and double releases are reported to `misuse_hook` and refused, instead of corrupting the arena.
+    /// The occupancy bitmap used to detect double releases is obtained from `memory_source` along with the blocks.
+    #[inline(always)]
+    pub fn new_checked(
+        memory_source: MS,
+        block_size: NonZeroUsize,
+        number_of_blocks: NonZeroUsize,
+        block_initializer: impl Fn(MemoryAddress, NonZeroUsize),
+        misuse_hook: ArenaMisuseHook,
+    ) -> Result<Self, AllocError> {
+        Self::new_with_checks(
+            memory_source,
+            block_size,
+            number_of_blocks,
+            block_initializer,
+            Some(misuse_hook),
+        )
+    }
+
+    #[inline(always)]
+    fn new_with_checks(
+        memory_source: MS,
+        block_size: NonZeroUsize,
+        number_of_blocks: NonZeroUsize,
+        block_initializer: impl Fn(MemoryAddress, NonZeroUsize),
+        misuse_hook: Option<ArenaMisuseHook>,
+    ) -> Result<Self, AllocError> {
+        let blocks_size = block_size.multiply(number_of_blocks);
+        let memory_source_size = match misuse_hook {
+            None => blocks_size,
+            Some(_) => blocks_size.add(Checks::occupancy_bitmap_size(number_of_blocks)),
+        };
 
         let allocations_start_from = memory_source.obtain(memory_source_size)?;
 
         Self::initialize_blocks_so_they_are_a_singly_linked_list(
             block_size,
             block_initializer,
-            memory_source_size,
+            blocks_size,
             allocations_start_from,
         );
 
+        let checks = misuse_hook.map(|misuse_hook| {
+            let occupancy_bitmap = allocations_start_from.add_non_zero(blocks_size);
+            unsafe {
+                occupancy_bitmap
+                    .as_ptr()
+                    .write_bytes(0x00, Checks::occupancy_bitmap_size(number_of_blocks))
+            };
+            Checks {
+                number_of_blocks,
+                occupancy_bitmap,
+                misuse_hook,
+            }
+        });
+
         Ok(Self {
             next_available_slot_index: Cell::default(),
 
@@ -114,9 +246,43 @@ impl<MS: MemorySource> ArenaMemorySource<MS> {
             memory_source,
             allocations_start_from,
             memory_source_size,
+
+            checks,
         })
     }
 
+    #[inline(always)]
+    fn check_release(
+        &self,
+        checks: &Checks,
+        non_zero_size: NonZeroUsize,
+        current_memory: MemoryAddress,
+    ) -> Result<(), ArenaMisuse> {
+        if unlikely!(non_zero_size > self.block_size) {
+            return Err(ArenaMisuse::SizeLargerThanBlock {
+                non_zero_size,
+                block_size: self.block_size,
+            });
+        }
+
+        if unlikely!(current_memory < self.allocations_start_from) {
+            return Err(ArenaMisuse::OutsideOfArena(current_memory));
+        }
+        let offset = current_memory.difference(self.allocations_start_from);
+        let slot_index = SlotIndex(offset / self.block_size.get());
+        if unlikely!(slot_index.0 >= checks.number_of_blocks.get()) {
+            return Err(ArenaMisuse::OutsideOfArena(current_memory));
+        }
+        if unlikely!(offset % self.block_size.get() != 0) {
+            return Err(ArenaMisuse::NotAtBlockBoundary(current_memory));
+        }
+
+        if unlikely!(!checks.set_occupied(slot_index, false)) {
+            return Err(ArenaMisuse::DoubleRelease(current_memory));
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     fn initialize_blocks_so_they_are_a_singly_linked_list(
         block_size: NonZeroUsize,
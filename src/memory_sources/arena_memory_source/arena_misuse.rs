@@ -0,0 +1,29 @@
+use crate::memory_address::MemoryAddress;
+use std::num::NonZeroUsize;
+
+/// A misuse of a checked `ArenaMemorySource` which has been detected and refused, rather than allowed to corrupt the arena's free list.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ArenaMisuse {
+    /// A size larger than a block was obtained or released.
+    SizeLargerThanBlock {
+        /// The size requested.
+        non_zero_size: NonZeroUsize,
+
+        /// The arena's block size.
+        block_size: NonZeroUsize,
+    },
+
+    /// An address outside of the arena was released.
+    OutsideOfArena(MemoryAddress),
+
+    /// An address inside the arena but not at the start of a block was released.
+    NotAtBlockBoundary(MemoryAddress),
+
+    /// A block was released which was not obtained or had already been released.
+    DoubleRelease(MemoryAddress),
+}
+
+/// Called with each misuse a checked `ArenaMemorySource` detects.
+///
+/// Obtains which are refused return `Err(AllocError)` after the hook is called; releases which are refused are ignored.
+pub type ArenaMisuseHook = fn(ArenaMisuse);
@@ -0,0 +1,223 @@
+use crate::extensions::non_null_u8_ext::NonNullU8Ext;
+use crate::extensions::non_zero_usize_ext::NonZeroUsizeExt;
+use crate::extensions::usize_ext::UsizeExt;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::arena_memory_source::slot_index::SlotIndex;
+use crate::memory_sources::memory_source::MemorySource;
+use std::alloc::AllocError;
+use std::num::NonZeroUsize;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Relaxed, Release};
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+
+/// A thread-safe arena memory source.
+///
+/// Like `ArenaMemorySource`, but the free list of blocks is a lock-free stack, so blocks can be obtained and released from many threads at once, eg to feed per-thread allocators from one fixed-size block pool.
+///
+/// The head of the free list is tagged: it holds both a slot index and a generation counter, incremented on every change, in one atomic word, so that a stale head can never be swapped in (the ABA problem).
+/// Consequently there can be at most `u32::MAX - 1` blocks.
+#[derive(Debug)]
+pub struct ConcurrentArenaMemorySource<MS: MemorySource> {
+    tagged_head: AtomicU64,
+
+    block_size: NonZeroUsize,
+    #[cfg(debug_assertions)]
+    number_of_blocks: NonZeroUsize,
+
+    memory_source: MS,
+    allocations_start_from: MemoryAddress,
+    memory_source_size: NonZeroUsize,
+}
+
+unsafe impl<MS: MemorySource + Send> Send for ConcurrentArenaMemorySource<MS> {}
+
+unsafe impl<MS: MemorySource + Sync> Sync for ConcurrentArenaMemorySource<MS> {}
+
+impl<MS: MemorySource> Drop for ConcurrentArenaMemorySource<MS> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.memory_source
+            .release(self.memory_source_size, self.allocations_start_from)
+    }
+}
+
+impl<MS: MemorySource> MemorySource for ConcurrentArenaMemorySource<MS> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        debug_assert!(non_zero_size <= self.block_size);
+
+        let mut tagged_head = self.tagged_head.load(Acquire);
+        loop {
+            let (next_available_slot_index, generation) = Self::untag(tagged_head);
+            if unlikely!(next_available_slot_index.is_fully_allocated()) {
+                return Err(AllocError);
+            }
+
+            // The block may have been obtained, and written to, by another thread since `tagged_head` was loaded; if so, the generation will have changed and the exchange will fail.
+            let block = self.block_from_slot_index(next_available_slot_index);
+            let new_tagged_head = Self::tag(
+                SlotIndex(Self::next_available_slot_index(block).load(Relaxed)),
+                generation,
+            );
+
+            match self.tagged_head.compare_exchange_weak(
+                tagged_head,
+                new_tagged_head,
+                AcqRel,
+                Acquire,
+            ) {
+                Ok(_) => return Ok(block),
+                Err(was) => tagged_head = was,
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        debug_assert!(non_zero_size <= self.block_size);
+
+        let slot_index = self.slot_index_from_block(current_memory);
+
+        let mut tagged_head = self.tagged_head.load(Acquire);
+        loop {
+            let (next_available_slot_index, generation) = Self::untag(tagged_head);
+            Self::next_available_slot_index(current_memory)
+                .store(next_available_slot_index.0, Relaxed);
+
+            match self.tagged_head.compare_exchange_weak(
+                tagged_head,
+                Self::tag(slot_index, generation),
+                Release,
+                Acquire,
+            ) {
+                Ok(_) => return,
+                Err(was) => tagged_head = was,
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.memory_source.decommit(non_zero_size, current_memory)
+    }
+}
+
+impl<MS: MemorySource> ConcurrentArenaMemorySource<MS> {
+    const SLOT_INDEX_MASK: u64 = ::std::u32::MAX as u64;
+
+    const GENERATION_SHIFT: u32 = 32;
+
+    /// Create a new instance by memory size and block size.
+    #[inline(always)]
+    pub fn new_by_amount(
+        memory_source: MS,
+        block_size: NonZeroUsize,
+        memory_source_size: NonZeroUsize,
+        block_initializer: impl Fn(MemoryAddress, NonZeroUsize),
+    ) -> Result<Self, AllocError> {
+        let number_of_blocks =
+            ((memory_source_size.get() + (block_size.get() - 1)) / block_size.get()).non_zero();
+
+        Self::new(
+            memory_source,
+            block_size,
+            number_of_blocks,
+            block_initializer,
+        )
+    }
+
+    /// Creates a new instance.
+    ///
+    /// `block_size` must be at least 8 to be useful, and a multiple of 8 so that blocks are suitably aligned.
+    /// `block_initializer` takes the address of a block and the size of a block; after it is called, the block will have the first 8 bytes (4 bytes on 32-bit platforms) overwritten with a slot index pointer.
+    #[inline(always)]
+    pub fn new(
+        memory_source: MS,
+        block_size: NonZeroUsize,
+        number_of_blocks: NonZeroUsize,
+        block_initializer: impl Fn(MemoryAddress, NonZeroUsize),
+    ) -> Result<Self, AllocError> {
+        assert!(
+            (number_of_blocks.get() as u64) < Self::SLOT_INDEX_MASK,
+            "number_of_blocks `{}` must be less than `u32::MAX`",
+            number_of_blocks
+        );
+
+        let memory_source_size = block_size.multiply(number_of_blocks);
+
+        let allocations_start_from = memory_source.obtain(memory_source_size)?;
+
+        let mut slot_index = SlotIndex(1);
+        let mut block_memory_address = allocations_start_from;
+        let allocations_end_at = allocations_start_from.add_non_zero(memory_source_size);
+        let allocations_end_at_less_one_block = allocations_end_at.subtract_non_zero(block_size);
+        while block_memory_address != allocations_end_at_less_one_block {
+            block_initializer(block_memory_address, block_size);
+            Self::next_available_slot_index(block_memory_address).store(slot_index.0, Relaxed);
+
+            slot_index.increment();
+            block_memory_address.add_assign_non_zero(block_size)
+        }
+        block_initializer(allocations_end_at_less_one_block, block_size);
+        Self::next_available_slot_index(allocations_end_at_less_one_block).store(
+            SlotIndex::IS_FULLY_ALLOCATED_NEXT_AVAILABLE_SLOT_INDEX_SENTINEL.0,
+            Relaxed,
+        );
+
+        Ok(Self {
+            tagged_head: AtomicU64::new(Self::tag(SlotIndex::default(), 0)),
+
+            block_size,
+            #[cfg(debug_assertions)]
+            number_of_blocks,
+
+            memory_source,
+            allocations_start_from,
+            memory_source_size,
+        })
+    }
+
+    /// Increments the generation.
+    #[inline(always)]
+    fn tag(slot_index: SlotIndex, previous_generation: u64) -> u64 {
+        let slot_index = if slot_index.is_fully_allocated() {
+            Self::SLOT_INDEX_MASK
+        } else {
+            (slot_index.0 as u64) & Self::SLOT_INDEX_MASK
+        };
+        (previous_generation.wrapping_add(1) << Self::GENERATION_SHIFT) | slot_index
+    }
+
+    #[inline(always)]
+    fn untag(tagged_head: u64) -> (SlotIndex, u64) {
+        let slot_index = match tagged_head & Self::SLOT_INDEX_MASK {
+            Self::SLOT_INDEX_MASK => {
+                SlotIndex::IS_FULLY_ALLOCATED_NEXT_AVAILABLE_SLOT_INDEX_SENTINEL
+            }
+            slot_index => SlotIndex(slot_index as usize),
+        };
+        (slot_index, tagged_head >> Self::GENERATION_SHIFT)
+    }
+
+    #[inline(always)]
+    fn next_available_slot_index<'a>(block: MemoryAddress) -> &'a AtomicUsize {
+        unsafe { &*(block.as_ptr() as *const AtomicUsize) }
+    }
+
+    #[inline(always)]
+    fn block_from_slot_index(&self, slot_index: SlotIndex) -> MemoryAddress {
+        #[cfg(debug_assertions)]
+        debug_assert!(
+            slot_index.0 < self.number_of_blocks.get(),
+            "slot_index `{:?}` is out of range",
+            slot_index
+        );
+
+        self.allocations_start_from
+            .add(self.block_size.get() * slot_index.0)
+    }
+
+    #[inline(always)]
+    fn slot_index_from_block(&self, block: MemoryAddress) -> SlotIndex {
+        SlotIndex(block.difference(self.allocations_start_from) / self.block_size.get())
+    }
+}
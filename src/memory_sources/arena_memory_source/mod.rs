@@ -1,10 +1,14 @@
 pub mod arena_memory_source;
+pub mod arena_misuse;
+pub mod concurrent_arena_memory_source;
 pub mod slot_index;
 pub mod unallocated_block;
 pub mod unsized_block;
 
 pub mod prelude {
     pub use super::arena_memory_source::*;
+    pub use super::arena_misuse::*;
+    pub use super::concurrent_arena_memory_source::*;
     pub use super::slot_index::*;
     pub use super::unallocated_block::*;
     pub use super::unsized_block::*;
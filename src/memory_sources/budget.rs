@@ -0,0 +1,138 @@
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Called when the bytes used against a budget rise to or above a soft-limit watermark.
+///
+/// Passed the budget, the watermark crossed and the bytes now used.
+pub type BudgetPressureHook = fn(budget: &Budget, watermark: usize, used_bytes: usize);
+
+/// A limit on the bytes which may be obtained by one or more `BudgetedMemorySource`s.
+///
+/// Budgets are hierarchical: bytes used against a budget also count against its parent, and so on up; obtaining fails if any budget on the way up would exceed its hard limit.
+/// This lets, say, per-task budgets roll up into a per-service budget.
+///
+/// Budgets are thread-safe; watermarks must be added before a budget is shared.
+#[derive(Debug)]
+pub struct Budget<'a> {
+    parent: Option<&'a Budget<'a>>,
+    hard_limit: usize,
+    watermarks: Vec<(usize, BudgetPressureHook)>,
+
+    used_bytes: AtomicUsize,
+    peak_used_bytes: AtomicUsize,
+    refusals: AtomicUsize,
+}
+
+impl<'a> Budget<'a> {
+    /// Creates a new top-level budget.
+    ///
+    /// Use `usize::MAX` as the `hard_limit` for a budget which only tracks usage.
+    #[inline(always)]
+    pub fn new(hard_limit: usize) -> Self {
+        Self::new_with_parent(None, hard_limit)
+    }
+
+    /// Creates a new budget whose usage also counts against `parent`.
+    #[inline(always)]
+    pub fn new_child(parent: &'a Budget<'a>, hard_limit: usize) -> Self {
+        Self::new_with_parent(Some(parent), hard_limit)
+    }
+
+    #[inline(always)]
+    fn new_with_parent(parent: Option<&'a Budget<'a>>, hard_limit: usize) -> Self {
+        Self {
+            parent,
+            hard_limit,
+            watermarks: Vec::new(),
+
+            used_bytes: AtomicUsize::new(0),
+            peak_used_bytes: AtomicUsize::new(0),
+            refusals: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds a soft-limit watermark; `pressure_hook` is called each time usage rises from below `watermark` to at or above it.
+    #[inline(always)]
+    pub fn add_watermark(&mut self, watermark: usize, pressure_hook: BudgetPressureHook) {
+        self.watermarks.push((watermark, pressure_hook))
+    }
+
+    /// Parent budget, if any.
+    #[inline(always)]
+    pub fn parent(&self) -> Option<&'a Budget<'a>> {
+        self.parent
+    }
+
+    /// Hard limit.
+    #[inline(always)]
+    pub fn hard_limit(&self) -> usize {
+        self.hard_limit
+    }
+
+    /// Bytes currently used, including those used by child budgets.
+    #[inline(always)]
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes.load(Relaxed)
+    }
+
+    /// Highest bytes ever used.
+    #[inline(always)]
+    pub fn peak_used_bytes(&self) -> usize {
+        self.peak_used_bytes.load(Relaxed)
+    }
+
+    /// Number of times obtaining memory was refused because this budget's hard limit would have been exceeded.
+    #[inline(always)]
+    pub fn refusals(&self) -> usize {
+        self.refusals.load(Relaxed)
+    }
+
+    /// Charges `bytes` against this budget and all of its ancestors, or, if any would exceed its hard limit, against none of them.
+    #[inline(always)]
+    pub(crate) fn charge(&self, bytes: usize) -> bool {
+        let used_bytes = self
+            .used_bytes
+            .fetch_update(Relaxed, Relaxed, |used_bytes| {
+                match used_bytes.checked_add(bytes) {
+                    Some(now_used_bytes) if now_used_bytes <= self.hard_limit => {
+                        Some(now_used_bytes)
+                    }
+                    _ => None,
+                }
+            });
+
+        match used_bytes {
+            Err(_) => {
+                self.refusals.fetch_add(1, Relaxed);
+                false
+            }
+
+            Ok(previously_used_bytes) => {
+                if let Some(parent) = self.parent {
+                    if unlikely!(!parent.charge(bytes)) {
+                        self.used_bytes.fetch_sub(bytes, Relaxed);
+                        return false;
+                    }
+                }
+
+                let now_used_bytes = previously_used_bytes + bytes;
+                self.peak_used_bytes.fetch_max(now_used_bytes, Relaxed);
+                for &(watermark, pressure_hook) in self.watermarks.iter() {
+                    if previously_used_bytes < watermark && now_used_bytes >= watermark {
+                        pressure_hook(self, watermark, now_used_bytes)
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// Credits `bytes` back to this budget and all of its ancestors.
+    #[inline(always)]
+    pub(crate) fn credit(&self, bytes: usize) {
+        self.used_bytes.fetch_sub(bytes, Relaxed);
+        if let Some(parent) = self.parent {
+            parent.credit(bytes)
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::budget::Budget;
+use crate::memory_sources::memory_source::MemorySource;
+use std::alloc::AllocError;
+use std::num::NonZeroUsize;
+
+/// A memory source which charges the memory it obtains against a `Budget`, failing to obtain memory once the budget, or any of its ancestors, would exceed its hard limit.
+///
+/// Several budgeted memory sources may share a budget.
+#[derive(Debug)]
+pub struct BudgetedMemorySource<'a, MS: MemorySource> {
+    budget: &'a Budget<'a>,
+    memory_source: MS,
+}
+
+impl<'a, MS: MemorySource> MemorySource for BudgetedMemorySource<'a, MS> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        if unlikely!(!self.budget.charge(non_zero_size.get())) {
+            return Err(AllocError);
+        }
+
+        let result = self.memory_source.obtain(non_zero_size);
+        if unlikely!(result.is_err()) {
+            self.budget.credit(non_zero_size.get())
+        }
+        result
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        self.memory_source.release(non_zero_size, current_memory);
+        self.budget.credit(non_zero_size.get())
+    }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.memory_source.decommit(non_zero_size, current_memory)
+    }
+}
+
+impl<'a, MS: MemorySource> BudgetedMemorySource<'a, MS> {
+    /// Creates a new instance.
+    #[inline(always)]
+    pub fn new(budget: &'a Budget<'a>, memory_source: MS) -> Self {
+        Self {
+            budget,
+            memory_source,
+        }
+    }
+
+    /// Budget.
+    #[inline(always)]
+    pub fn budget(&self) -> &'a Budget<'a> {
+        self.budget
+    }
+}
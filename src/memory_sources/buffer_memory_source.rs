@@ -0,0 +1,116 @@
+use crate::extensions::non_null_u8_ext::NonNullU8Ext;
+use crate::extensions::non_zero_usize_ext::NonZeroUsizeExt;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use std::alloc::AllocError;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::num::NonZeroUsize;
+use std::ptr::NonNull;
+
+/// A memory source which obtains memory from a caller-provided buffer, eg a `static` or an array on the stack, rather than from the operating system.
+///
+/// Useful in tests, to avoid system calls, and in environments where `mmap()` is forbidden, such as seccomp sandboxes.
+///
+/// Memory is obtained in order from the start of the buffer, with each obtained region aligned to the alignment given on creation.
+/// Releasing the most recently obtained memory makes it available again; once all obtained memory has been released, the whole buffer is available again.
+#[derive(Debug)]
+pub struct BufferMemorySource<'a> {
+    next_obtain_at: Cell<usize>,
+    live_obtains: Cell<usize>,
+
+    alignment: NonZeroUsize,
+    buffer_starts_at: usize,
+    buffer_ends_at: usize,
+
+    marker: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> MemorySource for BufferMemorySource<'a> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        let alignment_mask = self.alignment.get() - 1;
+        let obtain_at = match self.next_obtain_at.get().checked_add(alignment_mask) {
+            Some(unaligned) => unaligned & !alignment_mask,
+            None => return Err(AllocError),
+        };
+
+        let ends_at = match obtain_at.checked_add(non_zero_size.get()) {
+            Some(ends_at) if ends_at <= self.buffer_ends_at => ends_at,
+            _ => return Err(AllocError),
+        };
+
+        self.next_obtain_at.set(ends_at);
+        self.live_obtains.set(self.live_obtains.get() + 1);
+        Ok(MemoryAddress::from_usize(obtain_at))
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        let current_memory = current_memory.to_usize();
+        debug_assert!(
+            current_memory >= self.buffer_starts_at && current_memory < self.buffer_ends_at,
+            "Memory was not obtained from this memory source"
+        );
+        debug_assert_ne!(self.live_obtains.get(), 0, "Released more than obtained");
+
+        let live_obtains = self.live_obtains.get() - 1;
+        self.live_obtains.set(live_obtains);
+
+        if live_obtains == 0 {
+            self.next_obtain_at.set(self.buffer_starts_at)
+        } else if current_memory + non_zero_size.get() == self.next_obtain_at.get() {
+            self.next_obtain_at.set(current_memory)
+        }
+    }
+}
+
+impl<'a> BufferMemorySource<'a> {
+    /// Create a new instance using an initialized buffer.
+    ///
+    /// `alignment` must be a power of two.
+    #[inline(always)]
+    pub fn new(buffer: &'a mut [u8], alignment: NonZeroUsize) -> Self {
+        let length = buffer.len();
+        Self::from_raw_parts(
+            unsafe { NonNull::new_unchecked(buffer.as_mut_ptr()) },
+            length,
+            alignment,
+        )
+    }
+
+    /// Create a new instance using an uninitialized buffer, eg `[MaybeUninit::uninit(); 4096]` on the stack.
+    ///
+    /// `alignment` must be a power of two.
+    #[inline(always)]
+    pub fn from_uninitialized(buffer: &'a mut [MaybeUninit<u8>], alignment: NonZeroUsize) -> Self {
+        let length = buffer.len();
+        Self::from_raw_parts(
+            unsafe { NonNull::new_unchecked(buffer.as_mut_ptr() as *mut u8) },
+            length,
+            alignment,
+        )
+    }
+
+    #[inline(always)]
+    fn from_raw_parts(buffer: MemoryAddress, length: usize, alignment: NonZeroUsize) -> Self {
+        debug_assert!(
+            alignment.is_power_of_two(),
+            "alignment `{}` must be a power of two",
+            alignment
+        );
+
+        let buffer_starts_at = buffer.to_usize();
+        Self {
+            next_obtain_at: Cell::new(buffer_starts_at),
+            live_obtains: Cell::new(0),
+
+            alignment,
+            buffer_starts_at,
+            buffer_ends_at: buffer_starts_at + length,
+
+            marker: PhantomData,
+        }
+    }
+}
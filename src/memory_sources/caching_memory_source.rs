@@ -0,0 +1,263 @@
+use crate::extensions::non_null_u8_ext::NonNullU8Ext;
+use crate::extensions::non_zero_usize_ext::NonZeroUsizeExt;
+use crate::extensions::usize_ext::UsizeExt;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use std::alloc::AllocError;
+use std::cell::Cell;
+use std::mem::{align_of, size_of};
+use std::num::NonZeroUsize;
+use std::ptr::null_mut;
+use std::time::{Duration, Instant};
+
+const NUMBER_OF_BUCKETS: usize = 32;
+
+/// A memory source which, instead of releasing regions to an underlying memory source, caches them for reuse by later obtains of the same size.
+///
+/// Useful when short-lived allocators, such as a `BumpAllocator` per request, would otherwise cost a `mmap()` and `munmap()` pair each.
+///
+/// Cached regions are returned to the underlying memory source, oldest first, when more than `maximum_cached_bytes` are cached, or, if a `maximum_age` is given, once they have been cached for longer than it.
+/// Aging happens only when this memory source is used, or when `release_aged()` is called.
+///
+/// Memory obtained from the cache is not zeroed.
+/// Regions smaller than a cached region's header are never cached.
+#[derive(Debug)]
+pub struct CachingMemorySource<MS: MemorySource> {
+    buckets: [Bucket; NUMBER_OF_BUCKETS],
+    cached_bytes: Cell<usize>,
+    next_sequence_number: Cell<u64>,
+
+    maximum_cached_bytes: usize,
+    maximum_age: Option<Duration>,
+    decommit_idle_regions: bool,
+
+    memory_source: MS,
+}
+
+impl<MS: MemorySource> Drop for CachingMemorySource<MS> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.release_while(|_| true)
+    }
+}
+
+impl<MS: MemorySource> MemorySource for CachingMemorySource<MS> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        self.release_aged();
+
+        let bucket = self.bucket(non_zero_size);
+        let mut cached_region = bucket.newest.get();
+        while !cached_region.is_null() {
+            let cached = unsafe { &*cached_region };
+            if cached.size == non_zero_size {
+                self.uncache(bucket, cached_region);
+                return Ok(MemoryAddress::from_usize(cached_region as usize));
+            }
+            cached_region = cached.older;
+        }
+
+        self.memory_source.obtain(non_zero_size)
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        if unlikely!(
+            non_zero_size.get() > self.maximum_cached_bytes
+                || non_zero_size.get() < size_of::<CachedRegion>()
+                || !current_memory.is_aligned_to(align_of::<CachedRegion>().non_zero())
+        ) {
+            return self.memory_source.release(non_zero_size, current_memory);
+        }
+
+        let sequence_number = self.next_sequence_number.get();
+        self.next_sequence_number.set(sequence_number + 1);
+        let cached_at = self.maximum_age.map(|_| Instant::now());
+        let cached_region = current_memory.as_ptr() as *mut CachedRegion;
+        let bucket = self.bucket(non_zero_size);
+        let newest = bucket.newest.get();
+        unsafe {
+            cached_region.write(CachedRegion {
+                newer: null_mut(),
+                older: newest,
+                size: non_zero_size,
+                sequence_number,
+                cached_at,
+            })
+        };
+        if newest.is_null() {
+            bucket.oldest.set(cached_region)
+        } else {
+            unsafe { (*newest).newer = cached_region }
+        }
+        bucket.newest.set(cached_region);
+        self.cached_bytes
+            .set(self.cached_bytes.get() + non_zero_size.get());
+
+        if self.decommit_idle_regions {
+            let header_size = size_of::<CachedRegion>();
+            if let Some(idle_size) = NonZeroUsize::new(non_zero_size.get() - header_size) {
+                self.memory_source
+                    .decommit(idle_size, current_memory.add(header_size));
+            }
+        }
+
+        let maximum_cached_bytes = self.maximum_cached_bytes;
+        self.release_while(|this| this.cached_bytes.get() > maximum_cached_bytes);
+        self.release_aged();
+    }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.memory_source.decommit(non_zero_size, current_memory)
+    }
+}
+
+impl<MS: MemorySource> CachingMemorySource<MS> {
+    /// Creates a new instance.
+    ///
+    /// If `decommit_idle_regions` is true, all but the first page of a region is decommitted, using `MemorySource::decommit()`, while it sits in the cache; configure a `MemoryMapSource` with `DecommitAdvice::Free` to make this cheap.
+    #[inline(always)]
+    pub fn new(
+        memory_source: MS,
+        maximum_cached_bytes: usize,
+        maximum_age: Option<Duration>,
+        decommit_idle_regions: bool,
+    ) -> Self {
+        Self {
+            buckets: Default::default(),
+            cached_bytes: Cell::new(0),
+            next_sequence_number: Cell::new(0),
+
+            maximum_cached_bytes,
+            maximum_age,
+            decommit_idle_regions,
+
+            memory_source,
+        }
+    }
+
+    /// Number of bytes currently cached.
+    #[inline(always)]
+    pub fn cached_bytes(&self) -> usize {
+        self.cached_bytes.get()
+    }
+
+    /// Returns regions cached for longer than `maximum_age` to the underlying memory source.
+    #[inline(always)]
+    pub fn release_aged(&self) {
+        if let Some(maximum_age) = self.maximum_age {
+            if self.cached_bytes.get() != 0 {
+                let now = Instant::now();
+                self.release_while(|this| match this.oldest() {
+                    None => false,
+                    Some((_, cached_region)) => {
+                        let cached_at = unsafe { (*cached_region).cached_at }.unwrap();
+                        now.duration_since(cached_at) > maximum_age
+                    }
+                })
+            }
+        }
+    }
+
+    /// Returns all cached regions to the underlying memory source.
+    #[inline(always)]
+    pub fn release_all(&self) {
+        self.release_while(|_| true)
+    }
+
+    /// Repeatedly returns the oldest cached region to the underlying memory source whilst `predicate` is true.
+    #[inline(always)]
+    fn release_while(&self, predicate: impl Fn(&Self) -> bool) {
+        while predicate(self) {
+            match self.oldest() {
+                None => return,
+
+                Some((bucket, cached_region)) => {
+                    let size = unsafe { (*cached_region).size };
+                    self.uncache(bucket, cached_region);
+                    self.memory_source
+                        .release(size, MemoryAddress::from_usize(cached_region as usize))
+                }
+            }
+        }
+    }
+
+    /// The oldest cached region is the one with the lowest sequence number amongst the oldest of each bucket.
+    #[inline(always)]
+    fn oldest(&self) -> Option<(&Bucket, *mut CachedRegion)> {
+        let mut oldest: Option<(&Bucket, *mut CachedRegion)> = None;
+        for bucket in self.buckets.iter() {
+            let candidate = bucket.oldest.get();
+            if candidate.is_null() {
+                continue;
+            }
+            oldest = match oldest {
+                Some((_, current))
+                    if unsafe { (*current).sequence_number < (*candidate).sequence_number } =>
+                {
+                    oldest
+                }
+                _ => Some((bucket, candidate)),
+            };
+        }
+        oldest
+    }
+
+    #[inline(always)]
+    fn uncache(&self, bucket: &Bucket, cached_region: *mut CachedRegion) {
+        let CachedRegion {
+            newer, older, size, ..
+        } = unsafe { cached_region.read() };
+
+        if newer.is_null() {
+            bucket.newest.set(older)
+        } else {
+            unsafe { (*newer).older = older }
+        }
+        if older.is_null() {
+            bucket.oldest.set(newer)
+        } else {
+            unsafe { (*older).newer = newer }
+        }
+
+        self.cached_bytes.set(self.cached_bytes.get() - size.get());
+    }
+
+    #[inline(always)]
+    fn bucket(&self, non_zero_size: NonZeroUsize) -> &Bucket {
+        let index = non_zero_size.next_power_of_two().logarithm_base2();
+        &self.buckets[if index >= NUMBER_OF_BUCKETS {
+            NUMBER_OF_BUCKETS - 1
+        } else {
+            index
+        }]
+    }
+}
+
+/// Regions of sizes which round up to the same power of two share a bucket, newest first.
+#[derive(Debug)]
+struct Bucket {
+    newest: Cell<*mut CachedRegion>,
+    oldest: Cell<*mut CachedRegion>,
+}
+
+impl Default for Bucket {
+    #[inline(always)]
+    fn default() -> Self {
+        Self {
+            newest: Cell::new(null_mut()),
+            oldest: Cell::new(null_mut()),
+        }
+    }
+}
+
+/// Header written at the start of a cached region.
+#[derive(Debug)]
+struct CachedRegion {
+    newer: *mut CachedRegion,
+    older: *mut CachedRegion,
+    size: NonZeroUsize,
+    sequence_number: u64,
+    cached_at: Option<Instant>,
+}
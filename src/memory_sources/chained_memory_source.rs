@@ -0,0 +1,200 @@
+use crate::extensions::non_null_u8_ext::NonNullU8Ext;
+use crate::extensions::non_zero_usize_ext::NonZeroUsizeExt;
+use crate::extensions::usize_ext::UsizeExt;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use std::alloc::AllocError;
+use std::cell::Cell;
+use std::cmp::max;
+use std::mem::size_of;
+use std::num::NonZeroUsize;
+use std::ptr::null_mut;
+
+/// A memory source which hands out sub-regions of large slabs of memory obtained from another memory source.
+///
+/// Sub-regions are handed out in order from the most recently obtained slab; when it is exhausted, a new slab is obtained.
+/// A slab is released back to the other memory source once all the sub-regions in it have been released, unless it is the most recently obtained slab, which is instead reused from its start.
+///
+/// This makes it cheap to create (and nest) many fixed-size allocators, such as `BumpAllocator` or `BitSetAllocator`, each of which obtains memory once; share it between them with `RcMemorySource`.
+///
+/// Each slab starts with a small header, so the memory source must be able to obtain memory which can be written to.
+#[derive(Debug)]
+pub struct ChainedMemorySource<MS: MemorySource> {
+    most_recent_slab: Cell<*mut Slab>,
+
+    slab_size: NonZeroUsize,
+    sub_region_alignment: NonZeroUsize,
+
+    memory_source: MS,
+}
+
+impl<MS: MemorySource> Drop for ChainedMemorySource<MS> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        let mut slab = self.most_recent_slab.get();
+        while !slab.is_null() {
+            let older = unsafe { (*slab).older };
+            self.release_slab(slab);
+            slab = older
+        }
+    }
+}
+
+impl<MS: MemorySource> MemorySource for ChainedMemorySource<MS> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        let non_zero_size = non_zero_size.round_up_to_power_of_two(self.sub_region_alignment);
+
+        let most_recent_slab = self.most_recent_slab.get();
+        if likely!(!most_recent_slab.is_null()) {
+            if let Some(sub_region) =
+                unsafe { &mut *most_recent_slab }.obtain_sub_region(non_zero_size)
+            {
+                return Ok(sub_region);
+            }
+        }
+
+        self.obtain_from_new_slab(non_zero_size)
+    }
+
+    #[inline(always)]
+    fn release(&self, _non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        let most_recent_slab = self.most_recent_slab.get();
+        let mut newer: *mut Slab = null_mut();
+        let mut slab = most_recent_slab;
+        while !slab.is_null() {
+            let slab_reference = unsafe { &mut *slab };
+            if slab_reference.contains(current_memory) {
+                slab_reference.live_sub_regions -= 1;
+                if slab_reference.live_sub_regions == 0 {
+                    if slab == most_recent_slab {
+                        slab_reference.next_sub_region_at = self.header_size()
+                    } else {
+                        unsafe { (*newer).older = slab_reference.older };
+                        self.release_slab(slab)
+                    }
+                }
+                return;
+            }
+            newer = slab;
+            slab = slab_reference.older
+        }
+
+        debug_assert!(false, "Memory was not obtained from this memory source");
+    }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.memory_source.decommit(non_zero_size, current_memory)
+    }
+}
+
+impl<MS: MemorySource> ChainedMemorySource<MS> {
+    /// Create a new instance.
+    ///
+    /// * `slab_size` is the size of memory obtained from `memory_source` at a time; larger requests obtain a slab just large enough.
+    /// * `sub_region_alignment` is the alignment of sub-regions, relative to the alignment of slabs obtained from `memory_source`; it must be a power of two.
+    ///
+    /// No memory is obtained until the first call to `obtain()`.
+    #[inline(always)]
+    pub fn new(
+        memory_source: MS,
+        slab_size: NonZeroUsize,
+        sub_region_alignment: NonZeroUsize,
+    ) -> Self {
+        debug_assert!(
+            sub_region_alignment.is_power_of_two(),
+            "sub_region_alignment `{}` must be a power of two",
+            sub_region_alignment
+        );
+
+        Self {
+            most_recent_slab: Cell::new(null_mut()),
+
+            slab_size,
+            sub_region_alignment,
+
+            memory_source,
+        }
+    }
+
+    #[cold]
+    fn obtain_from_new_slab(
+        &self,
+        non_zero_size: NonZeroUsize,
+    ) -> Result<MemoryAddress, AllocError> {
+        let header_size = self.header_size();
+        let slab_size = max(self.slab_size, non_zero_size.add(header_size));
+
+        let slab_memory = self.memory_source.obtain(slab_size)?;
+        let slab = slab_memory.as_ptr() as *mut Slab;
+
+        let mut older = self.most_recent_slab.get();
+        if !older.is_null() && unsafe { (*older).live_sub_regions } == 0 {
+            let even_older = unsafe { (*older).older };
+            self.release_slab(older);
+            older = even_older
+        }
+
+        unsafe {
+            slab.write(Slab {
+                older,
+                size: slab_size,
+                next_sub_region_at: header_size,
+                live_sub_regions: 0,
+            })
+        };
+        self.most_recent_slab.set(slab);
+
+        Ok(unsafe { &mut *slab }
+            .obtain_sub_region(non_zero_size)
+            .expect("A new slab is always large enough"))
+    }
+
+    #[inline(always)]
+    fn release_slab(&self, slab: *mut Slab) {
+        let size = unsafe { (*slab).size };
+        self.memory_source
+            .release(size, MemoryAddress::from_usize(slab as usize))
+    }
+
+    #[inline(always)]
+    fn header_size(&self) -> usize {
+        size_of::<Slab>().round_up_to_power_of_two(self.sub_region_alignment)
+    }
+}
+
+/// The header at the start of each slab; slabs form a singly-linked list, most recently obtained first.
+#[derive(Debug)]
+struct Slab {
+    older: *mut Slab,
+    size: NonZeroUsize,
+    next_sub_region_at: usize,
+    live_sub_regions: usize,
+}
+
+impl Slab {
+    #[inline(always)]
+    fn obtain_sub_region(&mut self, non_zero_size: NonZeroUsize) -> Option<MemoryAddress> {
+        let remaining = self.size.get() - self.next_sub_region_at;
+        if unlikely!(non_zero_size.get() > remaining) {
+            return None;
+        }
+
+        let sub_region = self.start().add(self.next_sub_region_at);
+        self.next_sub_region_at += non_zero_size.get();
+        self.live_sub_regions += 1;
+        Some(sub_region)
+    }
+
+    #[inline(always)]
+    fn contains(&self, current_memory: MemoryAddress) -> bool {
+        let start = self.start();
+        current_memory >= start && current_memory < start.add_non_zero(self.size)
+    }
+
+    #[inline(always)]
+    fn start(&self) -> MemoryAddress {
+        MemoryAddress::from_usize(self as *const Self as usize)
+    }
+}
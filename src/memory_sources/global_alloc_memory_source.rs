@@ -0,0 +1,94 @@
+use crate::extensions::non_zero_usize_ext::NonZeroUsizeExt;
+use crate::extensions::usize_ext::UsizeExt;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use std::alloc::{AllocError, GlobalAlloc, Layout, System};
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+/// A memory source which obtains memory from a `GlobalAlloc`, such as `System`, rather than by memory mapping.
+///
+/// This allows allocators to be used under Miri or valgrind, or in processes which forbid anonymous memory maps.
+/// As with a memory map, obtained memory is zeroed.
+/// Every region is aligned to the same alignment, so the `Layout` needed to release a region can be recreated from its size alone.
+pub struct GlobalAllocMemorySource<GA: GlobalAlloc> {
+    global_alloc: GA,
+    non_zero_power_of_two_alignment: NonZeroUsize,
+}
+
+impl<GA: GlobalAlloc> Debug for GlobalAllocMemorySource<GA> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "GlobalAllocMemorySource {{ non_zero_power_of_two_alignment: {} }}",
+            self.non_zero_power_of_two_alignment
+        )
+    }
+}
+
+impl Default for GlobalAllocMemorySource<System> {
+    #[inline(always)]
+    fn default() -> Self {
+        Self::new(System, Self::PAGE_SIZE.non_zero())
+    }
+}
+
+impl<GA: GlobalAlloc> Deref for GlobalAllocMemorySource<GA> {
+    type Target = GA;
+
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        &self.global_alloc
+    }
+}
+
+impl<GA: GlobalAlloc> MemorySource for GlobalAllocMemorySource<GA> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        let pointer = unsafe { self.global_alloc.alloc_zeroed(self.layout(non_zero_size)) };
+        NonNull::new(pointer).ok_or(AllocError)
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        unsafe {
+            self.global_alloc
+                .dealloc(current_memory.as_ptr(), self.layout(non_zero_size))
+        }
+    }
+}
+
+impl<GA: GlobalAlloc> GlobalAllocMemorySource<GA> {
+    /// The alignment used by `default()`, matching that of a memory map.
+    pub const PAGE_SIZE: usize = 4096;
+
+    /// Creates a new instance.
+    ///
+    /// `non_zero_power_of_two_alignment` is the alignment of every region obtained.
+    #[inline(always)]
+    pub fn new(global_alloc: GA, non_zero_power_of_two_alignment: NonZeroUsize) -> Self {
+        debug_assert!(
+            non_zero_power_of_two_alignment.is_power_of_two(),
+            "non_zero_power_of_two_alignment must be a power of two"
+        );
+
+        Self {
+            global_alloc,
+            non_zero_power_of_two_alignment,
+        }
+    }
+
+    #[inline(always)]
+    fn layout(&self, non_zero_size: NonZeroUsize) -> Layout {
+        unsafe {
+            Layout::from_size_align_unchecked(
+                non_zero_size.get(),
+                self.non_zero_power_of_two_alignment.get(),
+            )
+        }
+    }
+}
@@ -16,4 +16,15 @@ pub trait MemorySource: Debug {
     ///
     /// Alignment will be whatever is appropriate, but is likely to be quite large.
     fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress);
+
+    /// Hint that a range of obtained memory is unused, so that any physical pages backing it can be reclaimed.
+    ///
+    /// The range remains obtained and accessible, but its contents become undefined.
+    /// The range need not be page-aligned; only whole pages inside it are decommitted.
+    ///
+    /// Returns the number of bytes decommitted; the default implementation decommits nothing.
+    #[inline(always)]
+    fn decommit(&self, _non_zero_size: NonZeroUsize, _current_memory: MemoryAddress) -> usize {
+        0
+    }
 }
@@ -0,0 +1,43 @@
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use libc::{MADV_DONTNEED, MADV_FREE};
+
+/// How a memory map source advises the kernel about memory passed to `MemorySource::decommit()`.
+///
+/// Defaults to `DontNeed`.
+///
+/// On operating systems other than Android and Linux, decommit advice has no effect.
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum DecommitAdvice {
+    /// Equivalent to `MADV_DONTNEED`.
+    ///
+    /// Physical pages are discarded immediately, so resident set size (RSS) falls straight away; the next access faults in a zeroed page.
+    DontNeed,
+
+    /// Equivalent to `MADV_FREE`.
+    ///
+    /// Physical pages are discarded lazily, only when the kernel comes under memory pressure; cheaper than `DontNeed` if the memory is soon reused, but RSS does not fall until the pages are reclaimed.
+    ///
+    /// Since Linux 4.5.
+    Free,
+}
+
+impl Default for DecommitAdvice {
+    #[inline(always)]
+    fn default() -> Self {
+        DecommitAdvice::DontNeed
+    }
+}
+
+impl DecommitAdvice {
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    pub(crate) fn madvise_advice(self) -> i32 {
+        use self::DecommitAdvice::*;
+
+        match self {
+            DontNeed => MADV_DONTNEED,
+
+            Free => MADV_FREE,
+        }
+    }
+}
@@ -1,5 +1,6 @@
 #[cfg(any(target_os = "android", target_os = "linux"))]
 use libc::MAP_HUGETLB;
+use std::num::NonZeroUsize;
 
 /// Request that an allocation uses huge pages.
 ///
@@ -103,4 +104,36 @@ impl Default for HugePageSize {
 impl HugePageSize {
     #[cfg(any(target_os = "android", target_os = "linux"))]
     const MAP_HUGE_SHIFT: i32 = 26;
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    const MAP_HUGE_MASK: i32 = 0x3F;
+
+    /// Size in bytes of a huge page, or `None` for regular pages.
+    ///
+    /// `Default` is assumed to be `2Mb`, the default on x86-64.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    pub fn size(self) -> Option<NonZeroUsize> {
+        const DEFAULT_LOGARITHM_BASE2: i32 = 21;
+
+        match self {
+            HugePageSize::None => None,
+
+            _ => {
+                let logarithm_base2 =
+                    match ((self as i32) >> Self::MAP_HUGE_SHIFT) & Self::MAP_HUGE_MASK {
+                        0 => DEFAULT_LOGARITHM_BASE2,
+                        logarithm_base2 => logarithm_base2,
+                    };
+                NonZeroUsize::new(1 << logarithm_base2)
+            }
+        }
+    }
+
+    /// Size in bytes of a huge page, or `None` for regular pages.
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    #[inline(always)]
+    pub fn size(self) -> Option<NonZeroUsize> {
+        None
+    }
 }
@@ -0,0 +1,52 @@
+use std::ffi::CStr;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::io::Write;
+use std::os::raw::c_char;
+
+/// A label naming memory in `/proc/<pid>/maps` and `/proc/<pid>/smaps`; see `MemoryMapSource::set_label()`.
+///
+/// Held inline, so that labels can be made without using the global allocator.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct MemoryLabel([u8; MemoryLabel::MAXIMUM_LENGTH + 1]);
+
+impl Debug for MemoryLabel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.as_c_str().fmt(f)
+    }
+}
+
+impl MemoryLabel {
+    /// The kernel rejects longer labels.
+    pub const MAXIMUM_LENGTH: usize = 79;
+
+    /// Creates a new instance; bytes beyond `MAXIMUM_LENGTH` are truncated.
+    #[inline(always)]
+    pub fn new(label: &CStr) -> Self {
+        let bytes = label.to_bytes();
+        let mut this = Self([0; Self::MAXIMUM_LENGTH + 1]);
+        let length = bytes.len().min(Self::MAXIMUM_LENGTH);
+        this.0[..length].copy_from_slice(&bytes[..length]);
+        this
+    }
+
+    /// Labels the pool for a NUMA node, eg `numa node 1 pool`.
+    #[inline(always)]
+    pub(crate) fn numa_node_pool(numa_node: u16) -> Self {
+        let mut this = Self([0; Self::MAXIMUM_LENGTH + 1]);
+        write!(
+            &mut this.0[..Self::MAXIMUM_LENGTH],
+            "numa node {} pool",
+            numa_node
+        )
+        .expect("Label is shorter than MAXIMUM_LENGTH");
+        this
+    }
+
+    /// As a nul-terminated string.
+    #[inline(always)]
+    pub fn as_c_str(&self) -> &CStr {
+        unsafe { CStr::from_ptr(self.0.as_ptr() as *const c_char) }
+    }
+}
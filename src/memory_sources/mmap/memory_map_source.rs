@@ -6,6 +6,8 @@ use crate::memory_sources::mmap::prelude::*;
 #[cfg(unix)]
 use ::libc::*;
 use std::alloc::AllocError;
+use std::ffi::CStr;
+use std::io;
 use std::num::NonZeroUsize;
 use std::ptr::null_mut;
 
@@ -30,6 +32,12 @@ pub struct MemoryMapSource {
 
     #[cfg(any(target_os = "android", target_os = "linux"))]
     numa_settings: Option<NumaSettings>,
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    decommit_advice: DecommitAdvice,
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    label: Option<MemoryLabel>,
 }
 
 impl Default for MemoryMapSource {
@@ -49,6 +57,11 @@ impl MemorySource for MemoryMapSource {
     fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
         Self::munmap_memory(current_memory, non_zero_size.get())
     }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.madvise_decommit_memory(current_memory, non_zero_size.get())
+    }
 }
 
 impl MemoryMapSource {
@@ -84,9 +97,102 @@ impl MemoryMapSource {
             madvise_flags: Self::madvise_flags(huge_page_size),
             #[cfg(any(target_os = "android", target_os = "linux"))]
             numa_settings,
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            decommit_advice: DecommitAdvice::default(),
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            label: None,
+        }
+    }
+
+    /// Change how memory passed to `MemorySource::decommit()` is advised to the kernel.
+    ///
+    /// On operating systems other than Android and Linux, this has no effect.
+    #[allow(unused_variables)]
+    #[inline(always)]
+    pub fn set_decommit_advice(&mut self, decommit_advice: DecommitAdvice) {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            self.decommit_advice = decommit_advice
+        }
+    }
+
+    /// Label memory obtained so that it is named in `/proc/<pid>/maps` and `/proc/<pid>/smaps`, eg as `[anon:thread-local bump]`, so resident set size (RSS) can be attributed.
+    ///
+    /// A label may be at most `MemoryLabel::MAXIMUM_LENGTH` bytes, and is truncated if longer; it may not contain `[`, `]`, `\`, `$` or `` ` ``.
+    ///
+    /// Needs Linux 5.17 or later with `CONFIG_ANON_VMA_NAME`; on older kernels memory is left unnamed.
+    /// On operating systems other than Android and Linux, this has no effect.
+    #[allow(unused_variables)]
+    #[inline(always)]
+    pub fn set_label(&mut self, label: Option<&CStr>) {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            self.label = label.map(MemoryLabel::new)
+        }
+    }
+
+    /// The label memory obtained is named with, if any.
+    #[inline(always)]
+    pub fn label(&self) -> Option<MemoryLabel> {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            self.label
+        }
+
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        None
+    }
+
+    /// Names `size` bytes of memory from `memory_address` using `prctl(PR_SET_VMA, PR_SET_VMA_ANON_NAME)`, or removes its name if `label` is `None`.
+    ///
+    /// Fails if the kernel does not support naming memory, or if `label` is not valid.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    pub fn label_memory(
+        memory_address: MemoryAddress,
+        size: usize,
+        label: Option<MemoryLabel>,
+    ) -> io::Result<()> {
+        const PR_SET_VMA: c_int = 0x53564D41;
+        const PR_SET_VMA_ANON_NAME: c_ulong = 0;
+
+        let label = match label {
+            None => std::ptr::null(),
+            Some(ref label) => label.as_c_str().as_ptr(),
+        };
+        let result = unsafe {
+            prctl(
+                PR_SET_VMA,
+                PR_SET_VMA_ANON_NAME,
+                memory_address.as_ptr() as c_ulong,
+                size as c_ulong,
+                label as c_ulong,
+            )
+        };
+        if likely!(result == 0) {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
         }
     }
 
+    /// On operating systems other than Android and Linux, memory can not be named, so this always fails.
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    #[inline(always)]
+    pub fn label_memory(
+        _memory_address: MemoryAddress,
+        _size: usize,
+        _label: Option<MemoryLabel>,
+    ) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// The system page size.
+    #[inline(always)]
+    pub(crate) fn page_size() -> NonZeroUsize {
+        (unsafe { sysconf(_SC_PAGESIZE) } as usize).non_zero()
+    }
+
     /// Configure with NUMA settings passed down
     #[inline(always)]
     pub fn with_numa_settings(ns: NumaSettings) -> Self {
@@ -121,6 +227,14 @@ impl MemoryMapSource {
             //     Err(_) => Err(AllocError),
             // };
 
+            // Kernels without support leave memory unnamed.
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            {
+                if self.label.is_some() {
+                    let _ = Self::label_memory(Self::cast_address(result), size, self.label);
+                }
+            }
+
             #[cfg(any(target_os = "android", target_os = "linux"))]
             match std::panic::catch_unwind(|| self.numa_memory(result, size)) {
                 Ok(_) => (),
@@ -169,6 +283,50 @@ impl MemoryMapSource {
         }
     }
 
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    fn madvise_decommit_memory(&self, current_memory: MemoryAddress, size: usize) -> usize {
+        Self::decommit_whole_pages(current_memory, size, self.decommit_advice)
+    }
+
+    /// Only whole pages within `size` bytes from `current_memory` are decommitted; returns the number of bytes decommitted.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    pub(crate) fn decommit_whole_pages(
+        current_memory: MemoryAddress,
+        size: usize,
+        decommit_advice: DecommitAdvice,
+    ) -> usize {
+        let page_size = Self::page_size();
+        let from = current_memory
+            .to_usize()
+            .round_up_to_power_of_two(page_size);
+        let to = (current_memory.to_usize() + size).round_down_to_power_of_two(page_size);
+        if unlikely!(to <= from) {
+            return 0;
+        }
+
+        let length = to - from;
+        let result = unsafe {
+            madvise(
+                from as *mut c_void,
+                length,
+                decommit_advice.madvise_advice(),
+            )
+        };
+        if likely!(result == 0) {
+            length
+        } else {
+            0
+        }
+    }
+
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    #[inline(always)]
+    fn madvise_decommit_memory(&self, _current_memory: MemoryAddress, _size: usize) -> usize {
+        0
+    }
+
     #[cfg(not(any(target_os = "android", target_os = "netbsd", target_os = "linux")))]
     #[inline(always)]
     fn mlock_memory(&self, address: *mut c_void, size: usize) -> Result<(), AllocError> {
@@ -324,7 +482,7 @@ impl MemoryMapSource {
 
     #[cfg(any(target_os = "android", target_os = "linux"))]
     #[inline(always)]
-    fn madvise_flags(huge_page_size: HugePageSize) -> i32 {
+    pub(crate) fn madvise_flags(huge_page_size: HugePageSize) -> i32 {
         const MADVISE_FLAGS: i32 = MADV_DONTDUMP;
 
         if huge_page_size != HugePageSize::None {
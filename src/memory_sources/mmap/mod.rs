@@ -1,11 +1,17 @@
+pub mod decommit_advice;
 pub mod huge_page_size;
+pub mod memory_label;
 pub mod memory_map_source;
+pub mod reserved_address_space_source;
 
 /// NUMA memory mapping.
 pub mod numa;
 
 pub mod prelude {
+    pub use super::decommit_advice::*;
     pub use super::huge_page_size::*;
+    pub use super::memory_label::*;
     pub use super::memory_map_source::*;
     pub use super::numa::prelude::*;
+    pub use super::reserved_address_space_source::*;
 }
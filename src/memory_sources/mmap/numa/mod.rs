@@ -2,11 +2,25 @@
 use super::*;
 
 pub mod numa_allocation_policy;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub mod numa_kernel_support;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub mod numa_migration;
 pub mod numa_node_bit_set;
 pub mod numa_settings;
+pub mod parse_numa_node_bit_set_error;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub mod thread_memory_policy_guard;
 
 pub mod prelude {
     pub use super::numa_allocation_policy::*;
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    pub use super::numa_kernel_support::*;
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    pub use super::numa_migration::*;
     pub use super::numa_node_bit_set::*;
     pub use super::numa_settings::*;
+    pub use super::parse_numa_node_bit_set_error::*;
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    pub use super::thread_memory_policy_guard::*;
 }
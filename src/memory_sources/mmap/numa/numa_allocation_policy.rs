@@ -1,4 +1,15 @@
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use crate::memory_sources::mmap::numa::numa_kernel_support::NumaKernelSupport;
+use crate::memory_sources::mmap::numa::numa_node_bit_set::NodeMask;
 use crate::memory_sources::mmap::numa::numa_node_bit_set::NumaNodeBitSet;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use crate::memory_sources::mmap::numa::thread_memory_policy_guard::ThreadMemoryPolicyGuard;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use libc::{c_long, syscall, SYS_get_mempolicy, SYS_set_mempolicy};
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::io;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::ptr::{null, null_mut};
 
 /// Defaults to `Default`.
 ///
@@ -53,6 +64,20 @@ pub enum NumaAllocationPolicy {
     ///
     /// Since Linux 3.8.
     Local,
+
+    /// `MPOL_PREFERRED_MANY`.
+    ///
+    /// Like `Preferred`, but the kernel tries to allocate pages from any of the nodes in the NUMA node bit set before falling back to other nodes.
+    ///
+    /// Since Linux 5.15; if the kernel does not support it, falls back to `Preferred` with the first node in the set.
+    PreferredMany(NumaNodeBitSet),
+
+    /// `MPOL_WEIGHTED_INTERLEAVE`.
+    ///
+    /// Like `Interleave`, but pages are interleaved across the nodes in the NUMA node bit set in proportion to per-node weights, which are set in `/sys/kernel/mm/mempolicy/weighted_interleave/`.
+    ///
+    /// Since Linux 6.9; if the kernel does not support it, falls back to `Interleave`.
+    WeightedInterleave(NumaNodeBitSet),
 }
 
 impl Default for NumaAllocationPolicy {
@@ -63,9 +88,25 @@ impl Default for NumaAllocationPolicy {
 }
 
 impl NumaAllocationPolicy {
+    /// The NUMA nodes of this policy, if it has any.
+    #[inline(always)]
+    pub fn numa_node_bit_set(&self) -> Option<&NumaNodeBitSet> {
+        use self::NumaAllocationPolicy::*;
+
+        match *self {
+            Default | Local => None,
+
+            Preferred(ref numa_node_bit_set)
+            | Bind(ref numa_node_bit_set)
+            | Interleave(ref numa_node_bit_set)
+            | PreferredMany(ref numa_node_bit_set)
+            | WeightedInterleave(ref numa_node_bit_set) => Some(numa_node_bit_set),
+        }
+    }
+
     #[cfg(any(target_os = "android", target_os = "linux"))]
     #[inline(always)]
-    pub(crate) fn values(&self) -> (i32, (i32, Option<usize>, usize)) {
+    pub(crate) fn values(&self) -> (i32, (i32, Option<NodeMask>, usize)) {
         use self::NumaAllocationPolicy::*;
 
         match *self {
@@ -78,6 +119,235 @@ impl NumaAllocationPolicy {
             Interleave(ref numa_node_bit_set) => (3, numa_node_bit_set.mask_and_size()),
 
             Local => (4, NumaNodeBitSet::NO_MODE_FLAGS_NODEMASK_MAXNODE),
+
+            PreferredMany(ref numa_node_bit_set) => {
+                (Self::MPOL_PREFERRED_MANY, numa_node_bit_set.mask_and_size())
+            }
+
+            WeightedInterleave(ref numa_node_bit_set) => (
+                Self::MPOL_WEIGHTED_INTERLEAVE,
+                numa_node_bit_set.mask_and_size(),
+            ),
+        }
+    }
+}
+
+#[cfg(any(target_os = "android", target_os = "linux"))]
+impl NumaAllocationPolicy {
+    const MPOL_F_STATIC_NODES: i32 = 1 << 15;
+
+    const MPOL_F_RELATIVE_NODES: i32 = 1 << 14;
+
+    pub(crate) const MPOL_F_NUMA_BALANCING: i32 = 1 << 13;
+
+    pub(crate) const MPOL_BIND: i32 = 2;
+
+    pub(crate) const MPOL_PREFERRED_MANY: i32 = 5;
+
+    pub(crate) const MPOL_WEIGHTED_INTERLEAVE: i32 = 6;
+
+    /// Does the running kernel support this policy's mode and, if NUMA balancing is requested, the `MPOL_F_NUMA_BALANCING` flag?
+    #[inline(always)]
+    pub fn is_supported_by_kernel(&self) -> bool {
+        self.is_supported_by(&NumaKernelSupport::of_running_kernel())
+    }
+
+    /// Does a kernel with `kernel_support` support this policy's mode and, if NUMA balancing is requested, the `MPOL_F_NUMA_BALANCING` flag?
+    #[inline(always)]
+    pub fn is_supported_by(&self, kernel_support: &NumaKernelSupport) -> bool {
+        use self::NumaAllocationPolicy::*;
+
+        let mode_is_supported = match *self {
+            PreferredMany(_) => kernel_support.preferred_many,
+
+            WeightedInterleave(_) => kernel_support.weighted_interleave,
+
+            _ => true,
+        };
+
+        mode_is_supported
+            && (!self.requests_numa_balancing()
+                || self.numa_balancing_is_supported_by(kernel_support))
+    }
+
+    /// Returns this policy if the running kernel supports it, otherwise its fallback; see `or_fallback_for()`.
+    #[inline(always)]
+    pub fn or_fallback(&self) -> Self {
+        self.or_fallback_for(&NumaKernelSupport::of_running_kernel())
+    }
+
+    /// Returns this policy if a kernel with `kernel_support` supports it, otherwise its fallback.
+    ///
+    /// * `PreferredMany` falls back to `Preferred` with the first node in the set.
+    /// * `WeightedInterleave` falls back to `Interleave`.
+    /// * `NumaNodeBitSet::numa_balancing` is turned off unless the mode is `Bind`, or `PreferredMany`, and the kernel supports it with that mode.
+    #[inline(always)]
+    pub fn or_fallback_for(&self, kernel_support: &NumaKernelSupport) -> Self {
+        use self::NumaAllocationPolicy::*;
+
+        let mut policy = *self;
+        if unlikely!(
+            policy.requests_numa_balancing()
+                && !policy.numa_balancing_is_supported_by(kernel_support)
+        ) {
+            if let Some(numa_node_bit_set) = policy.numa_node_bit_set_mut() {
+                numa_node_bit_set.numa_balancing = false
+            }
+        }
+
+        match policy {
+            PreferredMany(numa_node_bit_set) if unlikely!(!kernel_support.preferred_many) => {
+                let mut first_node_only = NumaNodeBitSet {
+                    bits: [0; NumaNodeBitSet::NUMBER_OF_WORDS],
+                    numa_balancing: false,
+                    ..numa_node_bit_set
+                };
+                if let Some(first) = numa_node_bit_set.first() {
+                    first_node_only.insert_numa_node(first)
+                }
+                Preferred(first_node_only)
+            }
+
+            WeightedInterleave(numa_node_bit_set)
+                if unlikely!(!kernel_support.weighted_interleave) =>
+            {
+                Interleave(numa_node_bit_set)
+            }
+
+            _ => policy,
+        }
+    }
+
+    #[inline(always)]
+    fn requests_numa_balancing(&self) -> bool {
+        self.numa_node_bit_set()
+            .map(|numa_node_bit_set| numa_node_bit_set.numa_balancing)
+            .unwrap_or(false)
+    }
+
+    #[inline(always)]
+    fn numa_node_bit_set_mut(&mut self) -> Option<&mut NumaNodeBitSet> {
+        use self::NumaAllocationPolicy::*;
+
+        match *self {
+            Default | Local => None,
+
+            Preferred(ref mut numa_node_bit_set)
+            | Bind(ref mut numa_node_bit_set)
+            | Interleave(ref mut numa_node_bit_set)
+            | PreferredMany(ref mut numa_node_bit_set)
+            | WeightedInterleave(ref mut numa_node_bit_set) => Some(numa_node_bit_set),
+        }
+    }
+
+    /// The kernel only accepts `MPOL_F_NUMA_BALANCING` with `MPOL_BIND` (since Linux 5.12) and, on newer kernels, `MPOL_PREFERRED_MANY`.
+    #[inline(always)]
+    fn numa_balancing_is_supported_by(&self, kernel_support: &NumaKernelSupport) -> bool {
+        use self::NumaAllocationPolicy::*;
+
+        match *self {
+            Bind(_) => kernel_support.bind_numa_balancing,
+
+            PreferredMany(_) => kernel_support.preferred_many_numa_balancing,
+
+            _ => false,
+        }
+    }
+
+    /// Makes this the memory policy of the current thread, using `set_mempolicy()`, or, if the kernel does not support it, its fallback (see `or_fallback()`).
+    ///
+    /// All memory the thread subsequently faults in, including memory from `System`, is allocated according to this policy, unless memory has its own policy (see `NumaSettings`).
+    #[inline(always)]
+    pub fn set_for_current_thread(&self) -> io::Result<()> {
+        let (policy, (mode_flags, nodemask, maxnode)) = self.or_fallback().values();
+        let nodemask = match nodemask {
+            None => null(),
+            Some(ref nodemask) => nodemask.as_ptr(),
+        };
+
+        let result = unsafe { syscall(SYS_set_mempolicy, policy | mode_flags, nodemask, maxnode) };
+        Self::result(result)
+    }
+
+    /// Makes this the memory policy of the current thread until the returned guard is dropped, when the previous memory policy is restored.
+    #[inline(always)]
+    pub fn use_for_current_thread(&self) -> io::Result<ThreadMemoryPolicyGuard> {
+        ThreadMemoryPolicyGuard::new(self)
+    }
+
+    /// The memory policy of the current thread, using `get_mempolicy()`.
+    ///
+    /// `Preferred` with no nodes is reported as `Local`, which it is equivalent to.
+    #[inline(always)]
+    pub fn of_current_thread() -> io::Result<Self> {
+        let mut mode: i32 = 0;
+        let mut nodemask: NodeMask = [0; NumaNodeBitSet::NUMBER_OF_WORDS];
+        const NO_FLAGS: usize = 0;
+
+        let result = unsafe {
+            syscall(
+                SYS_get_mempolicy,
+                &mut mode as *mut i32,
+                nodemask.as_mut_ptr(),
+                NumaNodeBitSet::MAXIMUM_NUMBER_OF_NODES + 1,
+                null_mut::<u8>(),
+                NO_FLAGS,
+            )
+        };
+        Self::result(result)?;
+
+        Self::from_values(mode, nodemask).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported memory policy mode {}", mode),
+            )
+        })
+    }
+
+    /// The inverse of `values()`.
+    #[inline(always)]
+    pub(crate) fn from_values(mode: i32, nodemask: NodeMask) -> Option<Self> {
+        use self::NumaAllocationPolicy::*;
+
+        let numa_node_bit_set = NumaNodeBitSet {
+            bits: nodemask,
+            static_nodes: mode & Self::MPOL_F_STATIC_NODES != 0,
+            relative_nodes: mode & Self::MPOL_F_RELATIVE_NODES != 0,
+            numa_balancing: mode & Self::MPOL_F_NUMA_BALANCING != 0,
+        };
+
+        match mode
+            & !(Self::MPOL_F_STATIC_NODES
+                | Self::MPOL_F_RELATIVE_NODES
+                | Self::MPOL_F_NUMA_BALANCING)
+        {
+            0 => Some(Default),
+
+            // Older kernels report `MPOL_LOCAL` as `MPOL_PREFERRED` with no nodes.
+            1 if numa_node_bit_set.is_empty() => Some(Local),
+
+            1 => Some(Preferred(numa_node_bit_set)),
+
+            2 => Some(Bind(numa_node_bit_set)),
+
+            3 => Some(Interleave(numa_node_bit_set)),
+
+            4 => Some(Local),
+
+            Self::MPOL_PREFERRED_MANY => Some(PreferredMany(numa_node_bit_set)),
+
+            Self::MPOL_WEIGHTED_INTERLEAVE => Some(WeightedInterleave(numa_node_bit_set)),
+
+            _ => None,
+        }
+    }
+
+    #[inline(always)]
+    fn result(result: c_long) -> io::Result<()> {
+        if likely!(result == 0) {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
         }
     }
 }
@@ -0,0 +1,130 @@
+use crate::memory_sources::mmap::numa::numa_allocation_policy::NumaAllocationPolicy;
+use crate::memory_sources::mmap::numa::numa_node_bit_set::NumaNodeBitSet;
+use libc::{
+    mmap, munmap, syscall, SYS_mbind, MAP_ANONYMOUS, MAP_FAILED, MAP_PRIVATE, PROT_READ, PROT_WRITE,
+};
+use std::ptr::null_mut;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering::Relaxed;
+
+const UNKNOWN: u8 = 0;
+
+const SUPPORTED: u8 = 1;
+
+const UNSUPPORTED: u8 = 2;
+
+static PREFERRED_MANY_IS_SUPPORTED: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+static WEIGHTED_INTERLEAVE_IS_SUPPORTED: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+static BIND_NUMA_BALANCING_IS_SUPPORTED: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+static PREFERRED_MANY_NUMA_BALANCING_IS_SUPPORTED: AtomicU8 = AtomicU8::new(UNKNOWN);
+
+/// Which of the newer NUMA memory policy modes and flags a kernel supports.
+///
+/// Decides which fallback, if any, `NumaAllocationPolicy::or_fallback_for()` chooses; construct one directly to find out what happens on another kernel.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NumaKernelSupport {
+    /// `MPOL_PREFERRED_MANY`, since Linux 5.15.
+    pub preferred_many: bool,
+
+    /// `MPOL_WEIGHTED_INTERLEAVE`, since Linux 6.9.
+    pub weighted_interleave: bool,
+
+    /// `MPOL_F_NUMA_BALANCING` with `MPOL_BIND`, since Linux 5.12.
+    pub bind_numa_balancing: bool,
+
+    /// `MPOL_F_NUMA_BALANCING` with `MPOL_PREFERRED_MANY`.
+    pub preferred_many_numa_balancing: bool,
+}
+
+impl NumaKernelSupport {
+    /// What the running kernel supports.
+    ///
+    /// Support is probed once, by trying each mode on a scratch memory mapping, and then remembered.
+    #[inline(always)]
+    pub fn of_running_kernel() -> Self {
+        Self {
+            preferred_many: Self::kernel_supports(
+                &PREFERRED_MANY_IS_SUPPORTED,
+                NumaAllocationPolicy::MPOL_PREFERRED_MANY,
+            ),
+            weighted_interleave: Self::kernel_supports(
+                &WEIGHTED_INTERLEAVE_IS_SUPPORTED,
+                NumaAllocationPolicy::MPOL_WEIGHTED_INTERLEAVE,
+            ),
+            bind_numa_balancing: Self::kernel_supports(
+                &BIND_NUMA_BALANCING_IS_SUPPORTED,
+                NumaAllocationPolicy::MPOL_BIND | NumaAllocationPolicy::MPOL_F_NUMA_BALANCING,
+            ),
+            preferred_many_numa_balancing: Self::kernel_supports(
+                &PREFERRED_MANY_NUMA_BALANCING_IS_SUPPORTED,
+                NumaAllocationPolicy::MPOL_PREFERRED_MANY
+                    | NumaAllocationPolicy::MPOL_F_NUMA_BALANCING,
+            ),
+        }
+    }
+
+    #[inline(always)]
+    fn kernel_supports(is_supported: &AtomicU8, mode: i32) -> bool {
+        match is_supported.load(Relaxed) {
+            SUPPORTED => true,
+
+            UNSUPPORTED => false,
+
+            _ => {
+                let supported = Self::probe(mode);
+                is_supported.store(if supported { SUPPORTED } else { UNSUPPORTED }, Relaxed);
+                supported
+            }
+        }
+    }
+
+    /// Only a successful `mbind()` counts as support; the probe binds to the first online NUMA node, or, if that can not be read, node 0.
+    #[cold]
+    fn probe(mode: i32) -> bool {
+        const UNUSED_FILE_DESCRIPTOR: i32 = -1;
+        const NO_OFFSET: i64 = 0;
+        const PROBE_SIZE: usize = 4096;
+        const NO_FLAGS: u32 = 0;
+
+        let probe_numa_node = NumaNodeBitSet::online()
+            .ok()
+            .and_then(|online| online.first())
+            .unwrap_or(0);
+        let (_, nodemask, maxnode) = NumaNodeBitSet::new()
+            .with_numa_node(probe_numa_node)
+            .mask_and_size();
+        let nodemask = nodemask.unwrap();
+
+        let probe = unsafe {
+            mmap(
+                null_mut(),
+                PROBE_SIZE,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                UNUSED_FILE_DESCRIPTOR,
+                NO_OFFSET,
+            )
+        };
+        if unlikely!(probe == MAP_FAILED) {
+            return false;
+        }
+
+        let result = unsafe {
+            syscall(
+                SYS_mbind,
+                probe,
+                PROBE_SIZE,
+                mode,
+                nodemask.as_ptr(),
+                maxnode,
+                NO_FLAGS,
+            )
+        };
+
+        unsafe { munmap(probe, PROBE_SIZE) };
+        result == 0
+    }
+}
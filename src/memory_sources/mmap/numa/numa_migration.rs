@@ -0,0 +1,193 @@
+use crate::allocators::global::memory_range::MemoryRange;
+use crate::extensions::prelude::*;
+use crate::memory_sources::mmap::memory_map_source::MemoryMapSource;
+use crate::memory_sources::mmap::numa::numa_allocation_policy::NumaAllocationPolicy;
+use libc::{c_int, c_void, syscall, SYS_mbind, SYS_move_pages, ENOENT};
+use std::io;
+use std::ptr::null;
+
+/// What happened to each page when migrating memory to another NUMA node.
+///
+/// Only pages which have been faulted in can be migrated; pages which have not are counted as `not_present`.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct NumaMigrationReport {
+    /// Pages now on a different NUMA node.
+    pub moved: usize,
+
+    /// Pages which could not be moved, eg because they are shared with another process or are locked.
+    pub failed: usize,
+
+    /// Pages which were already on a target NUMA node.
+    pub already_in_place: usize,
+
+    /// Pages which have not been faulted in.
+    pub not_present: usize,
+}
+
+/// Migrates the pages of `memory_range` (for example, an allocator's `memory_range()`) by binding it to `policy` with `mbind(MPOL_MF_MOVE)`.
+///
+/// The range keeps `policy`, so memory subsequently faulted in within it is also placed according to `policy`.
+/// `memory_range` is widened to whole pages, so any other allocations sharing its first or last page are also moved and keep `policy`; pass a page-aligned range, such as an allocator's `memory_range()`, to avoid this.
+///
+/// Pages are only counted as `failed` if `policy` has NUMA nodes and a page is not on one of them afterwards.
+#[inline(always)]
+pub fn migrate_by_rebinding(
+    memory_range: MemoryRange,
+    policy: &NumaAllocationPolicy,
+) -> io::Result<NumaMigrationReport> {
+    const MPOL_MF_MOVE: u32 = 1 << 1;
+
+    let (from, length) = whole_pages(memory_range);
+    let number_of_pages = length >> MemoryMapSource::page_size().logarithm_base2();
+    let targets = policy
+        .numa_node_bit_set()
+        .filter(|targets| !targets.is_empty());
+
+    let mut before = vec![0; number_of_pages];
+    query_numa_nodes(from, &mut before)?;
+
+    let (mode, (mode_flags, nodemask, maxnode)) = policy.or_fallback().values();
+    let nodemask = match nodemask {
+        None => null(),
+        Some(ref nodemask) => nodemask.as_ptr(),
+    };
+    let result = unsafe {
+        syscall(
+            SYS_mbind,
+            from,
+            length,
+            mode | mode_flags,
+            nodemask,
+            maxnode,
+            MPOL_MF_MOVE,
+        )
+    };
+    if unlikely!(result != 0) {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut after = vec![0; number_of_pages];
+    query_numa_nodes(from, &mut after)?;
+
+    let on_target = |numa_node: c_int| match targets {
+        None => true,
+        Some(targets) => targets.contains(numa_node as u16),
+    };
+
+    let mut report = NumaMigrationReport::default();
+    for (&before, &after) in before.iter().zip(after.iter()) {
+        if before < 0 || after < 0 {
+            report.not_present += 1;
+            continue;
+        }
+
+        if !on_target(after) {
+            report.failed += 1
+        } else if before == after {
+            report.already_in_place += 1
+        } else {
+            report.moved += 1
+        }
+    }
+    Ok(report)
+}
+
+/// Migrates the pages of `memory_range` (for example, an allocator's `memory_range()`) to `numa_node` with `move_pages()`.
+///
+/// Unlike `migrate_by_rebinding()`, the range's memory policy is unchanged.
+/// `memory_range` is widened to whole pages, so any other allocations sharing its first or last page are also moved.
+#[inline(always)]
+pub fn migrate_by_moving_pages(
+    memory_range: MemoryRange,
+    numa_node: u16,
+) -> io::Result<NumaMigrationReport> {
+    let (from, length) = whole_pages(memory_range);
+    let page_size = MemoryMapSource::page_size();
+    let number_of_pages = length >> page_size.logarithm_base2();
+
+    let mut before = vec![0; number_of_pages];
+    query_numa_nodes(from, &mut before)?;
+
+    let mut report = NumaMigrationReport::default();
+    let mut pages = Vec::with_capacity(number_of_pages);
+    for (page_index, &before) in before.iter().enumerate() {
+        if before < 0 {
+            report.not_present += 1
+        } else if before == numa_node as c_int {
+            report.already_in_place += 1
+        } else {
+            pages.push((from + (page_index << page_size.logarithm_base2())) as *mut c_void)
+        }
+    }
+    if pages.is_empty() {
+        return Ok(report);
+    }
+
+    let numa_nodes = vec![numa_node as c_int; pages.len()];
+    let mut status = vec![0; pages.len()];
+    move_pages(&pages, numa_nodes.as_ptr(), &mut status)?;
+
+    for &status in status.iter() {
+        if status == numa_node as c_int {
+            report.moved += 1
+        } else if status == -ENOENT {
+            report.not_present += 1
+        } else {
+            report.failed += 1
+        }
+    }
+    Ok(report)
+}
+
+/// Fills `numa_nodes` with the NUMA node of each page from `from`, or a negative error number, such as `-ENOENT` for a page which is not present.
+#[inline(always)]
+fn query_numa_nodes(from: usize, numa_nodes: &mut [c_int]) -> io::Result<()> {
+    let page_size = MemoryMapSource::page_size();
+    let pages: Vec<*mut c_void> = (0..numa_nodes.len())
+        .map(|page_index| (from + (page_index << page_size.logarithm_base2())) as *mut c_void)
+        .collect();
+    move_pages(&pages, null(), numa_nodes)
+}
+
+/// With a null `numa_nodes`, only queries.
+#[inline(always)]
+fn move_pages(
+    pages: &[*mut c_void],
+    numa_nodes: *const c_int,
+    status: &mut [c_int],
+) -> io::Result<()> {
+    const CURRENT_PROCESS: c_int = 0;
+    const NO_FLAGS: c_int = 0;
+
+    let result = unsafe {
+        syscall(
+            SYS_move_pages,
+            CURRENT_PROCESS,
+            pages.len(),
+            pages.as_ptr(),
+            numa_nodes,
+            status.as_mut_ptr(),
+            NO_FLAGS,
+        )
+    };
+    // A positive result is the number of pages which could not be moved; their status says why.
+    if likely!(result >= 0) {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[inline(always)]
+fn whole_pages(memory_range: MemoryRange) -> (usize, usize) {
+    let page_size = MemoryMapSource::page_size();
+    let from = memory_range
+        .from
+        .to_usize()
+        .round_down_to_power_of_two(page_size);
+    let to = memory_range
+        .to
+        .to_usize()
+        .round_up_to_power_of_two(page_size);
+    (from, to - from)
+}
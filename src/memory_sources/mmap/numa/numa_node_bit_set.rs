@@ -1,11 +1,32 @@
+use crate::memory_sources::mmap::numa::parse_numa_node_bit_set_error::ParseNumaNodeBitSetError;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::fs::File;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::io;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::io::Read;
+use std::mem::size_of;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use std::str::from_utf8;
+use std::str::FromStr;
+
+/// A node mask, as passed to `mbind()`, `set_mempolicy()` and the like.
+pub type NodeMask = [usize; NumaNodeBitSet::NUMBER_OF_WORDS];
+
 /// NUMA nodes to allocate on.
 ///
 /// If set to no nodes (the `Default::default()`) then memory is allocated on the local node if possible.
 ///
+/// Can hold any node up to the kernel's largest `MAX_NUMNODES`, `1024`.
+/// Parses from and formats to the list syntax used by sysfs and `numactl`, eg `0-3,7,9-11`.
+///
 /// Ignored on operating systems other than Android and Linux.
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[derive(Debug, Default, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct NumaNodeBitSet {
-    pub(crate) bits: usize,
+    pub(crate) bits: NodeMask,
 
     /// Specifies physical node IDs.
     ///
@@ -18,19 +39,103 @@ pub struct NumaNodeBitSet {
     ///
     /// (Not used if there are no nodes specified).
     pub relative_nodes: bool,
+
+    /// Enables NUMA balancing, so that the kernel migrates pages towards the node of the CPUs accessing them, within the nodes in the set.
+    ///
+    /// Only valid with `NumaAllocationPolicy::Bind` (since Linux 5.12) and `NumaAllocationPolicy::PreferredMany`; ignored if the kernel does not support it.
+    ///
+    /// (Not used if there are no nodes specified).
+    pub numa_balancing: bool,
+}
+
+impl Display for NumaNodeBitSet {
+    /// Formats as, eg, `0-3,7,9-11`; the empty set is formatted as nothing.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut nodes = self.iter().peekable();
+        let mut first = true;
+        while let Some(from) = nodes.next() {
+            let mut to = from;
+            while nodes.peek() == Some(&(to + 1)) {
+                to = nodes.next().unwrap();
+            }
+
+            if !first {
+                write!(f, ",")?;
+            }
+            first = false;
+
+            if from == to {
+                write!(f, "{}", from)?;
+            } else {
+                write!(f, "{}-{}", from, to)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NumaNodeBitSet {
+    type Err = ParseNumaNodeBitSetError;
+
+    /// Parses, eg, `0-3,7,9-11`, as found in `/sys/devices/system/node/online`; surrounding whitespace, such as a trailing line feed, is ignored.
+    #[inline(always)]
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        use self::ParseNumaNodeBitSetError::*;
+
+        let mut numa_node_bit_set = Self::new();
+
+        let list = list.trim();
+        if list.is_empty() {
+            return Ok(numa_node_bit_set);
+        }
+
+        for item in list.split(',') {
+            let (from, to) = match item.find('-') {
+                None => {
+                    let node = Self::parse_node(item)?;
+                    (node, node)
+                }
+
+                Some(index) => (
+                    Self::parse_node(&item[..index])?,
+                    Self::parse_node(&item[(index + 1)..])?,
+                ),
+            };
+            if unlikely!(from > to) {
+                return Err(DescendingRange { from, to });
+            }
+
+            for node in from..=to {
+                numa_node_bit_set.insert_numa_node(node)
+            }
+        }
+        Ok(numa_node_bit_set)
+    }
 }
 
 impl NumaNodeBitSet {
+    /// The largest number of NUMA nodes Linux supports (`MAX_NUMNODES` with `CONFIG_NODES_SHIFT` of 10).
+    pub const MAXIMUM_NUMBER_OF_NODES: usize = 1024;
+
+    /// Longer than the longest list possible, every other node of `MAXIMUM_NUMBER_OF_NODES`, which is 2,005 bytes.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    const MAXIMUM_LIST_LENGTH: usize = 4096;
+
+    const BITS_PER_WORD: usize = size_of::<usize>() * 8;
+
+    pub(crate) const NUMBER_OF_WORDS: usize = Self::MAXIMUM_NUMBER_OF_NODES / Self::BITS_PER_WORD;
+
     #[allow(dead_code)]
-    pub const NO_MODE_FLAGS_NODEMASK_MAXNODE: (i32, Option<usize>, usize) = (0, None, 0);
+    pub const NO_MODE_FLAGS_NODEMASK_MAXNODE: (i32, Option<NodeMask>, usize) = (0, None, 0);
 
     /// Generate an empty struct
     #[inline(always)]
     pub fn new() -> Self {
         NumaNodeBitSet {
-            bits: 0,
+            bits: [0; Self::NUMBER_OF_WORDS],
             static_nodes: false,
             relative_nodes: false,
+            numa_balancing: false,
         }
     }
 
@@ -38,9 +143,10 @@ impl NumaNodeBitSet {
     #[inline(always)]
     pub fn new_static() -> Self {
         NumaNodeBitSet {
-            bits: 0,
+            bits: [0; Self::NUMBER_OF_WORDS],
             static_nodes: true,
             relative_nodes: false,
+            numa_balancing: false,
         }
     }
 
@@ -48,38 +154,139 @@ impl NumaNodeBitSet {
     #[inline(always)]
     pub fn new_relative(&self) -> Self {
         NumaNodeBitSet {
-            bits: 0,
+            bits: [0; Self::NUMBER_OF_WORDS],
             static_nodes: false,
             relative_nodes: true,
+            numa_balancing: false,
         }
     }
 
+    /// The NUMA nodes which are online, read from `/sys/devices/system/node/online`.
+    ///
+    /// Does not allocate, so can be used whilst creating a global allocator (eg by a `LazyNumaNodePoolAllocator`); hence a list which can not be parsed is reported only as `io::ErrorKind::InvalidData`.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    pub fn online() -> io::Result<Self> {
+        let mut list = [0u8; Self::MAXIMUM_LIST_LENGTH];
+        let mut file = File::open("/sys/devices/system/node/online")?;
+        let mut length = 0;
+        loop {
+            if unlikely!(length == list.len()) {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            match file.read(&mut list[length..])? {
+                0 => break,
+                read => length += read,
+            }
+        }
+
+        from_utf8(&list[..length])
+            .ok()
+            .and_then(|list| list.parse().ok())
+            .ok_or_else(|| io::ErrorKind::InvalidData.into())
+    }
+
+    /// Adds a NUMA node, returning the set.
+    ///
+    /// Panics if `zero_based_node_index` is not less than `MAXIMUM_NUMBER_OF_NODES`.
+    #[inline(always)]
+    pub fn with_numa_node(mut self, zero_based_node_index: u16) -> Self {
+        self.insert_numa_node(zero_based_node_index);
+        self
+    }
+
     /// Is this the empty set?
     #[inline(always)]
     pub fn is_empty(&self) -> bool {
-        self.bits == 0
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    /// Number of NUMA nodes in the set.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Is a NUMA node in the set?
+    #[inline(always)]
+    pub fn contains(&self, zero_based_node_index: u16) -> bool {
+        if unlikely!(zero_based_node_index as usize >= Self::MAXIMUM_NUMBER_OF_NODES) {
+            return false;
+        }
+
+        let (word, bit) = Self::word_and_bit(zero_based_node_index);
+        self.bits[word] & bit != 0
     }
 
     /// Add a NUMA node into the set.
+    ///
+    /// Panics if `zero_based_node_index` is not less than `MAXIMUM_NUMBER_OF_NODES`.
     #[inline(always)]
-    pub fn insert_numa_node(&mut self, zero_based_node_index: u8) {
-        self.bits |= 1 << (zero_based_node_index as usize)
+    pub fn insert_numa_node(&mut self, zero_based_node_index: u16) {
+        Self::assert_is_valid_node(zero_based_node_index);
+        let (word, bit) = Self::word_and_bit(zero_based_node_index);
+        self.bits[word] |= bit
     }
 
     /// Remove a NUMA node from the set.
+    ///
+    /// Panics if `zero_based_node_index` is not less than `MAXIMUM_NUMBER_OF_NODES`.
     #[inline(always)]
-    pub fn remove_numa_node(&mut self, zero_based_node_index: u8) {
-        self.bits &= !(1 << (zero_based_node_index as usize))
+    pub fn remove_numa_node(&mut self, zero_based_node_index: u16) {
+        Self::assert_is_valid_node(zero_based_node_index);
+        let (word, bit) = Self::word_and_bit(zero_based_node_index);
+        self.bits[word] &= !bit
     }
 
+    /// NUMA nodes in either set; flags are taken from `self`.
+    #[inline(always)]
+    pub fn union(&self, other: &Self) -> Self {
+        self.combine(other, |left, right| left | right)
+    }
+
+    /// NUMA nodes in both sets; flags are taken from `self`.
+    #[inline(always)]
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.combine(other, |left, right| left & right)
+    }
+
+    /// NUMA nodes in `self` but not in `other`; flags are taken from `self`.
+    #[inline(always)]
+    pub fn difference(&self, other: &Self) -> Self {
+        self.combine(other, |left, right| left & !right)
+    }
+
+    /// Iterates NUMA nodes in ascending order.
+    #[inline(always)]
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = u16> + 'a {
+        self.bits
+            .iter()
+            .enumerate()
+            .flat_map(|(word_index, &word)| {
+                (0..Self::BITS_PER_WORD)
+                    .filter(move |&bit_index| word & (1 << bit_index) != 0)
+                    .map(move |bit_index| (word_index * Self::BITS_PER_WORD + bit_index) as u16)
+            })
+    }
+
+    /// The lowest NUMA node in the set, if any.
+    #[inline(always)]
+    pub fn first(&self) -> Option<u16> {
+        self.iter().next()
+    }
+
+    /// Returns the mode flags, node mask and `maxnode` to pass to `mbind()` and the like.
+    ///
+    /// `maxnode` is one more than the number of bits the kernel should read, as the kernel discards the last bit.
     #[cfg(any(target_os = "android", target_os = "linux"))]
     #[inline(always)]
-    pub fn mask_and_size(&self) -> (i32, Option<usize>, usize) {
+    pub fn mask_and_size(&self) -> (i32, Option<NodeMask>, usize) {
         if likely!(self.is_empty()) {
             Self::NO_MODE_FLAGS_NODEMASK_MAXNODE
         } else {
-            // let size = size_of::<usize>();
-
             let mut mode_flags = 0;
             if unlikely!(self.static_nodes) {
                 const MPOL_F_STATIC_NODES: i32 = 1 << 15;
@@ -89,8 +296,53 @@ impl NumaNodeBitSet {
                 const MPOL_F_RELATIVE_NODES: i32 = 1 << 14;
                 mode_flags |= MPOL_F_RELATIVE_NODES
             }
+            if unlikely!(self.numa_balancing) {
+                const MPOL_F_NUMA_BALANCING: i32 = 1 << 13;
+                mode_flags |= MPOL_F_NUMA_BALANCING
+            }
+
+            let highest_node = self.iter().last().unwrap() as usize;
+            (mode_flags, Some(self.bits), highest_node + 2)
+        }
+    }
+
+    #[inline(always)]
+    fn combine(&self, other: &Self, operation: impl Fn(usize, usize) -> usize) -> Self {
+        let mut combined = *self;
+        for (word, &other_word) in combined.bits.iter_mut().zip(other.bits.iter()) {
+            *word = operation(*word, other_word)
+        }
+        combined
+    }
+
+    #[inline(always)]
+    fn assert_is_valid_node(zero_based_node_index: u16) {
+        assert!(
+            (zero_based_node_index as usize) < Self::MAXIMUM_NUMBER_OF_NODES,
+            "NUMA node {} is not less than NumaNodeBitSet::MAXIMUM_NUMBER_OF_NODES ({})",
+            zero_based_node_index,
+            Self::MAXIMUM_NUMBER_OF_NODES
+        )
+    }
+
+    #[inline(always)]
+    fn word_and_bit(zero_based_node_index: u16) -> (usize, usize) {
+        let zero_based_node_index = zero_based_node_index as usize;
+        (
+            zero_based_node_index / Self::BITS_PER_WORD,
+            1 << (zero_based_node_index % Self::BITS_PER_WORD),
+        )
+    }
+
+    #[inline(always)]
+    fn parse_node(node: &str) -> Result<u16, ParseNumaNodeBitSetError> {
+        use self::ParseNumaNodeBitSetError::*;
 
-            (mode_flags, Some(self.bits), 2 as usize)
+        let node = node.trim();
+        let zero_based_node_index: usize = node.parse().map_err(|_| NotANode)?;
+        if unlikely!(zero_based_node_index >= Self::MAXIMUM_NUMBER_OF_NODES) {
+            return Err(NodeTooLarge(zero_based_node_index));
         }
+        Ok(zero_based_node_index as u16)
     }
 }
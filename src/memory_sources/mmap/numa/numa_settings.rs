@@ -1,4 +1,6 @@
 use crate::memory_sources::mmap::numa::numa_allocation_policy::NumaAllocationPolicy;
+#[cfg(any(target_os = "android", target_os = "linux"))]
+use crate::memory_sources::mmap::numa::numa_node_bit_set::NodeMask;
 use libc::c_void;
 #[cfg(any(target_os = "android", target_os = "linux"))]
 use libc::SYS_mbind;
@@ -11,7 +13,7 @@ pub struct NumaSettings {
     mbind_mode: i32,
 
     #[cfg(any(target_os = "android", target_os = "linux"))]
-    mbind_nodemask: Option<usize>,
+    mbind_nodemask: Option<NodeMask>,
 
     #[cfg(any(target_os = "android", target_os = "linux"))]
     mbind_maxnode: usize,
@@ -26,10 +28,13 @@ impl NumaSettings {
     ///
     /// * `allocation_policy`: NUMA node allocation policy (ignored on operating systems other than Android and Linux).
     /// * `strict`: Force allocations to migrate to NUMA nodes specified in `allocation_policy` or fail to allocate (ignored on operating systems other than Android and Linux).
+    ///
+    /// If the kernel does not support `allocation_policy`, its fallback is used instead (see `NumaAllocationPolicy::or_fallback()`).
     #[cfg(any(target_os = "android", target_os = "linux"))]
     #[inline(always)]
     pub fn new(allocation_policy: NumaAllocationPolicy, strict: bool) -> Self {
-        let (policy, (mode_flags, mbind_nodemask, mbind_maxnode)) = allocation_policy.values();
+        let (policy, (mode_flags, mbind_nodemask, mbind_maxnode)) =
+            allocation_policy.or_fallback().values();
         // (4, (0, None, 0))
         let mbind_mode = policy | mode_flags;
 
@@ -56,7 +61,7 @@ impl NumaSettings {
     pub(crate) fn post_allocate(&self, address: *mut c_void, size: usize) -> Result<(), ()> {
         let nodemask = match self.mbind_nodemask {
             None => null(),
-            Some(ref pointer) => pointer as *const usize,
+            Some(ref nodemask) => nodemask.as_ptr(),
         };
 
         let error_number = Self::mbind(
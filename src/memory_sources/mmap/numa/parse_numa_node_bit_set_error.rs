@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+/// An error parsing a `NumaNodeBitSet` from a list such as `0-3,7,9-11`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ParseNumaNodeBitSetError {
+    /// An item was not a node or a range of nodes.
+    NotANode,
+
+    /// A node was not less than `NumaNodeBitSet::MAXIMUM_NUMBER_OF_NODES`.
+    NodeTooLarge(usize),
+
+    /// A range of nodes started after it ended.
+    DescendingRange {
+        /// First node in range.
+        from: u16,
+
+        /// Last node in range.
+        to: u16,
+    },
+}
+
+impl Display for ParseNumaNodeBitSetError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::ParseNumaNodeBitSetError::*;
+
+        match *self {
+            NotANode => write!(f, "not a NUMA node or range of NUMA nodes"),
+
+            NodeTooLarge(node) => write!(f, "NUMA node {} is too large", node),
+
+            DescendingRange { from, to } => {
+                write!(f, "NUMA node range {}-{} is descending", from, to)
+            }
+        }
+    }
+}
+
+impl Error for ParseNumaNodeBitSetError {}
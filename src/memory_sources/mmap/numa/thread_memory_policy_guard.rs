@@ -0,0 +1,44 @@
+use crate::memory_sources::mmap::numa::numa_allocation_policy::NumaAllocationPolicy;
+use std::io;
+use std::marker::PhantomData;
+
+/// Restores the current thread's previous memory policy when dropped, including during unwinding.
+///
+/// Created by `NumaAllocationPolicy::use_for_current_thread()`.
+///
+/// Guards nest; they should be dropped in the reverse order to that in which they were created, which is what scoping does.
+///
+/// As memory policy is per-thread, a guard can not be sent to another thread.
+#[derive(Debug)]
+#[must_use = "the previous memory policy is restored as soon as the guard is dropped"]
+pub struct ThreadMemoryPolicyGuard {
+    restore_to: NumaAllocationPolicy,
+    not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ThreadMemoryPolicyGuard {
+    /// Failure to restore the previous memory policy is ignored.
+    #[inline(always)]
+    fn drop(&mut self) {
+        let _ = self.restore_to.set_for_current_thread();
+    }
+}
+
+impl ThreadMemoryPolicyGuard {
+    /// Makes `policy` the memory policy of the current thread.
+    #[inline(always)]
+    pub fn new(policy: &NumaAllocationPolicy) -> io::Result<Self> {
+        let restore_to = NumaAllocationPolicy::of_current_thread()?;
+        policy.set_for_current_thread()?;
+        Ok(Self {
+            restore_to,
+            not_send: PhantomData,
+        })
+    }
+
+    /// The memory policy which will be restored when dropped.
+    #[inline(always)]
+    pub fn restore_to(&self) -> &NumaAllocationPolicy {
+        &self.restore_to
+    }
+}
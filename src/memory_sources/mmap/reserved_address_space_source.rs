@@ -0,0 +1,422 @@
+use crate::allocators::global::memory_range::MemoryRange;
+use crate::extensions::prelude::*;
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use crate::memory_sources::mmap::prelude::*;
+use ::libc::*;
+use std::alloc::AllocError;
+use std::cell::Cell;
+use std::cmp::min;
+use std::ffi::CStr;
+use std::mem::size_of;
+use std::num::NonZeroUsize;
+use std::ptr::null_mut;
+
+/// This memory source reserves one large range of virtual address space, inaccessible (`PROT_NONE`) and without swap space, when created, and then commits and decommits pages within it as regions are obtained and released.
+///
+/// All regions therefore lie within one contiguous, predictable window of address space (see `memory_range()`), so checking whether memory came from this source is cheap and obtaining and releasing memory does not churn address space.
+///
+/// Regions are multiples of a granule, which is the huge page size if using huge pages or else the system page size.
+/// The first granules of the reservation hold a bitmap of which granules are in use.
+///
+/// When dropped, the whole reservation is unmapped, including any memory still obtained from it.
+#[derive(Debug)]
+pub struct ReservedAddressSpaceSource {
+    reservation: MemoryRange,
+    allocations_start_from: MemoryAddress,
+    granule_size: NonZeroUsize,
+    number_of_granules: usize,
+
+    /// One bit per granule, set when in use.
+    bitmap: *mut u64,
+    search_for_next_obtain_from: Cell<usize>,
+
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    map_flags: i32,
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    madvise_flags: i32,
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    numa_settings: Option<NumaSettings>,
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    decommit_advice: DecommitAdvice,
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    label: Option<MemoryLabel>,
+}
+
+impl Drop for ReservedAddressSpaceSource {
+    #[inline(always)]
+    fn drop(&mut self) {
+        MemoryMapSource::munmap_memory(
+            self.reservation.from,
+            self.reservation.to.difference(self.reservation.from),
+        )
+    }
+}
+
+impl MemorySource for ReservedAddressSpaceSource {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        let number_of_granules = self.number_of_granules_for(non_zero_size);
+        let first_granule = self.find_unused_granules(number_of_granules)?;
+        let memory_address = self.granule_address(first_granule);
+        let size = number_of_granules << self.granule_size.logarithm_base2();
+
+        let result = unsafe {
+            mprotect(
+                memory_address.as_ptr() as *mut c_void,
+                size,
+                PROT_READ | PROT_WRITE,
+            )
+        };
+        if unlikely!(result != 0) {
+            return Err(AllocError);
+        }
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            if unlikely!(
+                unsafe {
+                    madvise(
+                        memory_address.as_ptr() as *mut c_void,
+                        size,
+                        self.madvise_flags,
+                    )
+                } != 0
+            ) {
+                self.decommit_granules(memory_address, size);
+                return Err(AllocError);
+            }
+
+            // Kernels without support leave memory unnamed.
+            if self.label.is_some() {
+                let _ = MemoryMapSource::label_memory(memory_address, size, self.label);
+            }
+
+            if let Some(ref numa_settings) = self.numa_settings {
+                if unlikely!(numa_settings
+                    .post_allocate(memory_address.as_ptr() as *mut c_void, size)
+                    .is_err())
+                {
+                    self.decommit_granules(memory_address, size);
+                    return Err(AllocError);
+                }
+            }
+        }
+
+        self.set_granules_in_use(first_granule, number_of_granules, true);
+        self.search_for_next_obtain_from
+            .set(first_granule + number_of_granules);
+        Ok(memory_address)
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        debug_assert!(
+            self.reservation.contains(current_memory),
+            "current_memory was not obtained from this memory source"
+        );
+
+        let number_of_granules = self.number_of_granules_for(non_zero_size);
+        let size = number_of_granules << self.granule_size.logarithm_base2();
+        self.decommit_granules(current_memory, size);
+
+        let first_granule = current_memory.difference(self.allocations_start_from)
+            >> self.granule_size.logarithm_base2();
+        self.set_granules_in_use(first_granule, number_of_granules, false);
+        if first_granule < self.search_for_next_obtain_from.get() {
+            self.search_for_next_obtain_from.set(first_granule)
+        }
+    }
+
+    /// Only whole pages within `size` bytes from `current_memory` are decommitted, as advised by `set_decommit_advice()`; they remain accessible.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        MemoryMapSource::decommit_whole_pages(
+            current_memory,
+            non_zero_size.get(),
+            self.decommit_advice,
+        )
+    }
+}
+
+impl ReservedAddressSpaceSource {
+    const BITS_PER_WORD: usize = size_of::<u64>() * 8;
+
+    /// Reserves `reservation_size` bytes of address space, rounded up to the huge page size if using huge pages or else the system page size.
+    ///
+    /// * `huge_page_size`: Huge page size to use; on operating systems other than Android and Linux, specifying a huge page size has no effect.
+    /// * `numa_settings`: NUMA policy applied to each region as it is committed. On operating systems other than Android and Linux, specifying a value has no effect.
+    #[allow(unused_variables)]
+    #[inline(always)]
+    pub fn new(
+        reservation_size: NonZeroUsize,
+        huge_page_size: HugePageSize,
+        numa_settings: Option<NumaSettings>,
+    ) -> Result<Self, AllocError> {
+        const UNUSED_FILE_DESCRIPTOR: i32 = -1;
+        const NO_OFFSET: i64 = 0;
+
+        let granule_size = huge_page_size
+            .size()
+            .unwrap_or_else(MemoryMapSource::page_size);
+        let reservation_size = reservation_size.round_up_to_power_of_two(granule_size);
+        let total_granules = reservation_size.get() >> granule_size.logarithm_base2();
+
+        let bitmap_size =
+            ((total_granules + (Self::BITS_PER_WORD - 1)) / Self::BITS_PER_WORD) * size_of::<u64>();
+        let bitmap_granules = bitmap_size
+            .non_zero()
+            .round_up_to_power_of_two(granule_size)
+            .get()
+            >> granule_size.logarithm_base2();
+        if unlikely!(bitmap_granules >= total_granules) {
+            return Err(AllocError);
+        }
+
+        let map_flags = Self::map_flags(huge_page_size);
+        let reservation = unsafe {
+            mmap(
+                null_mut(),
+                reservation_size.get(),
+                PROT_NONE,
+                map_flags,
+                UNUSED_FILE_DESCRIPTOR,
+                NO_OFFSET,
+            )
+        };
+        if unlikely!(reservation == MAP_FAILED) {
+            return Err(AllocError);
+        }
+        let from = (reservation as *mut u8).non_null();
+
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let madvise_flags = MemoryMapSource::madvise_flags(huge_page_size);
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        unsafe {
+            madvise(reservation, reservation_size.get(), madvise_flags)
+        };
+
+        let bitmap_granules_size = bitmap_granules << granule_size.logarithm_base2();
+        if unlikely!(
+            unsafe { mprotect(reservation, bitmap_granules_size, PROT_READ | PROT_WRITE) } != 0
+        ) {
+            MemoryMapSource::munmap_memory(from, reservation_size.get());
+            return Err(AllocError);
+        }
+
+        Ok(Self {
+            reservation: MemoryRange::new(from, from.add_non_zero(reservation_size)),
+            allocations_start_from: from.add(bitmap_granules_size),
+            granule_size,
+            number_of_granules: total_granules - bitmap_granules,
+
+            bitmap: reservation as *mut u64,
+            search_for_next_obtain_from: Cell::new(0),
+
+            #[cfg(not(any(target_os = "android", target_os = "linux")))]
+            map_flags,
+
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            madvise_flags,
+
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            numa_settings,
+
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            decommit_advice: DecommitAdvice::default(),
+
+            #[cfg(any(target_os = "android", target_os = "linux"))]
+            label: None,
+        })
+    }
+
+    /// The range of reserved address space; all memory obtained lies within it.
+    #[inline(always)]
+    pub fn memory_range(&self) -> MemoryRange {
+        self.reservation
+    }
+
+    /// Does the reserved address space contain `memory_address`?
+    #[inline(always)]
+    pub fn contains(&self, memory_address: MemoryAddress) -> bool {
+        self.reservation.contains(memory_address)
+    }
+
+    /// Change how memory passed to `MemorySource::decommit()` is advised to the kernel; see `MemoryMapSource::set_decommit_advice()`.
+    ///
+    /// Memory released with `MemorySource::release()` is always discarded with `MADV_DONTNEED`, so that its granules read as zero when obtained again.
+    ///
+    /// On operating systems other than Android and Linux, this has no effect.
+    #[allow(unused_variables)]
+    #[inline(always)]
+    pub fn set_decommit_advice(&mut self, decommit_advice: DecommitAdvice) {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            self.decommit_advice = decommit_advice
+        }
+    }
+
+    /// Label memory obtained so that it is named in `/proc/<pid>/maps` and `/proc/<pid>/smaps`; see `MemoryMapSource::set_label()`.
+    ///
+    /// On operating systems other than Android and Linux, this has no effect.
+    #[allow(unused_variables)]
+    #[inline(always)]
+    pub fn set_label(&mut self, label: Option<&CStr>) {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            self.label = label.map(MemoryLabel::new)
+        }
+    }
+
+    /// The label memory obtained is named with, if any.
+    #[inline(always)]
+    pub fn label(&self) -> Option<MemoryLabel> {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        {
+            self.label
+        }
+
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        None
+    }
+
+    /// Size of the unit in which memory is committed and decommitted.
+    #[inline(always)]
+    pub fn granule_size(&self) -> NonZeroUsize {
+        self.granule_size
+    }
+
+    /// Decommits and makes inaccessible again.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[inline(always)]
+    fn decommit_granules(&self, memory_address: MemoryAddress, size: usize) {
+        let address = memory_address.as_ptr() as *mut c_void;
+        unsafe {
+            madvise(address, size, MADV_DONTNEED);
+            mprotect(address, size, PROT_NONE);
+        }
+    }
+
+    /// Decommits and makes inaccessible again by mapping fresh, reserved-only address space over the granules.
+    #[cfg(not(any(target_os = "android", target_os = "linux")))]
+    #[inline(always)]
+    fn decommit_granules(&self, memory_address: MemoryAddress, size: usize) {
+        const UNUSED_FILE_DESCRIPTOR: i32 = -1;
+        const NO_OFFSET: i64 = 0;
+
+        let result = unsafe {
+            mmap(
+                memory_address.as_ptr() as *mut c_void,
+                size,
+                PROT_NONE,
+                self.map_flags | MAP_FIXED,
+                UNUSED_FILE_DESCRIPTOR,
+                NO_OFFSET,
+            )
+        };
+        debug_assert_ne!(result, MAP_FAILED, "Could not decommit granules");
+    }
+
+    /// Next-fit: searches from after the most recently obtained region, then from the start.
+    #[inline(always)]
+    fn find_unused_granules(&self, number_of_granules: usize) -> Result<usize, AllocError> {
+        let search_from = self.search_for_next_obtain_from.get();
+        self.find_unused_granules_from(search_from, number_of_granules)
+            .or_else(|| {
+                if search_from == 0 {
+                    None
+                } else {
+                    self.find_unused_granules_from(0, number_of_granules)
+                }
+            })
+            .ok_or(AllocError)
+    }
+
+    /// Skips whole runs of used or unused granules within a bitmap word at a time, rather than testing granule by granule.
+    #[inline(always)]
+    fn find_unused_granules_from(
+        &self,
+        search_from: usize,
+        number_of_granules: usize,
+    ) -> Option<usize> {
+        let mut first_granule = search_from;
+        let mut run = 0;
+        let mut granule = search_from;
+        while granule < self.number_of_granules {
+            let bit_index = granule % Self::BITS_PER_WORD;
+            let bits = unsafe { *self.bitmap.add(granule / Self::BITS_PER_WORD) } >> bit_index;
+            let granules_left_in_word = min(
+                Self::BITS_PER_WORD - bit_index,
+                self.number_of_granules - granule,
+            );
+
+            if bits & 1 != 0 {
+                granule += min((!bits).trailing_zeros() as usize, granules_left_in_word);
+                run = 0;
+                first_granule = granule;
+            } else {
+                let unused_granules = min(bits.trailing_zeros() as usize, granules_left_in_word);
+                run += unused_granules;
+                if run >= number_of_granules {
+                    return Some(first_granule);
+                }
+                granule += unused_granules;
+            }
+        }
+        None
+    }
+
+    #[inline(always)]
+    fn set_granules_in_use(&self, first_granule: usize, number_of_granules: usize, in_use: bool) {
+        for granule in first_granule..(first_granule + number_of_granules) {
+            let word = unsafe { &mut *self.bitmap.add(granule / Self::BITS_PER_WORD) };
+            let bit = 1 << (granule % Self::BITS_PER_WORD);
+            if in_use {
+                *word |= bit
+            } else {
+                *word &= !bit
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn granule_address(&self, granule: usize) -> MemoryAddress {
+        self.allocations_start_from
+            .add(granule << self.granule_size.logarithm_base2())
+    }
+
+    #[inline(always)]
+    fn number_of_granules_for(&self, non_zero_size: NonZeroUsize) -> usize {
+        non_zero_size
+            .round_up_to_power_of_two(self.granule_size)
+            .get()
+            >> self.granule_size.logarithm_base2()
+    }
+
+    #[allow(unused_variables)]
+    #[inline(always)]
+    fn map_flags(huge_page_size: HugePageSize) -> i32 {
+        #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "linux"))]
+        const ANONYMOUS: i32 = MAP_ANONYMOUS;
+        #[cfg(not(any(target_os = "android", target_os = "netbsd", target_os = "linux")))]
+        const ANONYMOUS: i32 = MAP_ANON;
+
+        #[cfg(any(target_os = "android", target_os = "linux", target_os = "netbsd"))]
+        const DO_NOT_RESERVE_SWAP_SPACE: i32 = MAP_NORESERVE;
+        #[cfg(not(any(target_os = "android", target_os = "linux", target_os = "netbsd")))]
+        const DO_NOT_RESERVE_SWAP_SPACE: i32 = 0;
+
+        let map_flags = MAP_PRIVATE | ANONYMOUS | DO_NOT_RESERVE_SWAP_SPACE;
+
+        if cfg!(any(target_os = "android", target_os = "linux")) {
+            map_flags | (huge_page_size as i32)
+        } else {
+            map_flags
+        }
+    }
+}
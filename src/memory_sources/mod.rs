@@ -5,8 +5,16 @@ pub mod arena_memory_source;
 #[cfg(unix)]
 pub mod mmap;
 
+pub mod arc_memory_source;
+pub mod budget;
+pub mod budgeted_memory_source;
+pub mod buffer_memory_source;
+pub mod caching_memory_source;
+pub mod chained_memory_source;
+pub mod global_alloc_memory_source;
 pub mod memory_source;
 pub mod rc_memory_source;
+pub mod spin_locked_memory_source;
 
 pub mod prelude {
     pub use super::arena_memory_source::prelude::*;
@@ -19,6 +27,14 @@ pub mod prelude {
     #[cfg(unix)]
     pub use super::mmap::*;
 
+    pub use super::arc_memory_source::*;
+    pub use super::budget::*;
+    pub use super::budgeted_memory_source::*;
+    pub use super::buffer_memory_source::*;
+    pub use super::caching_memory_source::*;
+    pub use super::chained_memory_source::*;
+    pub use super::global_alloc_memory_source::*;
     pub use super::memory_source::*;
     pub use super::rc_memory_source::*;
+    pub use super::spin_locked_memory_source::*;
 }
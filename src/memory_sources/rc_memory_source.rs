@@ -38,6 +38,11 @@ impl<MS: MemorySource> MemorySource for RcMemorySource<MS> {
     fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
         self.0.release(non_zero_size, current_memory)
     }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.0.decommit(non_zero_size, current_memory)
+    }
 }
 
 impl<MS: MemorySource> RcMemorySource<MS> {
@@ -0,0 +1,55 @@
+use crate::memory_address::MemoryAddress;
+use crate::memory_sources::memory_source::MemorySource;
+use crate::spin_lock::SpinLock;
+use std::alloc::AllocError;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::num::NonZeroUsize;
+
+/// Makes a memory source which is not thread-safe, such as `ArenaMemorySource`, thread-safe by holding a spin lock whilst it is used.
+///
+/// A spin lock is used rather than a `Mutex` as it never allocates, and obtaining or releasing memory is usually quick.
+pub struct SpinLockedMemorySource<MS: MemorySource> {
+    memory_source: SpinLock<MS>,
+}
+
+impl<MS: MemorySource> Debug for SpinLockedMemorySource<MS> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SpinLockedMemorySource")
+    }
+}
+
+unsafe impl<MS: MemorySource + Send> Send for SpinLockedMemorySource<MS> {}
+
+unsafe impl<MS: MemorySource + Send> Sync for SpinLockedMemorySource<MS> {}
+
+impl<MS: MemorySource> MemorySource for SpinLockedMemorySource<MS> {
+    #[inline(always)]
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        self.memory_source
+            .locked(|memory_source| memory_source.obtain(non_zero_size))
+    }
+
+    #[inline(always)]
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        self.memory_source
+            .locked(|memory_source| memory_source.release(non_zero_size, current_memory))
+    }
+
+    #[inline(always)]
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        self.memory_source
+            .locked(|memory_source| memory_source.decommit(non_zero_size, current_memory))
+    }
+}
+
+impl<MS: MemorySource> SpinLockedMemorySource<MS> {
+    /// Creates a new instance.
+    #[inline(always)]
+    pub const fn new(memory_source: MS) -> Self {
+        Self {
+            memory_source: SpinLock::new(memory_source),
+        }
+    }
+}
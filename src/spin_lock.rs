@@ -0,0 +1,48 @@
+use std::hint::spin_loop;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+/// A spin lock around a value, for use where a `Mutex` can not be, as it never allocates.
+///
+/// The lock is released even if the callback holding it unwinds.
+pub(crate) struct SpinLock<T> {
+    locked: AtomicBool,
+    value: T,
+}
+
+impl<T> SpinLock<T> {
+    /// Creates a new, unlocked instance.
+    #[inline(always)]
+    pub(crate) const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            value,
+        }
+    }
+
+    /// Calls `callback` with the value whilst holding the lock.
+    #[inline(always)]
+    pub(crate) fn locked<R>(&self, callback: impl FnOnce(&T) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Acquire, Relaxed)
+            .is_err()
+        {
+            while self.locked.load(Relaxed) {
+                spin_loop()
+            }
+        }
+
+        let _guard = SpinLockGuard(&self.locked);
+        callback(&self.value)
+    }
+}
+
+struct SpinLockGuard<'a>(&'a AtomicBool);
+
+impl<'a> Drop for SpinLockGuard<'a> {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.0.store(false, Release)
+    }
+}
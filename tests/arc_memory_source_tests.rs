@@ -0,0 +1,86 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod arc_memory_source_tests {
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_address::MemoryAddress;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::alloc::AllocError;
+    use std::cell::Cell;
+    use std::collections::HashSet;
+    use std::num::NonZeroUsize;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::thread::spawn;
+
+    const BLOCK_SIZE: usize = 64;
+
+    const NUMBER_OF_BLOCKS: usize = 64;
+
+    #[test]
+    pub fn spin_locked_arena_is_shared_between_threads() {
+        let memory_source = ArcMemorySource::new(SpinLockedMemorySource::new(
+            ArenaMemorySource::new(
+                MemoryMapSource::new(false, true, true, false, HugePageSize::default(), None),
+                BLOCK_SIZE.non_zero(),
+                NUMBER_OF_BLOCKS.non_zero(),
+                |_, _| {},
+            )
+            .unwrap(),
+        ));
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let memory_source = memory_source.clone();
+                spawn(move || {
+                    for _ in 0..1000 {
+                        let blocks: Vec<_> = (0..8)
+                            .map(|_| memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap())
+                            .collect();
+                        for block in blocks {
+                            memory_source.release(BLOCK_SIZE.non_zero(), block)
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap()
+        }
+
+        let blocks: HashSet<_> = (0..NUMBER_OF_BLOCKS)
+            .map(|_| memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap())
+            .collect();
+        assert_eq!(blocks.len(), NUMBER_OF_BLOCKS, "Blocks were lost");
+    }
+
+    #[test]
+    pub fn spin_lock_is_released_if_memory_source_panics() {
+        let memory_source = SpinLockedMemorySource::new(PanicsOnceMemorySource::default());
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            memory_source.obtain(BLOCK_SIZE.non_zero())
+        }));
+        assert!(result.is_err(), "Memory source did not panic");
+
+        let block = memory_source
+            .obtain(BLOCK_SIZE.non_zero())
+            .expect("Did not obtain once the spin lock was released");
+        memory_source.release(BLOCK_SIZE.non_zero(), block)
+    }
+
+    #[derive(Debug, Default)]
+    struct PanicsOnceMemorySource(Cell<bool>);
+
+    impl MemorySource for PanicsOnceMemorySource {
+        fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+            if !self.0.replace(true) {
+                panic!("First obtain")
+            }
+            MemoryMapSource::default().obtain(non_zero_size)
+        }
+
+        fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+            MemoryMapSource::default().release(non_zero_size, current_memory)
+        }
+    }
+}
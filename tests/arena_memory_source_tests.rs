@@ -0,0 +1,66 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod arena_memory_source_tests {
+    use allocator_suite::extensions::non_null_u8_ext::NonNullU8Ext;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::cell::RefCell;
+
+    const BLOCK_SIZE: usize = 64;
+
+    const NUMBER_OF_BLOCKS: usize = 16;
+
+    thread_local! {
+        static MISUSES: RefCell<Vec<ArenaMisuse>> = RefCell::new(Vec::new());
+    }
+
+    fn record_misuse(misuse: ArenaMisuse) {
+        MISUSES.with(|misuses| misuses.borrow_mut().push(misuse))
+    }
+
+    #[test]
+    pub fn checked_arena_refuses_misuse() {
+        let memory_source = ArenaMemorySource::new_checked(
+            MemoryMapSource::new(false, true, true, false, HugePageSize::default(), None),
+            BLOCK_SIZE.non_zero(),
+            NUMBER_OF_BLOCKS.non_zero(),
+            |_, _| {},
+            record_misuse,
+        )
+        .unwrap();
+
+        assert!(memory_source.obtain((BLOCK_SIZE + 1).non_zero()).is_err());
+
+        let first = memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap();
+        let second = memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap();
+
+        memory_source.release(BLOCK_SIZE.non_zero(), first.add(1));
+        memory_source.release(
+            BLOCK_SIZE.non_zero(),
+            first.add(BLOCK_SIZE * NUMBER_OF_BLOCKS * 2),
+        );
+        memory_source.release(BLOCK_SIZE.non_zero(), first);
+        memory_source.release(BLOCK_SIZE.non_zero(), first);
+
+        assert_eq!(
+            MISUSES.with(|misuses| misuses.borrow().clone()),
+            vec![
+                ArenaMisuse::SizeLargerThanBlock {
+                    non_zero_size: (BLOCK_SIZE + 1).non_zero(),
+                    block_size: BLOCK_SIZE.non_zero(),
+                },
+                ArenaMisuse::NotAtBlockBoundary(first.add(1)),
+                ArenaMisuse::OutsideOfArena(first.add(BLOCK_SIZE * NUMBER_OF_BLOCKS * 2)),
+                ArenaMisuse::DoubleRelease(first),
+            ]
+        );
+
+        // The free list was not corrupted by the double release.
+        let third = memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap();
+        assert_eq!(third, first);
+        let fourth = memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap();
+        assert_ne!(fourth, first);
+        assert_ne!(fourth, second);
+    }
+}
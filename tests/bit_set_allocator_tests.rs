@@ -0,0 +1,112 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(test)]
+mod bit_set_allocator_tests {
+    use crate::common::*;
+    use allocator_suite::allocators::bit_set::bit_set_allocator::BitSetAllocator;
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::extensions::non_null_u8_ext::NonNullU8Ext;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_address::MemoryAddress;
+
+    /// Each bit set word tracks 64 blocks; sizing blocks so that they cover one page lets trimming decommit whole pages.
+    fn block_size() -> usize {
+        page_size() / 64
+    }
+
+    fn memory_size() -> usize {
+        page_size() * 16
+    }
+
+    /// Two allocations fill the blocks of one bit set word.
+    fn allocation_size() -> usize {
+        page_size() / 2
+    }
+
+    #[test]
+    pub fn every_block_can_be_allocated() {
+        let counts = Counts::default();
+        let allocator = new_allocator(&counts);
+
+        for block in 0..(memory_size() / block_size()) {
+            allocator
+                .allocate(block_size().non_zero(), 8.non_zero())
+                .expect(&format!("Did not allocate block `{}`", block));
+        }
+        assert!(allocator
+            .allocate(block_size().non_zero(), 8.non_zero())
+            .is_err());
+    }
+
+    #[test]
+    pub fn trim_decommits_runs_of_free_blocks() {
+        let counts = Counts::default();
+        let allocator = new_allocator(&counts);
+        let allocations = allocate_all(&allocator);
+        assert_eq!(allocator.trim(), 0, "Decommitted allocated blocks");
+
+        for allocation in &allocations[0..allocations.len() / 2] {
+            allocator.deallocate(allocation_size().non_zero(), 8.non_zero(), *allocation);
+        }
+        let decommitted = allocator.trim();
+        assert_eq!(
+            decommitted,
+            memory_size() / 2,
+            "Did not decommit free blocks"
+        );
+        assert_eq!(counts.decommitted_bytes.get(), decommitted);
+
+        let reallocation = allocator
+            .allocate(allocation_size().non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate after trimming"));
+        reallocation.write([0x0Bu8; 64]);
+    }
+
+    #[test]
+    pub fn trims_automatically_once_threshold_is_deallocated() {
+        let counts = Counts::default();
+        let allocator = new_allocator(&counts).with_trim_threshold((page_size() * 4).non_zero());
+        let allocations = allocate_all(&allocator);
+
+        for allocation in &allocations[0..4] {
+            allocator.deallocate(allocation_size().non_zero(), 8.non_zero(), *allocation);
+        }
+        assert_eq!(
+            counts.decommitted_bytes.get(),
+            0,
+            "Trimmed before the threshold was deallocated"
+        );
+
+        for allocation in &allocations[4..8] {
+            allocator.deallocate(allocation_size().non_zero(), 8.non_zero(), *allocation);
+        }
+        assert_eq!(
+            counts.decommitted_bytes.get(),
+            page_size() * 4,
+            "Did not trim once the threshold was deallocated"
+        );
+    }
+
+    fn new_allocator(counts: &Counts) -> BitSetAllocator<CountingMemorySource> {
+        BitSetAllocator::new_by_amount(
+            CountingMemorySource(counts),
+            block_size().non_zero(),
+            memory_size().non_zero(),
+        )
+        .unwrap()
+    }
+
+    fn allocate_all(allocator: &BitSetAllocator<CountingMemorySource>) -> Vec<MemoryAddress> {
+        (0..memory_size() / allocation_size())
+            .map(|_| {
+                let allocation = allocator
+                    .allocate(allocation_size().non_zero(), 8.non_zero())
+                    .expect(&format!("Did not allocate"));
+                allocation.write([0x0Au8; 64]);
+                allocation
+            })
+            .collect()
+    }
+}
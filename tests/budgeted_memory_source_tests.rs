@@ -0,0 +1,60 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(test)]
+mod budgeted_memory_source_tests {
+    use crate::common::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+
+    const REGION_SIZE: usize = 64 * 1024;
+
+    static WATERMARKS_CROSSED: AtomicUsize = AtomicUsize::new(0);
+
+    fn watermark_crossed(_budget: &Budget, watermark: usize, used_bytes: usize) {
+        assert!(used_bytes >= watermark);
+        WATERMARKS_CROSSED.fetch_add(1, Relaxed);
+    }
+
+    #[test]
+    pub fn child_budgets_count_against_their_parent() {
+        let mut service = Budget::new(REGION_SIZE * 3);
+        service.add_watermark(REGION_SIZE * 2, watermark_crossed);
+        let first_task = Budget::new_child(&service, REGION_SIZE * 2);
+        let second_task = Budget::new_child(&service, REGION_SIZE * 2);
+
+        let first = BudgetedMemorySource::new(&first_task, memory_map_source());
+        let second = BudgetedMemorySource::new(&second_task, memory_map_source());
+
+        let a = first.obtain(REGION_SIZE.non_zero()).unwrap();
+        let b = first.obtain(REGION_SIZE.non_zero()).unwrap();
+        assert!(
+            first.obtain(REGION_SIZE.non_zero()).is_err(),
+            "Child hard limit was exceeded"
+        );
+        assert_eq!(first_task.refusals(), 1);
+
+        let c = second.obtain(REGION_SIZE.non_zero()).unwrap();
+        assert!(
+            second.obtain(REGION_SIZE.non_zero()).is_err(),
+            "Parent hard limit was exceeded"
+        );
+        assert_eq!(service.refusals(), 1);
+        assert_eq!(
+            second_task.used_bytes(),
+            REGION_SIZE,
+            "Refused charge was not rolled back"
+        );
+        assert_eq!(service.used_bytes(), REGION_SIZE * 3);
+        assert_eq!(WATERMARKS_CROSSED.load(Relaxed), 1);
+
+        first.release(REGION_SIZE.non_zero(), a);
+        first.release(REGION_SIZE.non_zero(), b);
+        second.release(REGION_SIZE.non_zero(), c);
+        assert_eq!(service.used_bytes(), 0);
+        assert_eq!(service.peak_used_bytes(), REGION_SIZE * 3);
+    }
+}
@@ -0,0 +1,48 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod buffer_memory_source_tests {
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::allocators::global::prelude::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::mem::MaybeUninit;
+
+    #[test]
+    pub fn allocator_runs_on_stack_memory() {
+        const MEMORY_SIZE: usize = 4096;
+
+        let mut buffer = [MaybeUninit::<u8>::uninit(); MEMORY_SIZE * 2];
+        let memory_source = BufferMemorySource::from_uninitialized(&mut buffer, 256.non_zero());
+        let allocator = BumpAllocator::new(memory_source, MEMORY_SIZE.non_zero()).unwrap();
+
+        let allocation = allocator
+            .allocate(64.non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate"));
+        assert!(allocator.contains(allocation));
+        assert_eq!(
+            allocator.memory_range().from.as_ptr() as usize % 256,
+            0,
+            "Memory was not aligned"
+        );
+
+        allocator.deallocate(64.non_zero(), 8.non_zero(), allocation);
+    }
+
+    #[test]
+    pub fn released_memory_is_obtained_again() {
+        let mut buffer = vec![0u8; 1024];
+        let memory_source = BufferMemorySource::new(&mut buffer, 64.non_zero());
+
+        let first = memory_source.obtain(100.non_zero()).unwrap();
+        let second = memory_source.obtain(100.non_zero()).unwrap();
+        assert!(memory_source.obtain(1024.non_zero()).is_err());
+
+        memory_source.release(100.non_zero(), second);
+        assert_eq!(memory_source.obtain(100.non_zero()).unwrap(), second);
+
+        memory_source.release(100.non_zero(), second);
+        memory_source.release(100.non_zero(), first);
+        assert_eq!(memory_source.obtain(100.non_zero()).unwrap(), first);
+    }
+}
@@ -0,0 +1,90 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(test)]
+mod caching_memory_source_tests {
+    use crate::common::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_address::MemoryAddress;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    const REGION_SIZE: usize = 64 * 1024;
+
+    #[test]
+    pub fn released_regions_are_reused_until_over_budget() {
+        let counts = Counts::default();
+        let memory_source =
+            CachingMemorySource::new(CountingMemorySource(&counts), REGION_SIZE * 2, None, true);
+
+        let first = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        memory_source.release(REGION_SIZE.non_zero(), first);
+        assert_eq!(memory_source.cached_bytes(), REGION_SIZE);
+        assert_eq!(memory_source.obtain(REGION_SIZE.non_zero()).unwrap(), first);
+        assert_eq!(counts.obtained.get(), 1, "Cached region was not reused");
+
+        let regions: Vec<MemoryAddress> = (0..3)
+            .map(|_| memory_source.obtain(REGION_SIZE.non_zero()).unwrap())
+            .chain(Some(first))
+            .collect();
+        for region in &regions {
+            memory_source.release(REGION_SIZE.non_zero(), *region)
+        }
+        assert_eq!(memory_source.cached_bytes(), REGION_SIZE * 2);
+        assert_eq!(
+            counts.released.get(),
+            2,
+            "Oldest regions over budget were not released"
+        );
+
+        drop(memory_source);
+        assert_eq!(
+            counts.released.get(),
+            4,
+            "Cached regions were not released on drop"
+        );
+    }
+
+    #[test]
+    pub fn oldest_region_is_released_first_whatever_its_size() {
+        let counts = Counts::default();
+        let memory_source =
+            CachingMemorySource::new(CountingMemorySource(&counts), REGION_SIZE * 5, None, false);
+
+        let large = memory_source.obtain((REGION_SIZE * 4).non_zero()).unwrap();
+        let small = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        let newest_small = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        memory_source.release((REGION_SIZE * 4).non_zero(), large);
+        memory_source.release(REGION_SIZE.non_zero(), small);
+        memory_source.release(REGION_SIZE.non_zero(), newest_small);
+
+        assert_eq!(counts.released.get(), 1);
+        assert_eq!(
+            memory_source.cached_bytes(),
+            REGION_SIZE * 2,
+            "Oldest, large, region was not the one released"
+        );
+    }
+
+    #[test]
+    pub fn aged_regions_are_released() {
+        let counts = Counts::default();
+        let memory_source = CachingMemorySource::new(
+            CountingMemorySource(&counts),
+            usize::MAX,
+            Some(Duration::from_millis(10)),
+            false,
+        );
+
+        let region = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        memory_source.release(REGION_SIZE.non_zero(), region);
+        assert_eq!(counts.released.get(), 0);
+
+        sleep(Duration::from_millis(20));
+        memory_source.release_aged();
+        assert_eq!(counts.released.get(), 1, "Aged region was not released");
+        assert_eq!(memory_source.cached_bytes(), 0);
+    }
+}
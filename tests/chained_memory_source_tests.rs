@@ -0,0 +1,51 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(test)]
+mod chained_memory_source_tests {
+    use crate::common::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_address::MemoryAddress;
+    use allocator_suite::memory_sources::prelude::*;
+
+    const SLAB_SIZE: usize = 64 * 1024;
+
+    const SUB_REGION_SIZE: usize = 16 * 1024;
+
+    #[test]
+    pub fn slabs_are_obtained_when_exhausted_and_released_when_empty() {
+        let counts = Counts::default();
+        let memory_source = ChainedMemorySource::new(
+            CountingMemorySource(&counts),
+            SLAB_SIZE.non_zero(),
+            64.non_zero(),
+        );
+
+        // The slab header takes up some of the first slab, so only three sub-regions fit in it.
+        let sub_regions: Vec<MemoryAddress> = (0..4)
+            .map(|_| memory_source.obtain(SUB_REGION_SIZE.non_zero()).unwrap())
+            .collect();
+        assert_eq!(counts.obtained.get(), 2);
+
+        for sub_region in &sub_regions[0..3] {
+            memory_source.release(SUB_REGION_SIZE.non_zero(), *sub_region)
+        }
+        assert_eq!(counts.released.get(), 1, "Empty slab was not released");
+
+        memory_source.release(SUB_REGION_SIZE.non_zero(), sub_regions[3]);
+        assert_eq!(
+            counts.released.get(),
+            1,
+            "Most recent slab should be kept for reuse"
+        );
+        assert_eq!(
+            memory_source.obtain(SUB_REGION_SIZE.non_zero()).unwrap(),
+            sub_regions[3],
+            "Most recent slab was not reused from its start"
+        );
+
+        drop(memory_source);
+        assert_eq!(counts.released.get(), 2, "Slab was not released on drop");
+    }
+}
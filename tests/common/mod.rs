@@ -0,0 +1,73 @@
+//! Fixtures shared by the integration tests.
+//!
+//! Each test crate uses only some of these, so unused ones are allowed.
+#![allow(dead_code)]
+
+use allocator_suite::memory_address::MemoryAddress;
+use allocator_suite::memory_sources::prelude::*;
+use std::alloc::AllocError;
+use std::cell::Cell;
+use std::num::NonZeroUsize;
+
+/// Counts of calls to a `CountingMemorySource`.
+#[derive(Debug, Default)]
+pub struct Counts {
+    pub obtained: Cell<usize>,
+    pub released: Cell<usize>,
+    pub decommitted_bytes: Cell<usize>,
+}
+
+/// A memory source which counts its obtains, releases and decommitted bytes, and forwards them to a fresh `memory_map_source()`.
+#[derive(Debug)]
+pub struct CountingMemorySource<'a>(pub &'a Counts);
+
+impl<'a> MemorySource for CountingMemorySource<'a> {
+    fn obtain(&self, non_zero_size: NonZeroUsize) -> Result<MemoryAddress, AllocError> {
+        self.0.obtained.set(self.0.obtained.get() + 1);
+        memory_map_source().obtain(non_zero_size)
+    }
+
+    fn release(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) {
+        self.0.released.set(self.0.released.get() + 1);
+        memory_map_source().release(non_zero_size, current_memory)
+    }
+
+    fn decommit(&self, non_zero_size: NonZeroUsize, current_memory: MemoryAddress) -> usize {
+        let decommitted_bytes = memory_map_source().decommit(non_zero_size, current_memory);
+        self.0
+            .decommitted_bytes
+            .set(self.0.decommitted_bytes.get() + decommitted_bytes);
+        decommitted_bytes
+    }
+}
+
+/// An unlocked, pre-faulted memory map source with no NUMA policy.
+pub fn memory_map_source() -> MemoryMapSource {
+    MemoryMapSource::new(false, true, true, false, HugePageSize::default(), None)
+}
+
+/// The system page size.
+#[cfg(unix)]
+pub fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// The name of the mapping containing `memory_address` in `/proc/self/maps`, eg `[anon:label]`, or empty if it is unnamed.
+#[cfg(any(target_os = "android", target_os = "linux"))]
+pub fn mapping_name(memory_address: MemoryAddress) -> String {
+    let address = memory_address.as_ptr() as usize;
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+    maps.lines()
+        .find_map(|line| {
+            let mut fields = line.splitn(6, ' ');
+            let (from, to) = fields.next()?.split_once('-')?;
+            let from = usize::from_str_radix(from, 16).ok()?;
+            let to = usize::from_str_radix(to, 16).ok()?;
+            if from <= address && address < to {
+                Some(fields.nth(4).unwrap_or("").trim().to_string())
+            } else {
+                None
+            }
+        })
+        .expect("Memory is not mapped")
+}
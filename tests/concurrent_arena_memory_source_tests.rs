@@ -0,0 +1,61 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod concurrent_arena_memory_source_tests {
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+    use std::thread::spawn;
+
+    const BLOCK_SIZE: usize = 64;
+
+    const NUMBER_OF_BLOCKS: usize = 256;
+
+    #[test]
+    pub fn blocks_are_neither_lost_nor_shared_between_threads() {
+        let memory_source = Arc::new(
+            ConcurrentArenaMemorySource::new(
+                MemoryMapSource::new(false, true, true, false, HugePageSize::default(), None),
+                BLOCK_SIZE.non_zero(),
+                NUMBER_OF_BLOCKS.non_zero(),
+                |_, _| {},
+            )
+            .unwrap(),
+        );
+
+        let threads: Vec<_> = (0..4u8)
+            .map(|thread_number| {
+                let memory_source = memory_source.clone();
+                spawn(move || {
+                    for _ in 0..1000 {
+                        let blocks: Vec<_> = (0..16)
+                            .map(|_| memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap())
+                            .collect();
+                        for block in &blocks {
+                            unsafe { block.as_ptr().write_bytes(thread_number, BLOCK_SIZE) }
+                        }
+                        for block in &blocks {
+                            assert!(
+                                (0..BLOCK_SIZE)
+                                    .all(|offset| unsafe { *block.as_ptr().add(offset) }
+                                        == thread_number),
+                                "Block was obtained by more than one thread at once"
+                            );
+                            memory_source.release(BLOCK_SIZE.non_zero(), *block)
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap()
+        }
+
+        let blocks: HashSet<_> = (0..NUMBER_OF_BLOCKS)
+            .map(|_| memory_source.obtain(BLOCK_SIZE.non_zero()).unwrap())
+            .collect();
+        assert_eq!(blocks.len(), NUMBER_OF_BLOCKS, "Blocks were lost");
+        assert!(memory_source.obtain(BLOCK_SIZE.non_zero()).is_err());
+    }
+}
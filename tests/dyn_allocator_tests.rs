@@ -0,0 +1,51 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod dyn_allocator_tests {
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::allocators::global::prelude::*;
+    use allocator_suite::extensions::non_null_u8_ext::NonNullU8Ext;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::prelude::mmap::prelude::MemoryMapSource;
+
+    #[test]
+    pub fn allocator_chosen_at_runtime() {
+        for long_lived in &[false, true] {
+            let allocator = new_allocator(*long_lived);
+
+            let allocation = allocator
+                .allocate(64.non_zero(), 8.non_zero())
+                .expect(&format!("Did not allocate"));
+            assert!(
+                allocator.contains(allocation),
+                "Allocation was not within memory range"
+            );
+            allocation.write([0x0Au8; 64]);
+
+            let reallocation = allocator
+                .growing_reallocate(128.non_zero(), 8.non_zero(), 64.non_zero(), allocation)
+                .expect(&format!("Did not reallocate"));
+            assert_eq!(
+                reallocation.read::<[u8; 64]>(),
+                [0x0Au8; 64],
+                "Did not preserve memory contents when growing"
+            );
+
+            allocator.deallocate(128.non_zero(), 8.non_zero(), reallocation);
+        }
+    }
+
+    fn new_allocator(long_lived: bool) -> Box<dyn DynLocalAllocator> {
+        const MEMORY_SIZE: usize = 1 << 16;
+
+        let memory_source = MemoryMapSource::default();
+        if long_lived {
+            Box::new(
+                MultipleBinarySearchTreeAllocator::new(memory_source, MEMORY_SIZE.non_zero())
+                    .unwrap(),
+            )
+        } else {
+            Box::new(BumpAllocator::new(memory_source, MEMORY_SIZE.non_zero()).unwrap())
+        }
+    }
+}
@@ -0,0 +1,40 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod global_alloc_memory_source_tests {
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::allocators::global::prelude::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+
+    const MEMORY_SIZE: usize = 64 * 1024;
+
+    #[test]
+    pub fn allocator_runs_on_system_allocator() {
+        let memory_source = GlobalAllocMemorySource::default();
+        let allocator = BumpAllocator::new(memory_source, MEMORY_SIZE.non_zero()).unwrap();
+
+        let allocation = allocator
+            .allocate(64.non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate"));
+        assert!(allocator.contains(allocation));
+        assert_eq!(
+            allocator.memory_range().from.as_ptr() as usize % 4096,
+            0,
+            "Memory was not aligned"
+        );
+
+        allocator.deallocate(64.non_zero(), 8.non_zero(), allocation);
+    }
+
+    #[test]
+    pub fn obtained_memory_is_zeroed() {
+        let memory_source = GlobalAllocMemorySource::new(std::alloc::System, 64.non_zero());
+
+        let memory = memory_source.obtain(MEMORY_SIZE.non_zero()).unwrap();
+        assert_eq!(memory.as_ptr() as usize % 64, 0, "Memory was not aligned");
+        assert!((0..MEMORY_SIZE).all(|offset| unsafe { *memory.as_ptr().add(offset) } == 0));
+
+        memory_source.release(MEMORY_SIZE.non_zero(), memory);
+    }
+}
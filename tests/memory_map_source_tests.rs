@@ -0,0 +1,47 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(test)]
+mod memory_map_source_tests {
+    use crate::common::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::ffi::CStr;
+
+    const MEMORY_SIZE: usize = 64 * 1024;
+
+    #[test]
+    pub fn labelled_memory_still_obtained_without_kernel_support() {
+        let mut memory_source =
+            MemoryMapSource::new(false, true, true, false, HugePageSize::default(), None);
+        memory_source.set_label(Some(
+            CStr::from_bytes_with_nul(b"memory map source test\0").unwrap(),
+        ));
+
+        let memory = memory_source.obtain(MEMORY_SIZE.non_zero()).unwrap();
+        unsafe { memory.as_ptr().write_bytes(0xFF, MEMORY_SIZE) };
+
+        // Only kernels from 5.17 with `CONFIG_ANON_VMA_NAME` name mappings; labelling again finds out whether this one does.
+        let name = mapping_name(memory);
+        if MemoryMapSource::label_memory(memory, MEMORY_SIZE, memory_source.label()).is_ok() {
+            assert_eq!(name, "[anon:memory map source test]");
+        } else {
+            assert!(!name.starts_with("[anon:"), "Mapping is named {}", name);
+        }
+
+        memory_source.release(MEMORY_SIZE.non_zero(), memory);
+    }
+
+    #[test]
+    pub fn labels_are_truncated_to_maximum_length() {
+        let mut bytes = vec![b'a'; MemoryLabel::MAXIMUM_LENGTH + 1];
+        bytes.push(0);
+        let label = CStr::from_bytes_with_nul(&bytes).unwrap();
+
+        assert_eq!(
+            MemoryLabel::new(label).as_c_str().to_bytes(),
+            &label.to_bytes()[..MemoryLabel::MAXIMUM_LENGTH]
+        );
+    }
+}
@@ -1,12 +1,15 @@
 #![feature(allocator_api)]
 
+mod common;
+
 #[cfg(test)]
 mod multiple_binary_search_tree_allocator_tests {
 
+    use crate::common::*;
     use allocator_suite::allocators::prelude::*;
     use allocator_suite::extensions::usize_ext::UsizeExt;
 
-    use allocator_suite::prelude::mmap::prelude::MemoryMapSource;
+    use allocator_suite::prelude::mmap::prelude::{HugePageSize, MemoryMapSource};
     use std::alloc::AllocError as AllocErr;
     use allocator_suite::allocators::binary_search_trees::binary_search_trees_with_cached_knowledge_of_first_child::BinarySearchTreesWithCachedKnowledgeOfFirstChild;
     use allocator_suite::extensions::non_null_u8_ext::NonNullU8Ext;
@@ -148,6 +151,95 @@ mod multiple_binary_search_tree_allocator_tests {
         assert_allocator_is_empty(&allocator);
     }
 
+    #[test]
+    pub fn deallocated_neighbouring_blocks_coalesce() {
+        let allocator = new_allocator(256);
+
+        let allocations: Vec<_> = (0..4)
+            .map(|_| {
+                allocator
+                    .allocate(64.non_zero(), 8.non_zero())
+                    .expect(&format!("Did not allocate"))
+            })
+            .collect();
+        for allocation in allocations {
+            allocator.deallocate(64.non_zero(), 8.non_zero(), allocation);
+        }
+
+        let _allocation = allocator
+            .allocate(256.non_zero(), 8.non_zero())
+            .expect(&format!("Did not coalesce deallocated blocks"));
+        assert_allocator_is_empty(&allocator);
+    }
+
+    #[test]
+    pub fn trim_decommits_free_blocks() {
+        const MEMORY_SIZE: usize = 1 << 20;
+
+        let memory_source =
+            MemoryMapSource::new(false, true, true, false, HugePageSize::default(), None);
+        let allocator =
+            MultipleBinarySearchTreeAllocator::new(memory_source, MEMORY_SIZE.non_zero()).unwrap();
+
+        let allocation = allocator
+            .allocate((MEMORY_SIZE / 2).non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate"));
+        allocation.write([0x0Au8; 64]);
+        allocator.deallocate((MEMORY_SIZE / 2).non_zero(), 8.non_zero(), allocation);
+
+        assert!(allocator.trim() > 0, "Did not decommit any free blocks");
+
+        let reallocation = allocator
+            .allocate((MEMORY_SIZE / 2).non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate after trimming"));
+        reallocation.write([0x0Bu8; 64]);
+    }
+
+    #[test]
+    pub fn trims_automatically_once_threshold_is_deallocated() {
+        const MEMORY_SIZE: usize = 1 << 20;
+
+        let counts = Counts::default();
+        let allocator = MultipleBinarySearchTreeAllocator::new(
+            CountingMemorySource(&counts),
+            MEMORY_SIZE.non_zero(),
+        )
+        .unwrap()
+        .with_trim_threshold((MEMORY_SIZE / 4).non_zero());
+
+        // Splitting up the single free block frees nearly all of the memory as smaller blocks, but is not a deallocation.
+        let small_allocation = allocator
+            .allocate(64.non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate"));
+        assert_eq!(
+            counts.decommitted_bytes.get(),
+            0,
+            "Block splits counted towards the trim threshold"
+        );
+
+        let below_threshold = allocator
+            .allocate((MEMORY_SIZE / 8).non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate"));
+        let above_threshold = allocator
+            .allocate((MEMORY_SIZE / 2).non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate"));
+
+        allocator.deallocate((MEMORY_SIZE / 8).non_zero(), 8.non_zero(), below_threshold);
+        assert_eq!(
+            counts.decommitted_bytes.get(),
+            0,
+            "Trimmed before the threshold was deallocated"
+        );
+
+        allocator.deallocate((MEMORY_SIZE / 2).non_zero(), 8.non_zero(), above_threshold);
+        assert!(
+            counts.decommitted_bytes.get() > 0,
+            "Did not trim once the threshold was deallocated"
+        );
+
+        allocator.deallocate(64.non_zero(), 8.non_zero(), small_allocation);
+    }
+
     fn test_repeated_small_allocations(memory_size: usize) {
         let allocator = new_allocator(memory_size);
 
@@ -0,0 +1,20 @@
+#[cfg(test)]
+mod non_null_u8_ext_tests {
+    use allocator_suite::extensions::non_null_u8_ext::NonNullU8Ext;
+    use std::ptr::NonNull;
+
+    #[test]
+    pub fn unset_middle_bits_unsets_every_bit_in_range() {
+        let mut word: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+        NonNull::from(&mut word)
+            .cast::<u8>()
+            .unset_middle_bits_of_u64(4, 8);
+        assert_eq!(word, 0xFFFF_FFFF_FFFF_FF0F);
+
+        let mut word: u64 = 0xFFFF_FFFF_FFFF_FFFF;
+        NonNull::from(&mut word)
+            .cast::<u8>()
+            .unset_bottom_bits_of_u64(3);
+        assert_eq!(word, 0xFFFF_FFFF_FFFF_FFF8);
+    }
+}
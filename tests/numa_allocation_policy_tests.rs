@@ -0,0 +1,109 @@
+#[cfg(all(test, any(target_os = "android", target_os = "linux")))]
+mod numa_allocation_policy_tests {
+    use allocator_suite::memory_sources::prelude::*;
+
+    const NOTHING_SUPPORTED: NumaKernelSupport = NumaKernelSupport {
+        preferred_many: false,
+        weighted_interleave: false,
+        bind_numa_balancing: false,
+        preferred_many_numa_balancing: false,
+    };
+
+    const EVERYTHING_SUPPORTED: NumaKernelSupport = NumaKernelSupport {
+        preferred_many: true,
+        weighted_interleave: true,
+        bind_numa_balancing: true,
+        preferred_many_numa_balancing: true,
+    };
+
+    #[test]
+    pub fn unsupported_policies_fall_back() {
+        let numa_node_bit_set: NumaNodeBitSet = "1-2".parse().unwrap();
+        let mut balanced = numa_node_bit_set;
+        balanced.numa_balancing = true;
+
+        assert_eq!(
+            NumaAllocationPolicy::PreferredMany(balanced).or_fallback_for(&NOTHING_SUPPORTED),
+            NumaAllocationPolicy::Preferred(NumaNodeBitSet::new().with_numa_node(1))
+        );
+        assert_eq!(
+            NumaAllocationPolicy::WeightedInterleave(numa_node_bit_set)
+                .or_fallback_for(&NOTHING_SUPPORTED),
+            NumaAllocationPolicy::Interleave(numa_node_bit_set)
+        );
+        assert_eq!(
+            NumaAllocationPolicy::Bind(balanced).or_fallback_for(&NOTHING_SUPPORTED),
+            NumaAllocationPolicy::Bind(numa_node_bit_set)
+        );
+
+        let preferred_many_without_numa_balancing = NumaKernelSupport {
+            preferred_many: true,
+            ..NOTHING_SUPPORTED
+        };
+        assert_eq!(
+            NumaAllocationPolicy::PreferredMany(balanced)
+                .or_fallback_for(&preferred_many_without_numa_balancing),
+            NumaAllocationPolicy::PreferredMany(numa_node_bit_set)
+        );
+        assert!(!NumaAllocationPolicy::PreferredMany(balanced)
+            .is_supported_by(&preferred_many_without_numa_balancing));
+
+        assert_eq!(
+            NumaAllocationPolicy::Local.or_fallback_for(&NOTHING_SUPPORTED),
+            NumaAllocationPolicy::Local
+        );
+    }
+
+    #[test]
+    pub fn supported_policies_do_not_fall_back() {
+        let numa_node_bit_set: NumaNodeBitSet = "1-2".parse().unwrap();
+        let mut balanced = numa_node_bit_set;
+        balanced.numa_balancing = true;
+
+        for policy in &[
+            NumaAllocationPolicy::PreferredMany(balanced),
+            NumaAllocationPolicy::WeightedInterleave(numa_node_bit_set),
+            NumaAllocationPolicy::Bind(balanced),
+        ] {
+            assert!(policy.is_supported_by(&EVERYTHING_SUPPORTED));
+            assert_eq!(policy.or_fallback_for(&EVERYTHING_SUPPORTED), *policy);
+        }
+
+        let interleave = NumaAllocationPolicy::Interleave(balanced);
+        assert!(
+            !interleave.is_supported_by(&EVERYTHING_SUPPORTED),
+            "NUMA balancing is only supported with Bind and PreferredMany"
+        );
+        assert_eq!(
+            interleave.or_fallback_for(&EVERYTHING_SUPPORTED),
+            NumaAllocationPolicy::Interleave(numa_node_bit_set)
+        );
+    }
+
+    #[test]
+    pub fn running_kernel_decides_fallback() {
+        let policy = NumaAllocationPolicy::PreferredMany("0-1".parse().unwrap());
+        assert_eq!(
+            policy.or_fallback(),
+            policy.or_fallback_for(&NumaKernelSupport::of_running_kernel())
+        );
+        assert_eq!(
+            policy.is_supported_by_kernel(),
+            policy.is_supported_by(&NumaKernelSupport::of_running_kernel())
+        );
+    }
+
+    #[test]
+    pub fn memory_is_obtained_with_newer_policies() {
+        let numa_settings = NumaSettings::new(
+            NumaAllocationPolicy::PreferredMany(NumaNodeBitSet::new().with_numa_node(0)),
+            false,
+        );
+        let memory_source = MemoryMapSource::with_numa_settings(numa_settings);
+
+        let size = std::num::NonZeroUsize::new(64 * 1024).unwrap();
+        let memory = memory_source.obtain(size).unwrap();
+        unsafe { memory.as_ptr().write_bytes(0xFF, size.get()) };
+        memory_source.release(size, memory);
+    }
+}
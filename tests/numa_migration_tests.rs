@@ -0,0 +1,56 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(all(test, any(target_os = "android", target_os = "linux")))]
+mod numa_migration_tests {
+    use crate::common::*;
+    use allocator_suite::allocators::global::prelude::*;
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+
+    const NUMBER_OF_PAGES: usize = 16;
+
+    #[test]
+    pub fn pages_on_target_node_are_already_in_place() {
+        let page_size = page_size();
+        let memory_size = NUMBER_OF_PAGES * page_size;
+        let allocator = BumpAllocator::new(
+            MemoryMapSource::new(false, false, true, false, HugePageSize::None, None),
+            memory_size.non_zero(),
+        )
+        .unwrap();
+
+        // Fault in only the first half of the pages.
+        let allocation = allocator
+            .allocate((memory_size / 2).non_zero(), page_size.non_zero())
+            .unwrap();
+        unsafe { allocation.as_ptr().write_bytes(0xFF, memory_size / 2) };
+
+        // Kernel without NUMA support.
+        let report = match migrate_by_moving_pages(allocator.memory_range(), 0) {
+            Ok(report) => report,
+            Err(_) => return,
+        };
+        assert_eq!(
+            report.moved + report.failed + report.already_in_place + report.not_present,
+            NUMBER_OF_PAGES
+        );
+        assert_eq!(report.not_present, NUMBER_OF_PAGES / 2);
+
+        let report = migrate_by_rebinding(
+            allocator.memory_range(),
+            &NumaAllocationPolicy::Bind(NumaNodeBitSet::new().with_numa_node(0)),
+        )
+        .unwrap();
+        assert_eq!(report.failed, 0);
+        assert_eq!(report.moved, 0);
+        assert_eq!(report.already_in_place, NUMBER_OF_PAGES / 2);
+        assert_eq!(
+            unsafe { *allocation.as_ptr() },
+            0xFF,
+            "Contents were not preserved"
+        );
+    }
+}
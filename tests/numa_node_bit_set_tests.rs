@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod numa_node_bit_set_tests {
+    use allocator_suite::memory_sources::prelude::*;
+
+    #[test]
+    pub fn parses_and_formats_node_lists() {
+        let numa_node_bit_set: NumaNodeBitSet = "0-3,7,9-11\n".parse().unwrap();
+        assert_eq!(numa_node_bit_set.len(), 8);
+        assert!(numa_node_bit_set.contains(7));
+        assert!(!numa_node_bit_set.contains(8));
+        assert_eq!(numa_node_bit_set.to_string(), "0-3,7,9-11");
+
+        assert!("".parse::<NumaNodeBitSet>().unwrap().is_empty());
+        assert_eq!(
+            "3-1".parse::<NumaNodeBitSet>(),
+            Err(ParseNumaNodeBitSetError::DescendingRange { from: 3, to: 1 })
+        );
+        assert_eq!(
+            "1024".parse::<NumaNodeBitSet>(),
+            Err(ParseNumaNodeBitSetError::NodeTooLarge(1024))
+        );
+        assert_eq!(
+            "0,x".parse::<NumaNodeBitSet>(),
+            Err(ParseNumaNodeBitSetError::NotANode)
+        );
+    }
+
+    #[test]
+    pub fn set_operations_span_words() {
+        let left: NumaNodeBitSet = "1,64-65,1023".parse().unwrap();
+        let right: NumaNodeBitSet = "65,200".parse().unwrap();
+
+        assert_eq!(left.union(&right).to_string(), "1,64-65,200,1023");
+        assert_eq!(left.intersection(&right).to_string(), "65");
+        assert_eq!(left.difference(&right).to_string(), "1,64,1023");
+        assert_eq!(left.iter().collect::<Vec<_>>(), vec![1, 64, 65, 1023]);
+        assert_eq!(left.first(), Some(1));
+    }
+
+    #[test]
+    pub fn nodes_beyond_maximum_are_never_contained() {
+        let numa_node_bit_set: NumaNodeBitSet = "1023".parse().unwrap();
+        assert!(numa_node_bit_set.contains(1023));
+        assert!(!numa_node_bit_set.contains(1024));
+        assert!(!numa_node_bit_set.contains(u16::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "NUMA node 1024 is not less than")]
+    pub fn inserting_node_beyond_maximum_panics() {
+        NumaNodeBitSet::new().insert_numa_node(1024)
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    pub fn maxnode_covers_highest_node() {
+        let (_, nodemask, maxnode) = NumaNodeBitSet::new().with_numa_node(70).mask_and_size();
+        let nodemask = nodemask.unwrap();
+        assert_eq!(nodemask[1], 1 << 6);
+        assert_eq!(maxnode, 72);
+
+        assert_eq!(NumaNodeBitSet::new().mask_and_size().1, None);
+    }
+
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    #[test]
+    pub fn online_numa_nodes_are_read_from_sysfs() {
+        let online = NumaNodeBitSet::online().unwrap();
+        assert!(!online.is_empty(), "No NUMA node is online");
+    }
+}
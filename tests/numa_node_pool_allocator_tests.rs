@@ -0,0 +1,261 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(test)]
+mod numa_node_pool_allocator_tests {
+    use crate::common::*;
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::cell::RefCell;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::Relaxed;
+
+    const POOL_SIZE: usize = 64 * 1024;
+
+    static FAKE_NUMA_NODE: AtomicUsize = AtomicUsize::new(0);
+
+    fn fake_current_numa_node() -> u16 {
+        FAKE_NUMA_NODE.load(Relaxed) as u16
+    }
+
+    #[test]
+    pub fn allocations_come_from_current_numa_nodes_pool() {
+        let (allocator, first, second) = two_pools(fake_current_numa_node);
+        assert_eq!(
+            allocator.numa_nodes().collect::<Vec<_>>(),
+            vec![first, second]
+        );
+
+        FAKE_NUMA_NODE.store(second as usize, Relaxed);
+        let on_second = allocator.allocate(64.non_zero(), 8.non_zero()).unwrap();
+        assert_eq!(allocator.owning_numa_node(on_second), Some(second));
+
+        FAKE_NUMA_NODE.store(first as usize, Relaxed);
+        let on_first = allocator.allocate(64.non_zero(), 8.non_zero()).unwrap();
+        assert_eq!(allocator.owning_numa_node(on_first), Some(first));
+
+        let nearly_exhausts_first = allocator
+            .allocate((POOL_SIZE - 128).non_zero(), 8.non_zero())
+            .unwrap();
+        let overflows_to_second = allocator.allocate(256.non_zero(), 8.non_zero()).unwrap();
+        assert_eq!(
+            allocator.owning_numa_node(nearly_exhausts_first),
+            Some(first)
+        );
+        assert_eq!(
+            allocator.owning_numa_node(overflows_to_second),
+            Some(second)
+        );
+
+        // Deallocation is routed by address, not by the current NUMA node.
+        allocator.deallocate(256.non_zero(), 8.non_zero(), overflows_to_second);
+        allocator.deallocate(64.non_zero(), 8.non_zero(), on_second);
+    }
+
+    #[test]
+    pub fn allocator_is_shared_between_threads() {
+        use std::sync::Arc;
+        use std::thread::spawn;
+
+        let numa_nodes = NumaNodeBitSet::new().with_numa_node(0);
+        let allocator = Arc::new(
+            NumaNodePoolAllocator::new(
+                &numa_nodes,
+                |_| {
+                    MultipleBinarySearchTreeAllocator::new(
+                        memory_map_source(),
+                        POOL_SIZE.non_zero(),
+                    )
+                },
+                fake_current_numa_node,
+            )
+            .unwrap(),
+        );
+
+        let threads = (0..4)
+            .map(|_| {
+                let allocator = allocator.clone();
+                spawn(move || {
+                    for _ in 0..1000 {
+                        let allocation = allocator.allocate(64.non_zero(), 8.non_zero()).unwrap();
+                        allocator.deallocate(64.non_zero(), 8.non_zero(), allocation)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap()
+        }
+    }
+
+    #[test]
+    pub fn memory_which_can_not_grow_in_its_pool_moves_to_another() {
+        // Allocations come from the first pool.
+        fn numa_node_without_pool() -> u16 {
+            u16::MAX
+        }
+
+        let (allocator, first, second) = two_pools(numa_node_without_pool);
+
+        let growing = allocator.allocate(64.non_zero(), 8.non_zero()).unwrap();
+        unsafe { growing.as_ptr().write_bytes(0xA5, 64) };
+        let exhausts_first = allocator
+            .allocate((POOL_SIZE - 128).non_zero(), 8.non_zero())
+            .unwrap();
+        assert_eq!(allocator.owning_numa_node(exhausts_first), Some(first));
+
+        let grown = allocator
+            .growing_reallocate(256.non_zero(), 8.non_zero(), 64.non_zero(), growing)
+            .expect("Did not move to another pool");
+        assert_eq!(allocator.owning_numa_node(grown), Some(second));
+        let contents = unsafe { std::slice::from_raw_parts(grown.as_ptr(), 64) };
+        assert!(
+            contents.iter().all(|&byte| byte == 0xA5),
+            "Did not copy the memory"
+        );
+
+        allocator.deallocate(256.non_zero(), 8.non_zero(), grown);
+    }
+
+    #[test]
+    pub fn lazy_allocator_is_created_on_first_use() {
+        use std::alloc::AllocError;
+        use std::thread::spawn;
+
+        fn only_numa_node_zero() -> Result<NumaNodeBitSet, AllocError> {
+            Ok(NumaNodeBitSet::new().with_numa_node(0))
+        }
+
+        fn new_pool(
+            memory_map_source: MemoryMapSource,
+        ) -> Result<MultipleBinarySearchTreeAllocator<MemoryMapSource>, AllocError> {
+            MultipleBinarySearchTreeAllocator::new(memory_map_source, POOL_SIZE.non_zero())
+        }
+
+        static LAZY: LazyNumaNodePoolAllocator<MultipleBinarySearchTreeAllocator<MemoryMapSource>> =
+            LazyNumaNodePoolAllocator::new(only_numa_node_zero, new_pool, fake_current_numa_node);
+
+        let threads = (0..4)
+            .map(|_| {
+                spawn(|| {
+                    for _ in 0..1000 {
+                        let allocation = LAZY.allocate(64.non_zero(), 8.non_zero()).unwrap();
+                        LAZY.deallocate(64.non_zero(), 8.non_zero(), allocation)
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap()
+        }
+
+        assert_eq!(
+            LAZY.numa_node_pool_allocator()
+                .unwrap()
+                .numa_nodes()
+                .collect::<Vec<_>>(),
+            vec![0]
+        );
+    }
+
+    #[test]
+    pub fn lazy_allocator_fails_allocations_if_creation_fails() {
+        use std::alloc::AllocError;
+
+        fn no_numa_nodes() -> Result<NumaNodeBitSet, AllocError> {
+            Err(AllocError)
+        }
+
+        fn new_pool(
+            memory_map_source: MemoryMapSource,
+        ) -> Result<BumpAllocator<MemoryMapSource>, AllocError> {
+            BumpAllocator::new(memory_map_source, POOL_SIZE.non_zero())
+        }
+
+        let lazy = LazyNumaNodePoolAllocator::new(no_numa_nodes, new_pool, fake_current_numa_node);
+        assert!(lazy.allocate(64.non_zero(), 8.non_zero()).is_err());
+        assert!(lazy.numa_node_pool_allocator().is_none());
+    }
+
+    #[test]
+    pub fn memory_not_from_any_pool_is_ignored() {
+        let numa_nodes = NumaNodeBitSet::new().with_numa_node(0);
+        let allocator = NumaNodePoolAllocator::new(
+            &numa_nodes,
+            |_| BumpAllocator::new(memory_map_source(), POOL_SIZE.non_zero()),
+            fake_current_numa_node,
+        )
+        .unwrap();
+
+        let memory_source = memory_map_source();
+        let foreign = memory_source.obtain(POOL_SIZE.non_zero()).unwrap();
+        assert_eq!(allocator.owning_numa_node(foreign), None);
+        allocator.deallocate(64.non_zero(), 8.non_zero(), foreign);
+        assert!(allocator
+            .growing_reallocate(128.non_zero(), 8.non_zero(), 64.non_zero(), foreign)
+            .is_err());
+        memory_source.release(POOL_SIZE.non_zero(), foreign)
+    }
+
+    #[test]
+    pub fn pools_are_labelled_by_numa_node() {
+        let numa_nodes = NumaNodeBitSet::new().with_numa_node(0).with_numa_node(1);
+        let labels = RefCell::new(Vec::new());
+        NumaNodePoolAllocator::new(
+            &numa_nodes,
+            |bound_memory_map_source| {
+                labels
+                    .borrow_mut()
+                    .push(bound_memory_map_source.label().unwrap());
+                BumpAllocator::new(memory_map_source(), POOL_SIZE.non_zero())
+            },
+            fake_current_numa_node,
+        )
+        .unwrap();
+
+        let labels = labels.into_inner();
+        assert_eq!(labels[0].as_c_str().to_str(), Ok("numa node 0 pool"));
+        assert_eq!(labels[1].as_c_str().to_str(), Ok("numa node 1 pool"));
+    }
+
+    /// On a machine with only one NUMA node, both pools are bound to it instead.
+    fn two_pools(
+        current_numa_node: CurrentNumaNode,
+    ) -> (
+        NumaNodePoolAllocator<BumpAllocator<MemoryMapSource>>,
+        u16,
+        u16,
+    ) {
+        let online = NumaNodeBitSet::online().unwrap();
+        let single_numa_node = online.len() == 1;
+        let (first, second) = if single_numa_node {
+            (0, 1)
+        } else {
+            let mut online = online.iter();
+            (online.next().unwrap(), online.next().unwrap())
+        };
+        let numa_nodes = NumaNodeBitSet::new()
+            .with_numa_node(first)
+            .with_numa_node(second);
+
+        let allocator = NumaNodePoolAllocator::new(
+            &numa_nodes,
+            |memory_map_source| {
+                let memory_map_source = if single_numa_node {
+                    MemoryMapSource::with_numa_settings(NumaSettings::new(
+                        NumaAllocationPolicy::Bind(NumaNodeBitSet::new().with_numa_node(0)),
+                        false,
+                    ))
+                } else {
+                    memory_map_source
+                };
+                BumpAllocator::new(memory_map_source, POOL_SIZE.non_zero())
+            },
+            current_numa_node,
+        )
+        .unwrap();
+        (allocator, first, second)
+    }
+}
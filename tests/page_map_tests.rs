@@ -0,0 +1,62 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod page_map_tests {
+    use allocator_suite::allocators::global::prelude::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use std::ptr::NonNull;
+
+    #[test]
+    pub fn finds_owner_of_any_address() {
+        let page_map = PageMap::new();
+        let first = 0x1000.non_zero();
+        let second = 0x2000.non_zero();
+
+        page_map
+            .insert(memory_range(0x7F00_0000_0000, 0x7F00_0010_0000), first)
+            .unwrap();
+        page_map
+            .insert(memory_range(0x7F00_0010_0000, 0x7F00_0010_0800), second)
+            .unwrap();
+        page_map
+            .insert(memory_range(0x7F00_0010_0800, 0x7F00_0010_1000), first)
+            .unwrap();
+
+        assert_eq!(
+            page_map.get(address(0x7F00_0000_0000)),
+            PageOwner::Owner(first)
+        );
+        assert_eq!(
+            page_map.get(address(0x7F00_000F_FFFF)),
+            PageOwner::Owner(first)
+        );
+        assert_eq!(
+            page_map.get(address(0x7F00_0010_0000)),
+            PageOwner::Shared,
+            "Page overlapped by two owners was not shared"
+        );
+        assert_eq!(page_map.get(address(0x7F00_0010_1000)), PageOwner::Unowned);
+        assert_eq!(page_map.get(address(0x1000)), PageOwner::Unowned);
+
+        page_map.remove(memory_range(0x7F00_0000_0000, 0x7F00_0010_0000), first);
+        assert_eq!(page_map.get(address(0x7F00_0000_0000)), PageOwner::Unowned);
+        assert_eq!(page_map.get(address(0x7F00_0010_0000)), PageOwner::Shared);
+
+        page_map.remove(memory_range(0x7F00_0010_0800, 0x7F00_0010_1000), first);
+        assert_eq!(page_map.get(address(0x7F00_0010_0000)), PageOwner::Shared);
+        page_map.remove(memory_range(0x7F00_0010_0000, 0x7F00_0010_0800), second);
+        assert_eq!(
+            page_map.get(address(0x7F00_0010_0000)),
+            PageOwner::Unowned,
+            "Shared page was not reset once all its owners were removed"
+        );
+    }
+
+    fn memory_range(from: usize, to: usize) -> MemoryRange {
+        MemoryRange::new(address(from), address(to))
+    }
+
+    fn address(address: usize) -> NonNull<u8> {
+        NonNull::new(address as *mut u8).unwrap()
+    }
+}
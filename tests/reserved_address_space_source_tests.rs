@@ -0,0 +1,137 @@
+#![feature(allocator_api)]
+
+mod common;
+
+#[cfg(test)]
+mod reserved_address_space_source_tests {
+    use crate::common::*;
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::extensions::non_null_u8_ext::NonNullU8Ext;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::ffi::CStr;
+
+    const RESERVATION_SIZE: usize = 64 * 1024 * 1024;
+
+    const REGION_SIZE: usize = 1024 * 1024;
+
+    #[test]
+    pub fn regions_are_committed_within_reservation_and_reused() {
+        let memory_source =
+            ReservedAddressSpaceSource::new(RESERVATION_SIZE.non_zero(), HugePageSize::None, None)
+                .unwrap();
+
+        let first = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        let second = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        assert!(memory_source.contains(first));
+        assert!(memory_source.contains(second));
+        assert_ne!(first, second);
+
+        unsafe {
+            first.as_ptr().write_bytes(0xFF, REGION_SIZE);
+            second.as_ptr().write_bytes(0xFF, REGION_SIZE);
+        }
+
+        memory_source.release(REGION_SIZE.non_zero(), first);
+        let third = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        assert_eq!(third, first, "Released region was not reused");
+        assert_eq!(
+            unsafe { *third.as_ptr() },
+            0,
+            "Released region was not decommitted"
+        );
+
+        memory_source.release(REGION_SIZE.non_zero(), second);
+        memory_source.release(REGION_SIZE.non_zero(), third);
+    }
+
+    #[test]
+    pub fn obtain_fails_once_reservation_is_exhausted() {
+        let memory_source =
+            ReservedAddressSpaceSource::new(RESERVATION_SIZE.non_zero(), HugePageSize::None, None)
+                .unwrap();
+
+        assert!(memory_source
+            .obtain((RESERVATION_SIZE + 1).non_zero())
+            .is_err());
+        let allocator = BumpAllocator::new(memory_source, REGION_SIZE.non_zero()).unwrap();
+        assert!(allocator.allocate(64.non_zero(), 8.non_zero()).is_ok());
+    }
+
+    #[test]
+    pub fn unused_granules_are_found_across_bitmap_words() {
+        let page_size = page_size();
+        let memory_source = ReservedAddressSpaceSource::new(
+            (1024 * page_size).non_zero(),
+            HugePageSize::None,
+            None,
+        )
+        .unwrap();
+
+        let pages: Vec<_> = (0..)
+            .map(|_| memory_source.obtain(page_size.non_zero()))
+            .take_while(|page| page.is_ok())
+            .map(|page| page.unwrap())
+            .collect();
+        assert!(pages.len() > 128);
+
+        memory_source.release(page_size.non_zero(), pages[10]);
+        for page in &pages[63..67] {
+            memory_source.release(page_size.non_zero(), *page)
+        }
+
+        assert_eq!(
+            memory_source.obtain((page_size * 4).non_zero()).unwrap(),
+            pages[63],
+            "Did not find a run of unused granules spanning two bitmap words"
+        );
+        assert_eq!(
+            memory_source.obtain(page_size.non_zero()).unwrap(),
+            pages[10]
+        );
+        assert!(memory_source.obtain(page_size.non_zero()).is_err());
+    }
+
+    #[test]
+    pub fn decommit_uses_decommit_advice() {
+        let page_size = page_size();
+        let mut memory_source =
+            ReservedAddressSpaceSource::new(RESERVATION_SIZE.non_zero(), HugePageSize::None, None)
+                .unwrap();
+        memory_source.set_decommit_advice(DecommitAdvice::Free);
+
+        let region = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+        unsafe { region.as_ptr().write_bytes(0xFF, REGION_SIZE) };
+
+        assert_eq!(
+            memory_source.decommit((page_size * 2 + 1).non_zero(), region.add(1)),
+            page_size,
+            "Did not decommit only the whole page within the memory"
+        );
+        unsafe { region.as_ptr().add(page_size).write_bytes(0xFF, page_size) };
+
+        memory_source.release(REGION_SIZE.non_zero(), region);
+    }
+
+    #[test]
+    pub fn obtained_memory_is_labelled() {
+        let mut memory_source =
+            ReservedAddressSpaceSource::new(RESERVATION_SIZE.non_zero(), HugePageSize::None, None)
+                .unwrap();
+        memory_source.set_label(Some(
+            CStr::from_bytes_with_nul(b"reserved address space source test\0").unwrap(),
+        ));
+
+        let region = memory_source.obtain(REGION_SIZE.non_zero()).unwrap();
+
+        // Only kernels from 5.17 with `CONFIG_ANON_VMA_NAME` name mappings; labelling again finds out whether this one does.
+        let name = mapping_name(region);
+        if MemoryMapSource::label_memory(region, REGION_SIZE, memory_source.label()).is_ok() {
+            assert_eq!(name, "[anon:reserved address space source test]");
+        } else {
+            assert!(!name.starts_with("[anon:"), "Mapping is named {}", name);
+        }
+
+        memory_source.release(REGION_SIZE.non_zero(), region);
+    }
+}
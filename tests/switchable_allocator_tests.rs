@@ -13,6 +13,10 @@ mod switchable_allocator_tests {
 
     // General imports
     use allocator_suite::adaptors::prelude::*;
+    use allocator_suite::allocators::allocator::Allocator;
+    use allocator_suite::allocators::global::prelude::*;
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::memory_sources::prelude::*;
     use std::alloc::System;
 
     switchable_allocator!(
@@ -27,4 +31,628 @@ mod switchable_allocator_tests {
     pub fn switchable_generation() {
         let _vec = Vec::<usize>::with_capacity(1234);
     }
+
+    #[test]
+    pub fn switchable_allocator_as_plain_value() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+
+        let first = new_switchable_allocator();
+        let second = new_switchable_allocator();
+
+        first.initialize_thread_local_allocator(
+            MultipleBinarySearchTreeAllocator::new(MemoryMapSource::default(), 4096.non_zero())
+                .unwrap(),
+        );
+        assert!(
+            second.thread_local_allocator().is_none(),
+            "Per-thread state was shared between instances"
+        );
+
+        let allocation = first
+            .callback_with_thread_local_allocator(|| first.allocate(64.non_zero(), 8.non_zero()))
+            .expect(&format!("Did not allocate"));
+        assert!(
+            first
+                .thread_local_allocator_unchecked()
+                .contains(allocation),
+            "Did not allocate from the thread local allocator"
+        );
+        assert_eq!(
+            first.save_current_allocator_in_use(),
+            CurrentAllocatorInUse::Global,
+            "Did not restore the current allocator in use"
+        );
+
+        first.deallocate(64.non_zero(), 8.non_zero(), allocation);
+        first.drop_thread_local_allocator();
+    }
+
+    #[test]
+    pub fn thread_local_allocator_retired_on_thread_exit() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+        use std::ptr::NonNull;
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering::SeqCst;
+        use std::thread::spawn;
+
+        static SWITCHABLE_ALLOCATOR: SwitchableAllocator<
+            BumpAllocator<ArenaMemorySource<MemoryMapSource>>,
+            MultipleBinarySearchTreeAllocator<MemoryMapSource>,
+            GlobalAllocToAllocatorAdaptor<System>,
+        > = unsafe { SwitchableAllocator::new(GlobalAllocToAllocatorAdaptor(System)) };
+
+        static LEAKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+        fn leaked_thread_local_allocator_hook(leaked_bytes: usize, _memory_range: MemoryRange) {
+            LEAKED_BYTES.fetch_add(leaked_bytes, SeqCst);
+        }
+
+        SWITCHABLE_ALLOCATOR
+            .set_leaked_thread_local_allocator_hook(Some(leaked_thread_local_allocator_hook));
+
+        let allocation = spawn(|| {
+            SWITCHABLE_ALLOCATOR.initialize_thread_local_allocator(
+                MultipleBinarySearchTreeAllocator::new(MemoryMapSource::default(), 4096.non_zero())
+                    .unwrap(),
+            );
+
+            let freed = SWITCHABLE_ALLOCATOR
+                .callback_with_thread_local_allocator(|| {
+                    SWITCHABLE_ALLOCATOR.allocate(32.non_zero(), 8.non_zero())
+                })
+                .expect(&format!("Did not allocate"));
+            SWITCHABLE_ALLOCATOR.deallocate(32.non_zero(), 8.non_zero(), freed);
+
+            let leaked = SWITCHABLE_ALLOCATOR
+                .callback_with_thread_local_allocator(|| {
+                    SWITCHABLE_ALLOCATOR.allocate(64.non_zero(), 8.non_zero())
+                })
+                .expect(&format!("Did not allocate"));
+            leaked.as_ptr() as usize
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(
+            LEAKED_BYTES.load(SeqCst),
+            64,
+            "Did not report leaked bytes on thread exit"
+        );
+
+        // Freeing memory of a retired thread local allocator is ignored rather than passed to the global allocator.
+        SWITCHABLE_ALLOCATOR.deallocate(
+            64.non_zero(),
+            8.non_zero(),
+            NonNull::new(allocation as *mut u8).unwrap(),
+        );
+    }
+
+    #[test]
+    pub fn dropped_thread_local_allocator_with_live_allocations_is_retired() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering::SeqCst;
+
+        static LEAKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+        fn leaked_thread_local_allocator_hook(leaked_bytes: usize, _memory_range: MemoryRange) {
+            LEAKED_BYTES.fetch_add(leaked_bytes, SeqCst);
+        }
+
+        let switchable_allocator = new_switchable_allocator();
+        switchable_allocator
+            .set_leaked_thread_local_allocator_hook(Some(leaked_thread_local_allocator_hook));
+        switchable_allocator.initialize_thread_local_allocator(
+            MultipleBinarySearchTreeAllocator::new(MemoryMapSource::default(), 4096.non_zero())
+                .unwrap(),
+        );
+
+        let leaked = switchable_allocator
+            .callback_with_thread_local_allocator(|| {
+                switchable_allocator.allocate(64.non_zero(), 8.non_zero())
+            })
+            .expect(&format!("Did not allocate"));
+        switchable_allocator.drop_thread_local_allocator();
+
+        assert_eq!(
+            LEAKED_BYTES.load(SeqCst),
+            64,
+            "Did not report leaked bytes when dropped"
+        );
+
+        // The memory of the retired thread local allocator is still mapped.
+        unsafe { leaked.as_ptr().write_bytes(0xFF, 64) };
+        switchable_allocator.deallocate(64.non_zero(), 8.non_zero(), leaked);
+    }
+
+    #[test]
+    pub fn retired_registration_is_reused_once_leaked_bytes_are_freed() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+        use std::ptr::NonNull;
+
+        let global_allocator = GlobalAllocToAllocatorAdaptor(System);
+        let local_allocator_registry = LocalAllocatorRegistry::new();
+
+        let registration = local_allocator_registry
+            .register(
+                &global_allocator,
+                memory_range(0x7F00_0000_0000, 0x7F00_0010_0000),
+            )
+            .unwrap();
+        local_allocator_registry.retire_until_freed(registration, &global_allocator, 96.non_zero());
+
+        let address = NonNull::new(0x7F00_0000_0000 as *mut u8).unwrap();
+        local_allocator_registry
+            .free_remotely(
+                registration,
+                &global_allocator,
+                64.non_zero(),
+                8.non_zero(),
+                address,
+            )
+            .unwrap();
+        assert!(
+            local_allocator_registry.find(address).is_some(),
+            "Vacated whilst bytes were still leaked"
+        );
+
+        local_allocator_registry
+            .free_remotely(
+                registration,
+                &global_allocator,
+                32.non_zero(),
+                8.non_zero(),
+                address,
+            )
+            .unwrap();
+        assert!(
+            local_allocator_registry.find(address).is_none(),
+            "Did not vacate once all leaked bytes were freed"
+        );
+
+        let reused = local_allocator_registry
+            .register(
+                &global_allocator,
+                memory_range(0x7F00_0020_0000, 0x7F00_0030_0000),
+            )
+            .unwrap();
+        assert_eq!(
+            reused as *const LocalAllocatorRegistration,
+            registration as *const LocalAllocatorRegistration,
+            "Did not reuse the vacated registration"
+        );
+
+        local_allocator_registry.vacate(reused);
+        unsafe { local_allocator_registry.free(&global_allocator) }
+    }
+
+    #[test]
+    pub fn remote_free_fails_only_if_too_small_to_hold_its_queue_entry() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+        use allocator_suite::memory_address::MemoryAddress;
+        use std::alloc::AllocError;
+        use std::num::NonZeroUsize;
+        use std::ptr::NonNull;
+
+        #[derive(Debug)]
+        struct ExhaustedAllocator;
+
+        impl Allocator for ExhaustedAllocator {
+            fn allocate(
+                &self,
+                _non_zero_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+            ) -> Result<MemoryAddress, AllocError> {
+                Err(AllocError)
+            }
+
+            fn deallocate(
+                &self,
+                _non_zero_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+                _current_memory: MemoryAddress,
+            ) {
+                panic!("Deallocated a queue entry which was never allocated")
+            }
+
+            fn growing_reallocate(
+                &self,
+                _non_zero_new_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+                _non_zero_current_size: NonZeroUsize,
+                _current_memory: MemoryAddress,
+            ) -> Result<MemoryAddress, AllocError> {
+                Err(AllocError)
+            }
+
+            fn shrinking_reallocate(
+                &self,
+                _non_zero_new_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+                _non_zero_current_size: NonZeroUsize,
+                _current_memory: MemoryAddress,
+            ) -> Result<MemoryAddress, AllocError> {
+                Err(AllocError)
+            }
+        }
+
+        let global_allocator = GlobalAllocToAllocatorAdaptor(System);
+        let local_allocator_registry = LocalAllocatorRegistry::new();
+
+        let mut memory = vec![0u64; 8];
+        let from = memory.as_mut_ptr() as usize;
+        let registration = local_allocator_registry
+            .register(&global_allocator, memory_range(from, from + 64))
+            .unwrap();
+
+        let small = NonNull::new(from as *mut u8).unwrap();
+        assert!(
+            local_allocator_registry
+                .free_remotely(
+                    registration,
+                    &ExhaustedAllocator,
+                    8.non_zero(),
+                    8.non_zero(),
+                    small,
+                )
+                .is_err(),
+            "Did not report that the remote free could not be queued"
+        );
+
+        let large = NonNull::new((from + 32) as *mut u8).unwrap();
+        local_allocator_registry
+            .free_remotely(
+                registration,
+                &ExhaustedAllocator,
+                32.non_zero(),
+                8.non_zero(),
+                large,
+            )
+            .expect("Did not queue the remote free in the freed memory itself");
+
+        let mut drained = Vec::new();
+        registration.drain_remote_frees(
+            &ExhaustedAllocator,
+            |non_zero_size, _non_zero_power_of_two_alignment, current_memory| {
+                drained.push((non_zero_size.get(), current_memory))
+            },
+        );
+        assert_eq!(drained, vec![(32, large)]);
+
+        local_allocator_registry.vacate(registration);
+        unsafe { local_allocator_registry.free(&global_allocator) }
+    }
+
+    #[test]
+    pub fn memory_freed_on_another_thread_is_returned_to_owning_thread() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+        use std::ptr::NonNull;
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering::SeqCst;
+        use std::sync::mpsc::channel;
+        use std::thread::spawn;
+
+        static SWITCHABLE_ALLOCATOR: SwitchableAllocator<
+            BumpAllocator<ArenaMemorySource<MemoryMapSource>>,
+            MultipleBinarySearchTreeAllocator<MemoryMapSource>,
+            GlobalAllocToAllocatorAdaptor<System>,
+        > = unsafe { SwitchableAllocator::new(GlobalAllocToAllocatorAdaptor(System)) };
+
+        static LEAKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+        fn leaked_thread_local_allocator_hook(leaked_bytes: usize, _memory_range: MemoryRange) {
+            LEAKED_BYTES.fetch_add(leaked_bytes, SeqCst);
+        }
+
+        SWITCHABLE_ALLOCATOR
+            .set_leaked_thread_local_allocator_hook(Some(leaked_thread_local_allocator_hook));
+
+        let (send_allocation, receive_allocation) = channel();
+        let (send_freed, receive_freed) = channel();
+
+        let owning_thread = spawn(move || {
+            SWITCHABLE_ALLOCATOR.initialize_thread_local_allocator(
+                MultipleBinarySearchTreeAllocator::new(MemoryMapSource::default(), 4096.non_zero())
+                    .unwrap(),
+            );
+
+            let allocation = SWITCHABLE_ALLOCATOR
+                .callback_with_thread_local_allocator(|| {
+                    SWITCHABLE_ALLOCATOR.allocate(64.non_zero(), 8.non_zero())
+                })
+                .expect(&format!("Did not allocate"));
+            send_allocation.send(allocation.as_ptr() as usize).unwrap();
+            receive_freed.recv().unwrap();
+
+            // Allocating drains the memory freed by the other thread.
+            SWITCHABLE_ALLOCATOR
+                .callback_with_thread_local_allocator(|| {
+                    SWITCHABLE_ALLOCATOR.allocate(32.non_zero(), 8.non_zero())
+                })
+                .expect(&format!("Did not allocate"));
+        });
+
+        let allocation = receive_allocation.recv().unwrap();
+        SWITCHABLE_ALLOCATOR.deallocate(
+            64.non_zero(),
+            8.non_zero(),
+            NonNull::new(allocation as *mut u8).unwrap(),
+        );
+        send_freed.send(()).unwrap();
+        owning_thread.join().unwrap();
+
+        assert_eq!(
+            LEAKED_BYTES.load(SeqCst),
+            32,
+            "Memory freed on another thread was not returned to the owning thread"
+        );
+    }
+
+    #[test]
+    pub fn thread_local_allocator_created_lazily() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+
+        let switchable_allocator = new_switchable_allocator();
+        switchable_allocator.set_thread_local_allocator_factory(Some(|| {
+            MultipleBinarySearchTreeAllocator::new(MemoryMapSource::default(), 4096.non_zero())
+        }));
+
+        let allocation = switchable_allocator
+            .callback_with_thread_local_allocator(|| {
+                switchable_allocator.allocate(64.non_zero(), 8.non_zero())
+            })
+            .expect(&format!("Did not allocate"));
+        assert!(
+            switchable_allocator
+                .thread_local_allocator_unchecked()
+                .contains(allocation),
+            "Did not allocate from a lazily created thread local allocator"
+        );
+
+        switchable_allocator.deallocate(64.non_zero(), 8.non_zero(), allocation);
+    }
+
+    #[test]
+    pub fn thread_local_allocator_falls_back_to_global_allocator() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+        use std::alloc::AllocError;
+
+        let switchable_allocator = new_switchable_allocator();
+        switchable_allocator.set_thread_local_allocator_factory(Some(|| Err(AllocError)));
+
+        let allocation = switchable_allocator
+            .callback_with_thread_local_allocator(|| {
+                switchable_allocator.allocate(64.non_zero(), 8.non_zero())
+            })
+            .expect(&format!("Did not fall back to the global allocator"));
+        assert!(
+            switchable_allocator.thread_local_allocator().is_none(),
+            "Created a thread local allocator"
+        );
+
+        switchable_allocator.deallocate(64.non_zero(), 8.non_zero(), allocation);
+    }
+
+    #[test]
+    pub fn unregistrable_thread_local_allocator_falls_back_to_global_allocator() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+        use allocator_suite::memory_address::MemoryAddress;
+        use std::alloc::AllocError;
+        use std::num::NonZeroUsize;
+
+        /// Claims memory beyond the 48-bit addresses a page map covers, as on a 57-bit address space.
+        #[derive(Debug)]
+        struct BeyondPageMapAllocator;
+
+        impl Allocator for BeyondPageMapAllocator {
+            fn allocate(
+                &self,
+                _non_zero_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+            ) -> Result<MemoryAddress, AllocError> {
+                Err(AllocError)
+            }
+
+            fn deallocate(
+                &self,
+                _non_zero_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+                _current_memory: MemoryAddress,
+            ) {
+                unreachable!()
+            }
+
+            fn growing_reallocate(
+                &self,
+                _non_zero_new_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+                _non_zero_current_size: NonZeroUsize,
+                _current_memory: MemoryAddress,
+            ) -> Result<MemoryAddress, AllocError> {
+                Err(AllocError)
+            }
+
+            fn shrinking_reallocate(
+                &self,
+                _non_zero_new_size: NonZeroUsize,
+                _non_zero_power_of_two_alignment: NonZeroUsize,
+                _non_zero_current_size: NonZeroUsize,
+                _current_memory: MemoryAddress,
+            ) -> Result<MemoryAddress, AllocError> {
+                Err(AllocError)
+            }
+        }
+
+        impl LocalAllocator for BeyondPageMapAllocator {
+            fn memory_range(&self) -> MemoryRange {
+                memory_range(0x0100_0000_0000_0000, 0x0100_0000_0010_0000)
+            }
+        }
+
+        let switchable_allocator: SwitchableAllocator<
+            BumpAllocator<ArenaMemorySource<MemoryMapSource>>,
+            BeyondPageMapAllocator,
+            GlobalAllocToAllocatorAdaptor<System>,
+        > = unsafe { SwitchableAllocator::new(GlobalAllocToAllocatorAdaptor(System)) };
+        switchable_allocator.initialize_thread_local_allocator(BeyondPageMapAllocator);
+        assert!(
+            switchable_allocator.thread_local_allocator().is_none(),
+            "Kept a thread local allocator which could not be registered"
+        );
+
+        let allocation = switchable_allocator
+            .callback_with_thread_local_allocator(|| {
+                switchable_allocator.allocate(64.non_zero(), 8.non_zero())
+            })
+            .expect(&format!("Did not fall back to the global allocator"));
+        switchable_allocator.deallocate(64.non_zero(), 8.non_zero(), allocation);
+    }
+
+    #[test]
+    pub fn exhausted_thread_local_allocator_overflows_to_global_allocator() {
+        use allocator_suite::extensions::usize_ext::UsizeExt;
+
+        let switchable_allocator = new_switchable_allocator();
+        switchable_allocator.initialize_thread_local_allocator(
+            MultipleBinarySearchTreeAllocator::new(MemoryMapSource::default(), 4096.non_zero())
+                .unwrap(),
+        );
+
+        assert!(
+            switchable_allocator
+                .callback_with_thread_local_allocator(|| {
+                    switchable_allocator.allocate(8192.non_zero(), 8.non_zero())
+                })
+                .is_err(),
+            "Did not fail with the default overflow policy"
+        );
+
+        switchable_allocator.set_overflow_policy(OverflowPolicy::Global);
+        let allocation = switchable_allocator
+            .callback_with_thread_local_allocator(|| {
+                switchable_allocator.allocate(8192.non_zero(), 8.non_zero())
+            })
+            .expect(&format!("Did not overflow to the global allocator"));
+        assert!(
+            !switchable_allocator
+                .thread_local_allocator_unchecked()
+                .contains(allocation),
+            "Allocated from the exhausted thread local allocator"
+        );
+        switchable_allocator.deallocate(8192.non_zero(), 8.non_zero(), allocation);
+
+        assert_eq!(
+            switchable_allocator.overflow_statistics(),
+            OverflowStatistics {
+                coroutine_local_overflows: 0,
+                thread_local_overflows: 2,
+                unsatisfied_overflows: 1,
+            }
+        );
+    }
+
+    #[test]
+    pub fn overflow_policy_changed_on_another_thread_is_never_torn() {
+        use std::num::NonZeroUsize;
+        use std::sync::atomic::AtomicBool;
+        use std::sync::atomic::Ordering::SeqCst;
+        use std::thread::spawn;
+
+        static SWITCHABLE_ALLOCATOR: SwitchableAllocator<
+            BumpAllocator<ArenaMemorySource<MemoryMapSource>>,
+            MultipleBinarySearchTreeAllocator<MemoryMapSource>,
+            GlobalAllocToAllocatorAdaptor<System>,
+        > = unsafe { SwitchableAllocator::new(GlobalAllocToAllocatorAdaptor(System)) };
+
+        static FINISHED: AtomicBool = AtomicBool::new(false);
+
+        fn overflow_hook(
+            _overflowed: CurrentAllocatorInUse,
+            _non_zero_size: NonZeroUsize,
+            _non_zero_power_of_two_alignment: NonZeroUsize,
+        ) -> Option<CurrentAllocatorInUse> {
+            Some(CurrentAllocatorInUse::Global)
+        }
+
+        let hook = OverflowPolicy::Hook(overflow_hook);
+        let secondary = OverflowPolicy::Secondary(CurrentAllocatorInUse::ThreadLocal);
+
+        let changing_thread = spawn(move || {
+            while !FINISHED.load(SeqCst) {
+                SWITCHABLE_ALLOCATOR.set_overflow_policy(hook);
+                SWITCHABLE_ALLOCATOR.set_overflow_policy(secondary);
+            }
+        });
+
+        for _ in 0..1_000_000 {
+            let overflow_policy = SWITCHABLE_ALLOCATOR.overflow_policy();
+            assert!(
+                overflow_policy == OverflowPolicy::Fail
+                    || overflow_policy == hook
+                    || overflow_policy == secondary,
+                "Read a torn overflow policy {:?}",
+                overflow_policy
+            );
+        }
+
+        FINISHED.store(true, SeqCst);
+        changing_thread.join().unwrap();
+    }
+
+    #[test]
+    pub fn guards_restore_current_allocator_in_use() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let switchable_allocator = new_switchable_allocator();
+
+        {
+            let _thread_local = switchable_allocator.use_thread_local();
+            {
+                let _coroutine_local = switchable_allocator.use_coroutine_local();
+                assert_eq!(
+                    switchable_allocator.save_current_allocator_in_use(),
+                    CurrentAllocatorInUse::CoroutineLocal
+                );
+            }
+            assert_eq!(
+                switchable_allocator.save_current_allocator_in_use(),
+                CurrentAllocatorInUse::ThreadLocal,
+                "Nested guard did not restore"
+            );
+        }
+        assert_eq!(
+            switchable_allocator.save_current_allocator_in_use(),
+            CurrentAllocatorInUse::Global,
+            "Guard did not restore"
+        );
+
+        let unwound = catch_unwind(AssertUnwindSafe(|| {
+            let _thread_local = switchable_allocator.use_thread_local();
+            panic!("Unwinding")
+        }));
+        assert!(unwound.is_err());
+        assert_eq!(
+            switchable_allocator.save_current_allocator_in_use(),
+            CurrentAllocatorInUse::Global,
+            "Guard did not restore during unwinding"
+        );
+    }
+
+    fn new_switchable_allocator() -> SwitchableAllocator<
+        BumpAllocator<ArenaMemorySource<MemoryMapSource>>,
+        MultipleBinarySearchTreeAllocator<MemoryMapSource>,
+        GlobalAllocToAllocatorAdaptor<System>,
+    > {
+        // Not yet used, so can still be moved to the caller.
+        unsafe { SwitchableAllocator::new(GlobalAllocToAllocatorAdaptor(System)) }
+    }
+
+    fn memory_range(from: usize, to: usize) -> MemoryRange {
+        use std::ptr::NonNull;
+
+        MemoryRange::new(
+            NonNull::new(from as *mut u8).unwrap(),
+            NonNull::new(to as *mut u8).unwrap(),
+        )
+    }
 }
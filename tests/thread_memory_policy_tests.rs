@@ -0,0 +1,57 @@
+#[cfg(all(test, any(target_os = "android", target_os = "linux")))]
+mod thread_memory_policy_tests {
+    use allocator_suite::memory_sources::prelude::*;
+
+    #[test]
+    pub fn guard_restores_previous_thread_memory_policy() {
+        let previous = match NumaAllocationPolicy::of_current_thread() {
+            Ok(previous) => previous,
+
+            // Kernel without NUMA support.
+            Err(_) => return,
+        };
+
+        {
+            let guard = NumaAllocationPolicy::Local
+                .use_for_current_thread()
+                .unwrap();
+            assert_eq!(guard.restore_to(), &previous);
+            assert_eq!(
+                NumaAllocationPolicy::of_current_thread().unwrap(),
+                NumaAllocationPolicy::Local
+            );
+
+            // Memory from `System` is now faulted in according to the thread's memory policy.
+            let vector = vec![0xFFu8; 64 * 1024];
+            assert_eq!(vector[0], 0xFF);
+        }
+
+        assert_eq!(NumaAllocationPolicy::of_current_thread().unwrap(), previous);
+    }
+
+    #[test]
+    pub fn binding_to_no_nodes_is_refused() {
+        if NumaAllocationPolicy::of_current_thread().is_err() {
+            return;
+        }
+
+        assert!(NumaAllocationPolicy::Bind(NumaNodeBitSet::new())
+            .set_for_current_thread()
+            .is_err());
+    }
+
+    #[test]
+    pub fn preferring_no_nodes_is_local() {
+        if NumaAllocationPolicy::of_current_thread().is_err() {
+            return;
+        }
+
+        let _guard = NumaAllocationPolicy::Preferred(NumaNodeBitSet::new())
+            .use_for_current_thread()
+            .unwrap();
+        assert_eq!(
+            NumaAllocationPolicy::of_current_thread().unwrap(),
+            NumaAllocationPolicy::Local
+        );
+    }
+}
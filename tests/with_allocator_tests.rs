@@ -0,0 +1,145 @@
+#![feature(allocator_api)]
+
+#[cfg(test)]
+mod with_allocator_tests {
+    use allocator_suite::adaptors::prelude::*;
+    use allocator_suite::allocators::allocator::Allocator;
+    use allocator_suite::allocators::global::prelude::*;
+    use allocator_suite::allocators::prelude::*;
+    use allocator_suite::extensions::usize_ext::UsizeExt;
+    use allocator_suite::memory_address::MemoryAddress;
+    use allocator_suite::memory_sources::prelude::*;
+    use std::alloc::System;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::ptr::null;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    type TestSwitchableAllocator = SwitchableAllocator<
+        BumpAllocator<ArenaMemorySource<MemoryMapSource>>,
+        MultipleBinarySearchTreeAllocator<MemoryMapSource>,
+        GlobalAllocToAllocatorAdaptor<System>,
+    >;
+
+    #[test]
+    pub fn each_task_allocates_from_its_own_coroutine_local_allocator() {
+        let switchable_allocator: TestSwitchableAllocator =
+            unsafe { SwitchableAllocator::new(GlobalAllocToAllocatorAdaptor(System)) };
+
+        let first = WithAllocator::new(
+            &switchable_allocator,
+            new_coroutine_local_allocator(),
+            allocate_either_side_of_yield(&switchable_allocator),
+        );
+        let second = WithAllocator::new(
+            &switchable_allocator,
+            new_coroutine_local_allocator(),
+            allocate_either_side_of_yield(&switchable_allocator),
+        );
+
+        let memory_ranges = run_to_completion(vec![Box::pin(first), Box::pin(second)]);
+        assert_ne!(
+            memory_ranges[0], memory_ranges[1],
+            "Tasks shared a coroutine local allocator"
+        );
+
+        assert!(
+            switchable_allocator.coroutine_local_allocator().is_none(),
+            "Coroutine local allocator was not swapped out"
+        );
+        assert_eq!(
+            switchable_allocator.save_current_allocator_in_use(),
+            CurrentAllocatorInUse::Global,
+            "Current allocator in use was not restored"
+        );
+    }
+
+    async fn allocate_either_side_of_yield(
+        switchable_allocator: &TestSwitchableAllocator,
+    ) -> MemoryRange {
+        let before = allocate_from_coroutine_local_allocator(switchable_allocator);
+        YieldOnce(false).await;
+        let after = allocate_from_coroutine_local_allocator(switchable_allocator);
+
+        let coroutine_local_allocator = switchable_allocator.coroutine_local_allocator_unchecked();
+        assert!(
+            coroutine_local_allocator.contains(before),
+            "Coroutine local allocator changed between polls"
+        );
+        assert!(coroutine_local_allocator.contains(after));
+        coroutine_local_allocator.memory_range()
+    }
+
+    fn allocate_from_coroutine_local_allocator(
+        switchable_allocator: &TestSwitchableAllocator,
+    ) -> MemoryAddress {
+        let allocation = switchable_allocator
+            .allocate(64.non_zero(), 8.non_zero())
+            .expect(&format!("Did not allocate"));
+        assert!(
+            switchable_allocator
+                .coroutine_local_allocator_unchecked()
+                .contains(allocation),
+            "Did not allocate from the coroutine local allocator"
+        );
+        allocation
+    }
+
+    fn new_coroutine_local_allocator() -> BumpAllocator<ArenaMemorySource<MemoryMapSource>> {
+        const MEMORY_SIZE: usize = 64 * 1024;
+
+        let memory_source = ArenaMemorySource::new(
+            MemoryMapSource::default(),
+            MEMORY_SIZE.non_zero(),
+            1.non_zero(),
+            |_, _| {},
+        )
+        .unwrap();
+        BumpAllocator::new(memory_source, MEMORY_SIZE.non_zero()).unwrap()
+    }
+
+    /// A minimal, single-threaded executor which polls each future in turn until all are ready, returning their outputs in order.
+    fn run_to_completion<T>(mut futures: Vec<Pin<Box<dyn Future<Output = T> + '_>>>) -> Vec<T> {
+        let waker = unsafe { Waker::from_raw(no_op_raw_waker()) };
+        let mut context = Context::from_waker(&waker);
+
+        let mut outputs: Vec<Option<T>> = futures.iter().map(|_| None).collect();
+        while outputs.iter().any(Option::is_none) {
+            for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+                if output.is_none() {
+                    if let Poll::Ready(ready) = future.as_mut().poll(&mut context) {
+                        *output = Some(ready)
+                    }
+                }
+            }
+        }
+        outputs.into_iter().map(Option::unwrap).collect()
+    }
+
+    fn no_op_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            no_op_raw_waker()
+        }
+
+        fn no_op(_: *const ()) {}
+
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, no_op, no_op, no_op);
+        RawWaker::new(null(), &VTABLE)
+    }
+
+    struct YieldOnce(bool);
+
+    impl Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+            if self.0 {
+                Poll::Ready(())
+            } else {
+                self.0 = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}